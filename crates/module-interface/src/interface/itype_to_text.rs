@@ -14,21 +14,25 @@
  * limitations under the License.
  */
 
-use crate::it_interface::IRecordTypes;
-
+use wasmer_it::IRecordType;
 use wasmer_it::IType;
 
+use std::collections::HashMap;
+
 /// Converts the supplied IType to a Aqua0compatible text representation.
 ///
 /// SAFETY:
 ///     It's assumed that arguments are well-formed and all records have a corresponded type in
-///     record_types.
-pub fn itype_text_view(arg_ty: &IType, record_types: &IRecordTypes) -> String {
+///     record_types, which could be shared both through Rc and Arc.
+pub fn itype_text_view<R: AsRef<IRecordType>>(
+    arg_ty: &IType,
+    record_types: &HashMap<u64, R>,
+) -> String {
     match arg_ty {
         IType::Record(record_type_id) => {
             // assumed that this functions called with well-formed args
             let record = record_types.get(record_type_id).unwrap();
-            record.name.clone()
+            record.as_ref().name.clone()
         }
         IType::Array(array_ty) => format!("[]{}", itype_text_view(array_ty, record_types)),
         IType::Boolean => "bool".to_string(),
@@ -16,6 +16,7 @@
 
 use std::path::PathBuf;
 use fluence_faas::FaaSConfig;
+use fluence_faas::VfsConfig;
 
 /// Describes behaviour of the Fluence AppService.
 #[derive(Default)]
@@ -23,4 +24,18 @@ pub struct AppServiceConfig {
     /// Used for preparing filesystem on the service initialization stage.
     pub service_base_dir: PathBuf,
     pub faas_config: FaaSConfig,
+
+    /// If Some, local and tmp directories of the service are created in a virtual filesystem
+    /// of every module instead of service_base_dir, modules that have their own virtual
+    /// filesystem keep it. A file of this config is treated as a directory where
+    /// the filesystem of every module is saved to a file named after the module.
+    pub vfs: Option<VfsConfig>,
+
+    /// Maximum size in bytes of all files in local and tmp directories of the service,
+    /// writes beyond it fail with ENOSPC. Isn't applied to virtual directories.
+    pub service_disk_quota: Option<u64>,
+
+    /// Maximum number of bytes that every module of the service could write to local and tmp
+    /// directories of the service. Isn't applied to virtual directories.
+    pub module_disk_quota: Option<u64>,
 }
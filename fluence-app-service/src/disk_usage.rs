@@ -0,0 +1,30 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde_derive::Serialize;
+
+use std::collections::HashMap;
+
+/// Disk space taken by files in local and tmp directories of a service.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct DiskUsage {
+    /// Bytes taken by all files of the directories, it's 0 if the directories are virtual.
+    pub service: u64,
+
+    /// Bytes written to the directories by every module, modules are tracked
+    /// only if there is a per-module quota.
+    pub modules: HashMap<String, u64>,
+}
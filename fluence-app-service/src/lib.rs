@@ -25,6 +25,7 @@
 )]
 
 mod config;
+mod disk_usage;
 mod errors;
 mod service;
 mod service_interface;
@@ -39,6 +40,7 @@ pub use service_interface::RecordType;
 pub use service_interface::ServiceInterface;
 
 pub use config::AppServiceConfig;
+pub use disk_usage::DiskUsage;
 pub use raw_toml_config::TomlAppServiceConfig;
 
 pub use fluence_faas::FaaSConfig;
@@ -48,6 +50,9 @@ pub use fluence_faas::TomlFaaSConfig;
 pub use fluence_faas::TomlFaaSModuleConfig;
 pub use fluence_faas::TomlFaaSNamedModuleConfig;
 pub use fluence_faas::TomlWASIConfig;
+pub use fluence_faas::TomlWASIPath;
+pub use fluence_faas::TomlVfsConfig;
+pub use fluence_faas::TomlWASICapabilities;
 pub use fluence_faas::ModuleDescriptor;
 
 pub use fluence_faas::FaaSError;
@@ -57,7 +62,13 @@ pub use fluence_faas::IRecordType;
 pub use fluence_faas::IFunctionArg;
 pub use fluence_faas::IType;
 pub use fluence_faas::HostImportDescriptor;
+pub use fluence_faas::HostImportName;
 pub use fluence_faas::HostImportError;
+pub use fluence_faas::fail_host_import;
+pub use fluence_faas::HostImportValue;
+pub use fluence_faas::HostImportRecord;
+pub use fluence_faas::HostImportOutput;
+pub use fluence_faas::IntoHostImport;
 pub use fluence_faas::to_interface_value;
 pub use fluence_faas::from_interface_values;
 pub use fluence_faas::ne_vec;
@@ -67,3 +78,17 @@ pub use fluence_faas::min_it_version;
 
 pub use fluence_faas::CallParameters;
 pub use fluence_faas::SecurityTetraplet;
+
+pub use fluence_faas::CallDeadline;
+pub use fluence_faas::DeterministicEnv;
+pub use fluence_faas::CancellationHandle;
+pub use fluence_faas::ModuleSnapshot;
+pub use fluence_faas::FaaSStats;
+pub use fluence_faas::CallContext;
+pub use fluence_faas::CallInterceptor;
+pub use fluence_faas::ModuleStats;
+pub use fluence_faas::ModuleWasiState;
+pub use fluence_faas::FunctionStats;
+pub use fluence_faas::VfsConfig;
+pub use fluence_faas::DiskQuota;
+pub use fluence_faas::WasiCapabilities;
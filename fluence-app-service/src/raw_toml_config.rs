@@ -19,6 +19,7 @@ use crate::AppServiceError;
 use crate::config::AppServiceConfig;
 
 use fluence_faas::TomlFaaSConfig;
+use fluence_faas::TomlVfsConfig;
 use serde_derive::Serialize;
 use serde_derive::Deserialize;
 
@@ -29,6 +30,12 @@ use std::path::PathBuf;
 pub struct TomlAppServiceConfig {
     pub service_base_dir: Option<String>,
 
+    pub vfs: Option<TomlVfsConfig>,
+
+    pub service_disk_quota: Option<u64>,
+
+    pub module_disk_quota: Option<u64>,
+
     #[serde(flatten)]
     pub toml_faas_config: TomlFaaSConfig,
 }
@@ -58,6 +65,9 @@ impl TryInto<AppServiceConfig> for TomlAppServiceConfig {
         Ok(AppServiceConfig {
             service_base_dir,
             faas_config,
+            vfs: self.vfs.map(Into::into),
+            service_disk_quota: self.service_disk_quota,
+            module_disk_quota: self.module_disk_quota,
         })
     }
 }
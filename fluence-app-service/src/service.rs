@@ -16,6 +16,8 @@
 
 use crate::Result;
 use crate::config::AppServiceConfig;
+use crate::DiskQuota;
+use crate::DiskUsage;
 use crate::service_interface::ServiceInterface;
 use super::AppServiceError;
 
@@ -27,6 +29,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::io::ErrorKind;
+use std::sync::Arc;
 
 const SERVICE_ID_ENV_NAME: &str = "service_id";
 const SERVICE_LOCAL_DIR_NAME: &str = "local";
@@ -35,6 +38,14 @@ const SERVICE_TMP_DIR_NAME: &str = "tmp";
 pub struct AppService {
     faas: FluenceFaaS,
     facade_module_name: String,
+    disk_quotas: ServiceDiskQuotas,
+}
+
+/// Quotas of local and tmp directories of a service, the service quota is used only
+/// to count the usage if it isn't limited in the config.
+struct ServiceDiskQuotas {
+    service: Arc<DiskQuota>,
+    modules: HashMap<String, Arc<DiskQuota>>,
 }
 
 impl AppService {
@@ -59,13 +70,14 @@ impl AppService {
             .clone();
 
         let service_id = service_id.into();
-        Self::set_env_and_dirs(&mut config, service_id, envs)?;
+        let disk_quotas = Self::set_env_and_dirs(&mut config, service_id, envs)?;
 
         let faas = FluenceFaaS::with_raw_config(config.faas_config)?;
 
         Ok(Self {
             faas,
             facade_module_name,
+            disk_quotas,
         })
     }
 
@@ -87,6 +99,31 @@ impl AppService {
             .map_err(Into::into)
     }
 
+    /// Call a specified function of the facade module, the call is interrupted
+    /// when the provided deadline is reached.
+    pub fn call_with_deadline<S: AsRef<str>>(
+        &mut self,
+        func_name: S,
+        arguments: JValue,
+        call_parameters: crate::CallParameters,
+        deadline: impl Into<crate::CallDeadline>,
+    ) -> Result<JValue> {
+        self.faas
+            .call_with_json_and_deadline(
+                &self.facade_module_name,
+                func_name,
+                arguments,
+                call_parameters,
+                deadline,
+            )
+            .map_err(Into::into)
+    }
+
+    /// Register an interceptor that is invoked around every call of modules of this service.
+    pub fn add_interceptor(&mut self, interceptor: impl fluence_faas::CallInterceptor + 'static) {
+        self.faas.add_interceptor(interceptor)
+    }
+
     /// Return interface (function signatures and record types) of this service.
     pub fn get_interface(&self) -> ServiceInterface {
         use crate::service_interface::into_service_interface;
@@ -102,16 +139,56 @@ impl AppService {
         into_service_interface(faas_facade_interface)
     }
 
+    /// Return disk space taken by files in local and tmp directories of this service.
+    pub fn disk_usage(&self) -> DiskUsage {
+        let modules = self
+            .disk_quotas
+            .modules
+            .iter()
+            .map(|(name, quota)| (name.clone(), quota.usage()))
+            .collect();
+
+        DiskUsage {
+            service: self.disk_quotas.service.usage(),
+            modules,
+        }
+    }
+
     /// Prepare service before starting by:
     ///  1. creating a directory structure in the following form:
     ///     - service_base_dir/service_id/SERVICE_LOCAL_DIR_NAME
     ///     - service_base_dir/service_id/SERVICE_TMP_DIR_NAME
+    ///     or the same directories in virtual filesystems of modules if config.vfs is set
     ///  2. adding service_id to environment variables
+    ///  3. setting disk quotas of the host directories
     fn set_env_and_dirs(
         config: &mut AppServiceConfig,
         service_id: String,
         mut envs: HashMap<Vec<u8>, Vec<u8>>,
-    ) -> Result<()> {
+    ) -> Result<ServiceDiskQuotas> {
+        let disk_quotas = if let Some(vfs) = &config.vfs {
+            Self::set_virtual_dirs(&mut config.faas_config, vfs);
+            ServiceDiskQuotas {
+                service: Arc::new(DiskQuota::for_dirs(u64::MAX, vec![])),
+                modules: HashMap::new(),
+            }
+        } else {
+            Self::set_host_dirs(config, &service_id)?
+        };
+
+        envs.insert(
+            SERVICE_ID_ENV_NAME.as_bytes().to_vec(),
+            service_id.into_bytes(),
+        );
+
+        for module in &mut config.faas_config.modules_config {
+            module.config.extend_wasi_envs(envs.clone());
+        }
+
+        Ok(disk_quotas)
+    }
+
+    fn set_host_dirs(config: &mut AppServiceConfig, service_id: &str) -> Result<ServiceDiskQuotas> {
         use maplit::hashmap;
 
         let create = |dir: &PathBuf| match std::fs::create_dir(dir) {
@@ -124,7 +201,7 @@ impl AppService {
         };
 
         let base_dir = &config.service_base_dir;
-        let service_dir = base_dir.join(&service_id);
+        let service_dir = base_dir.join(service_id);
         create(&service_dir)?;
 
         let local_dir = service_dir.join(SERVICE_LOCAL_DIR_NAME);
@@ -133,6 +210,11 @@ impl AppService {
         let tmp_dir = service_dir.join(SERVICE_TMP_DIR_NAME);
         create(&tmp_dir)?;
 
+        let dirs = vec![local_dir.clone(), tmp_dir.clone()];
+        let service_quota = config.service_disk_quota.unwrap_or(u64::MAX);
+        let service_quota = Arc::new(DiskQuota::for_dirs(service_quota, dirs.clone()));
+        let mut module_quotas = HashMap::new();
+
         let local_dir = local_dir.to_string_lossy().to_string();
         let tmp_dir = tmp_dir.to_string_lossy().to_string();
 
@@ -145,19 +227,58 @@ impl AppService {
             String::from(SERVICE_TMP_DIR_NAME) => PathBuf::from(tmp_dir),
         };
 
-        envs.insert(
-            SERVICE_ID_ENV_NAME.as_bytes().to_vec(),
-            service_id.into_bytes(),
-        );
-
         for module in &mut config.faas_config.modules_config {
-            module.config.extend_wasi_envs(envs.clone());
             module
                 .config
                 .extend_wasi_files(preopened_files.clone(), mapped_dirs.clone());
+
+            // extend_wasi_files always leaves some WASI config
+            let disk_quotas = match &mut module.config.wasi {
+                Some(wasi) => &mut wasi.disk_quotas,
+                None => continue,
+            };
+            if config.service_disk_quota.is_some() {
+                disk_quotas.push(service_quota.clone());
+            }
+            if let Some(module_quota) = config.module_disk_quota {
+                let module_quota = Arc::new(DiskQuota::for_writes(module_quota, dirs.clone()));
+                disk_quotas.push(module_quota.clone());
+                module_quotas.insert(module.import_name.clone(), module_quota);
+            }
         }
 
-        Ok(())
+        Ok(ServiceDiskQuotas {
+            service: service_quota,
+            modules: module_quotas,
+        })
+    }
+
+    // host paths of mapped dirs aren't used by a virtual filesystem,
+    // so they are set to the paths seen by modules
+    fn set_virtual_dirs(faas_config: &mut fluence_faas::FaaSConfig, vfs: &crate::VfsConfig) {
+        use maplit::hashmap;
+
+        let mapped_dirs = hashmap! {
+            String::from(SERVICE_LOCAL_DIR_NAME) => PathBuf::from("/").join(SERVICE_LOCAL_DIR_NAME),
+            String::from(SERVICE_TMP_DIR_NAME) => PathBuf::from("/").join(SERVICE_TMP_DIR_NAME),
+        };
+
+        for module in &mut faas_config.modules_config {
+            module
+                .config
+                .extend_wasi_files(HashSet::new(), mapped_dirs.clone());
+
+            let module_vfs = crate::VfsConfig {
+                file: vfs
+                    .file
+                    .as_ref()
+                    .map(|dir| dir.join(format!("{}.vfs", module.import_name))),
+                ..vfs.clone()
+            };
+            if let Some(wasi) = &mut module.config.wasi {
+                wasi.vfs.get_or_insert(module_vfs);
+            }
+        }
     }
 }
 
@@ -176,13 +297,14 @@ impl AppService {
     {
         let mut config: AppServiceConfig = config.try_into()?;
         let service_id = service_id.into();
-        Self::set_env_and_dirs(&mut config, service_id, envs)?;
+        let disk_quotas = Self::set_env_and_dirs(&mut config, service_id, envs)?;
 
         let faas = FluenceFaaS::with_raw_config(config.faas_config)?;
 
         Ok(Self {
             faas,
             facade_module_name: String::new(),
+            disk_quotas,
         })
     }
 
@@ -209,19 +331,73 @@ impl AppService {
             .map_err(Into::into)
     }
 
+    /// Replace code of a loaded module keeping its name and optionally migrating its state,
+    /// the old module stays loaded if the upgrade fails.
+    pub fn upgrade_module<S, C>(
+        &mut self,
+        name: S,
+        new_wasm_bytes: &[u8],
+        config: Option<C>,
+    ) -> Result<()>
+    where
+        S: Into<String>,
+        C: TryInto<crate::FaaSModuleConfig>,
+        fluence_faas::FaaSError: From<C::Error>,
+    {
+        self.faas
+            .upgrade_module(name, new_wasm_bytes, config)
+            .map_err(Into::into)
+    }
+
     pub fn unload_module<S: AsRef<str>>(&mut self, module_name: S) -> Result<()> {
         self.faas.unload_module(module_name).map_err(Into::into)
     }
 
+    /// Capture linear memory, mutable globals and WASI state of a loaded module.
+    pub fn snapshot_module<S: AsRef<str>>(
+        &mut self,
+        module_name: S,
+    ) -> Result<crate::ModuleSnapshot> {
+        self.faas.snapshot_module(module_name).map_err(Into::into)
+    }
+
+    /// Restore state of a loaded module from a previously taken snapshot.
+    pub fn restore_module<S: AsRef<str>>(
+        &mut self,
+        module_name: S,
+        snapshot: &crate::ModuleSnapshot,
+    ) -> Result<()> {
+        self.faas
+            .restore_module(module_name, snapshot)
+            .map_err(Into::into)
+    }
+
+    /// Unload a module along with all modules that depend on it, returns names of unloaded modules.
+    pub fn unload_module_cascade<S: AsRef<str>>(&mut self, module_name: S) -> Result<Vec<String>> {
+        self.faas
+            .unload_module_cascade(module_name)
+            .map_err(Into::into)
+    }
+
+    /// Return names of modules that import functions of the module with given name.
+    pub fn module_dependents<S: AsRef<str>>(&self, module_name: S) -> Vec<String> {
+        self.faas.module_dependents(module_name)
+    }
+
     /// Return raw interface of the underlying [[FluenceFaaS]] instance
     pub fn get_full_interface(&self) -> fluence_faas::FaaSInterface<'_> {
         self.faas.get_interface()
     }
 
+    /// Return resources consumed by modules of this service.
+    pub fn stats(&self) -> fluence_faas::FaaSStats {
+        self.faas.stats()
+    }
+
     pub fn get_wasi_state<S: AsRef<str>>(
         &mut self,
         module_name: S,
-    ) -> Result<&wasmer_wasi::state::WasiState> {
+    ) -> Result<crate::ModuleWasiState<'_>> {
         self.faas.module_wasi_state(module_name).map_err(Into::into)
     }
 }
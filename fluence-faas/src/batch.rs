@@ -0,0 +1,82 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::IValue;
+
+use marine_rs_sdk::CallParameters;
+use serde_json::Value as JValue;
+
+/// Describes one call of a batch executed by FluenceFaaS::call_batch.
+#[derive(Clone, Debug)]
+pub struct CallRequest {
+    pub module_name: String,
+    pub function_name: String,
+    pub arguments: CallArguments,
+    pub call_parameters: CallParameters,
+}
+
+/// Arguments of a call in a batch, the call returns outputs of the same kind.
+#[derive(Clone, Debug)]
+pub enum CallArguments {
+    Json(JValue),
+    IValues(Vec<IValue>),
+}
+
+/// Outputs of a successfully executed call in a batch.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CallOutputs {
+    Json(JValue),
+    IValues(Vec<IValue>),
+}
+
+/// Defines what happens with modules touched by a batch if one of its calls fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxMode {
+    /// Memory, globals and WASI state of every module touched by the batch
+    /// (including modules called by them) are rolled back to their state before the batch.
+    Atomic,
+
+    /// The batch is stopped at the failed call, changes made by previous calls are kept.
+    NonAtomic,
+}
+
+impl CallRequest {
+    pub fn new(
+        module_name: impl Into<String>,
+        function_name: impl Into<String>,
+        arguments: CallArguments,
+        call_parameters: CallParameters,
+    ) -> Self {
+        Self {
+            module_name: module_name.into(),
+            function_name: function_name.into(),
+            arguments,
+            call_parameters,
+        }
+    }
+}
+
+impl From<JValue> for CallArguments {
+    fn from(arguments: JValue) -> Self {
+        CallArguments::Json(arguments)
+    }
+}
+
+impl From<Vec<IValue>> for CallArguments {
+    fn from(arguments: Vec<IValue>) -> Self {
+        CallArguments::IValues(arguments)
+    }
+}
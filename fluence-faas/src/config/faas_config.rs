@@ -15,10 +15,17 @@
  */
 
 use marine::HostImportDescriptor;
+use marine::HostImportName;
+use marine::DiskQuota;
+use marine::VfsConfig;
+use marine::WasiCapabilities;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::path::{PathBuf};
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 /// Info to load a module from filesystem into runtime.
 #[derive(Default)]
@@ -39,6 +46,9 @@ pub struct FaaSConfig {
 
     /// Settings for a module that name's not been found in modules_config.
     pub default_modules_config: Option<FaaSModuleConfig>,
+
+    /// Path to a dir where compiled modules are cached, modules are compiled on every load if not set.
+    pub module_cache_dir: Option<PathBuf>,
 }
 
 /// Various settings that could be used to guide Marine how to load a module in a proper way.
@@ -50,14 +60,29 @@ pub struct FaaSModuleConfig {
     /// Defines whether FaaS should provide a special host log_utf8_string function for this module.
     pub logger_enabled: bool,
 
-    /// Export from host functions that will be accessible on the Wasm side by provided name.
-    pub host_imports: HashMap<String, HostImportDescriptor>,
+    /// Export from host functions that will be accessible on the Wasm side by provided
+    /// namespace and name.
+    pub host_imports: HashMap<HostImportName, HostImportDescriptor>,
 
     /// A WASI config.
     pub wasi: Option<FaaSWASIConfig>,
 
     /// Mask used to filter logs, for details see `log_utf8_string`
     pub logging_mask: i32,
+
+    /// Defines whether calls of this module could be interrupted by a deadline.
+    pub interruptible: bool,
+
+    /// Defines whether memory and globals of this module are reset to their initial state after every call.
+    pub reset_after_call: bool,
+
+    /// Number of pre-instantiated copies of this module, 0 is treated as 1.
+    /// If there are several copies, they are reset after every call.
+    pub pool_size: usize,
+
+    /// Defines whether this module is executed deterministically: NaNs are canonicalized,
+    /// and WASI time and random values are derived from call parameters.
+    pub deterministic: bool,
 }
 
 impl FaaSModuleConfig {
@@ -69,6 +94,10 @@ impl FaaSModuleConfig {
                     envs: new_envs,
                     preopened_files: HashSet::new(),
                     mapped_dirs: HashMap::new(),
+                    vfs: None,
+                    disk_quotas: Vec::new(),
+                    read_only_dirs: HashSet::new(),
+                    capabilities: WasiCapabilities::default(),
                 })
             }
         };
@@ -93,6 +122,10 @@ impl FaaSModuleConfig {
                     envs: HashMap::new(),
                     preopened_files: new_preopened_files,
                     mapped_dirs: new_mapped_dirs,
+                    vfs: None,
+                    disk_quotas: Vec::new(),
+                    read_only_dirs: HashSet::new(),
+                    capabilities: WasiCapabilities::default(),
                 })
             }
         };
@@ -110,11 +143,28 @@ pub struct FaaSWASIConfig {
 
     /// Mapping from a usually short to full file name.
     pub mapped_dirs: HashMap<String, PathBuf>,
+
+    /// If Some, the module sees a virtual filesystem instead of host directories,
+    /// preopened files and aliases of mapped dirs become its directories.
+    pub vfs: Option<VfsConfig>,
+
+    /// Quotas of disk space checked by writes of this module to files in host directories.
+    pub disk_quotas: Vec<Arc<DiskQuota>>,
+
+    /// Host paths of preopened files and mapped dirs that this module could only read.
+    pub read_only_dirs: HashSet<PathBuf>,
+
+    /// Groups of WASI functions available to this module, disabled ones return ENOSYS,
+    /// disabled proc_exit traps the module.
+    pub capabilities: WasiCapabilities,
 }
 
 use super::TomlFaaSConfig;
 use super::TomlFaaSModuleConfig;
 use super::TomlWASIConfig;
+use super::TomlVfsConfig;
+use super::TomlWASIPath;
+use super::TomlWASICapabilities;
 use super::TomlFaaSNamedModuleConfig;
 use crate::FaaSError;
 
@@ -125,6 +175,7 @@ impl TryFrom<TomlFaaSConfig> for FaaSConfig {
 
     fn try_from(toml_config: TomlFaaSConfig) -> Result<Self, Self::Error> {
         let modules_dir = toml_config.modules_dir.map(PathBuf::from);
+        let module_cache_dir = toml_config.module_cache_dir.map(PathBuf::from);
 
         let default_modules_config = toml_config.default.map(|m| m.try_into()).transpose()?;
 
@@ -138,6 +189,7 @@ impl TryFrom<TomlFaaSConfig> for FaaSConfig {
             modules_dir,
             modules_config,
             default_modules_config,
+            module_cache_dir,
         })
     }
 }
@@ -159,20 +211,28 @@ impl TryFrom<TomlFaaSModuleConfig> for FaaSModuleConfig {
 
     fn try_from(toml_config: TomlFaaSModuleConfig) -> Result<Self, Self::Error> {
         let mounted_binaries = toml_config.mounted_binaries.unwrap_or_default();
-        let mounted_binaries = mounted_binaries
-            .into_iter()
-            .map(|(import_func_name, host_cmd)| {
-                let host_cmd = host_cmd.try_into::<String>()?;
-                Ok((import_func_name, host_cmd))
-            })
-            .collect::<Result<Vec<_>, Self::Error>>()?;
 
         let mut host_cli_imports = HashMap::new();
-        for (import_name, host_cmd) in mounted_binaries {
-            host_cli_imports.insert(
-                import_name,
-                crate::host_imports::create_mounted_binary_import(host_cmd),
-            );
+        for (name, value) in mounted_binaries {
+            match value {
+                // a table groups mounted binaries under a namespace named by its key
+                toml::Value::Table(namespace_binaries) => {
+                    for (import_name, host_cmd) in namespace_binaries {
+                        let host_cmd = host_cmd.try_into::<String>()?;
+                        host_cli_imports.insert(
+                            HostImportName::new(name.clone(), import_name),
+                            crate::host_imports::create_mounted_binary_import(host_cmd),
+                        );
+                    }
+                }
+                host_cmd => {
+                    let host_cmd = host_cmd.try_into::<String>()?;
+                    host_cli_imports.insert(
+                        HostImportName::from(name),
+                        crate::host_imports::create_mounted_binary_import(host_cmd),
+                    );
+                }
+            }
         }
 
         let wasi = toml_config.wasi.map(|w| w.try_into()).transpose()?;
@@ -183,6 +243,10 @@ impl TryFrom<TomlFaaSModuleConfig> for FaaSModuleConfig {
             host_imports: host_cli_imports,
             wasi,
             logging_mask: toml_config.logging_mask.unwrap_or(i32::max_value()),
+            interruptible: toml_config.interruptible.unwrap_or(false),
+            reset_after_call: toml_config.reset_after_call.unwrap_or(false),
+            pool_size: toml_config.pool_size.unwrap_or(1),
+            deterministic: toml_config.deterministic.unwrap_or(false),
         })
     }
 }
@@ -199,12 +263,24 @@ impl TryFrom<TomlWASIConfig> for FaaSWASIConfig {
             Ok((elem.0.into_bytes(), to.into_bytes()))
         };
 
-        let to_path = |elem: (String, toml::Value)| -> Result<(String, PathBuf), Self::Error> {
-            let to = elem
-                .1
-                .try_into::<String>()
-                .map_err(FaaSError::ParseConfigError)?;
-            Ok((elem.0, PathBuf::from(to)))
+        // paths of a virtual filesystem don't exist on the host, so they aren't validated
+        let is_virtual = toml_config.vfs.is_some();
+        let mut read_only_dirs = HashSet::new();
+        let mut to_host_path = |path: TomlWASIPath| -> Result<PathBuf, Self::Error> {
+            let (path, read_only) = match path {
+                TomlWASIPath::Path(path) => (path, false),
+                TomlWASIPath::Detailed { path, read_only } => (path, read_only.unwrap_or(false)),
+            };
+            let path = if is_virtual {
+                PathBuf::from(path)
+            } else {
+                validate_host_path(Path::new(&path))?
+            };
+
+            if read_only {
+                read_only_dirs.insert(path.clone());
+            }
+            Ok(path)
         };
 
         let envs = toml_config.envs.unwrap_or_default();
@@ -216,19 +292,72 @@ impl TryFrom<TomlWASIConfig> for FaaSWASIConfig {
         let preopened_files = toml_config.preopened_files.unwrap_or_default();
         let preopened_files = preopened_files
             .into_iter()
-            .map(PathBuf::from)
-            .collect::<HashSet<_>>();
+            .map(&mut to_host_path)
+            .collect::<Result<HashSet<_>, _>>()?;
 
         let mapped_dirs = toml_config.mapped_dirs.unwrap_or_default();
         let mapped_dirs = mapped_dirs
             .into_iter()
-            .map(to_path)
-            .collect::<Result<HashMap<_, _>, _>>()?;
+            .map(|(alias, path)| {
+                let path = path.try_into::<TomlWASIPath>()?;
+                Ok((alias, to_host_path(path)?))
+            })
+            .collect::<Result<HashMap<_, _>, Self::Error>>()?;
 
         Ok(FaaSWASIConfig {
             envs,
             preopened_files,
             mapped_dirs,
+            vfs: toml_config.vfs.map(Into::into),
+            disk_quotas: Vec::new(),
+            read_only_dirs,
+            capabilities: toml_config.capabilities.map(Into::into).unwrap_or_default(),
         })
     }
 }
+
+/// Checks that a host path of a preopened file or a mapped dir exists and doesn't contain `..`,
+/// returns the canonicalized path. Files inside the path aren't checked here, paths that lead
+/// outside of preopened dirs through `..` or symlinks are rejected when a module resolves them.
+fn validate_host_path(path: &Path) -> Result<PathBuf, FaaSError> {
+    if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+    {
+        return Err(FaaSError::InvalidConfig(format!(
+            "host path {:?} mustn't contain '..'",
+            path
+        )));
+    }
+
+    std::fs::canonicalize(path).map_err(|e| {
+        FaaSError::InvalidConfig(format!("host path {:?} can't be resolved: {}", path, e))
+    })
+}
+
+impl From<TomlWASICapabilities> for WasiCapabilities {
+    fn from(toml_config: TomlWASICapabilities) -> Self {
+        let default = WasiCapabilities::default();
+
+        WasiCapabilities {
+            filesystem: toml_config.filesystem.unwrap_or(default.filesystem),
+            clock: toml_config.clock.unwrap_or(default.clock),
+            random: toml_config.random.unwrap_or(default.random),
+            environ: toml_config.environ.unwrap_or(default.environ),
+            proc_exit: toml_config.proc_exit.unwrap_or(default.proc_exit),
+            poll: toml_config.poll.unwrap_or(default.poll),
+        }
+    }
+}
+
+impl From<TomlVfsConfig> for VfsConfig {
+    fn from(toml_config: TomlVfsConfig) -> Self {
+        let default = VfsConfig::default();
+
+        VfsConfig {
+            file: toml_config.file.map(PathBuf::from),
+            max_size: toml_config.max_size.unwrap_or(default.max_size),
+            max_inodes: toml_config.max_inodes.unwrap_or(default.max_inodes),
+        }
+    }
+}
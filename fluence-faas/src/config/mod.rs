@@ -25,6 +25,9 @@ pub use faas_config::ModuleDescriptor;
 
 pub use raw_faas_config::TomlFaaSNamedModuleConfig;
 pub use raw_faas_config::TomlWASIConfig;
+pub use raw_faas_config::TomlWASIPath;
+pub use raw_faas_config::TomlVfsConfig;
+pub use raw_faas_config::TomlWASICapabilities;
 pub use raw_faas_config::TomlFaaSConfig;
 pub use raw_faas_config::TomlFaaSModuleConfig;
 
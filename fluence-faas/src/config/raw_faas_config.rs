@@ -25,20 +25,41 @@ use std::path::Path;
 An example of the config:
 
 modules_dir = "wasm/artifacts/wasm_modules"
+module_cache_dir = "/var/cache/marine"
 
 [[module]]
     name = "ipfs_node.wasm"
     mem_pages_count = 100
     logger_enabled = true
+    interruptible = true
+    reset_after_call = false
+    pool_size = 1
+    deterministic = false
 
     [module.mounted_binaries]
     mysql = "/usr/bin/mysql"
     ipfs = "/usr/local/bin/ipfs"
 
+    [module.mounted_binaries.kv]
+    redis = "/usr/bin/redis-cli"
+
     [module.wasi]
     envs = { "IPFS_ADDR" = "/dns4/relay02.fluence.dev/tcp/15001" }
-    preopened_files = ["/Users/user/tmp"]
-    mapped_dirs = {"tmp" = "/Users/user/tmp"}
+    preopened_files = ["/Users/user/tmp", { path = "/Users/user/assets", read_only = true }]
+    mapped_dirs = {"tmp" = "/Users/user/tmp", "assets" = { path = "/Users/user/assets", read_only = true }}
+
+    [module.wasi.vfs]
+    file = "/var/lib/marine/ipfs_node.vfs"
+    max_size = 104857600
+    max_inodes = 10000
+
+    [module.wasi.capabilities]
+    filesystem = true
+    clock = true
+    random = false
+    environ = true
+    proc_exit = false
+    poll = false
 
 [default]
     mem_pages_count = 100
@@ -57,6 +78,7 @@ modules_dir = "wasm/artifacts/wasm_modules"
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct TomlFaaSConfig {
     pub modules_dir: Option<String>,
+    pub module_cache_dir: Option<String>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub module: Vec<TomlFaaSNamedModuleConfig>,
     pub default: Option<TomlFaaSModuleConfig>,
@@ -86,18 +108,53 @@ pub struct TomlFaaSModuleConfig {
     pub wasi: Option<TomlWASIConfig>,
     pub mounted_binaries: Option<toml::value::Table>,
     pub logging_mask: Option<i32>,
+    pub interruptible: Option<bool>,
+    pub reset_after_call: Option<bool>,
+    pub pool_size: Option<usize>,
+    pub deterministic: Option<bool>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct TomlWASIConfig {
-    pub preopened_files: Option<Vec<String>>,
+    pub preopened_files: Option<Vec<TomlWASIPath>>,
     pub envs: Option<toml::value::Table>,
     pub mapped_dirs: Option<toml::value::Table>,
+    pub vfs: Option<TomlVfsConfig>,
+    pub capabilities: Option<TomlWASICapabilities>,
+}
+
+/// A preopened file or a host path of a mapped dir, either a plain path
+/// or a table with the path and access options.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum TomlWASIPath {
+    Path(String),
+    Detailed {
+        path: String,
+        read_only: Option<bool>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct TomlVfsConfig {
+    pub file: Option<String>,
+    pub max_size: Option<u64>,
+    pub max_inodes: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct TomlWASICapabilities {
+    pub filesystem: Option<bool>,
+    pub clock: Option<bool>,
+    pub random: Option<bool>,
+    pub environ: Option<bool>,
+    pub proc_exit: Option<bool>,
+    pub poll: Option<bool>,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{TomlFaaSNamedModuleConfig, TomlFaaSModuleConfig, TomlWASIConfig};
+    use super::{TomlFaaSNamedModuleConfig, TomlFaaSModuleConfig, TomlWASIConfig, TomlWASIPath};
 
     #[test]
     fn serialize_named() {
@@ -108,15 +165,140 @@ mod tests {
                 mem_pages_count: Some(100),
                 logger_enabled: Some(false),
                 wasi: Some(TomlWASIConfig {
-                    preopened_files: Some(vec!["a".to_string()]),
+                    preopened_files: Some(vec![TomlWASIPath::Path("a".to_string())]),
                     envs: None,
                     mapped_dirs: None,
+                    vfs: None,
+                    capabilities: None,
                 }),
                 mounted_binaries: None,
                 logging_mask: None,
+                interruptible: None,
+                reset_after_call: None,
+                pool_size: None,
+                deterministic: None,
             },
         };
 
         assert!(toml::to_string(&config).is_ok())
     }
+
+    #[test]
+    fn mounted_binaries_namespaces() {
+        use crate::FaaSModuleConfig;
+        use crate::HostImportName;
+        use std::convert::TryInto;
+
+        let config: TomlFaaSModuleConfig = toml::from_str(
+            r#"
+            [mounted_binaries]
+            curl = "/usr/bin/curl"
+
+            [mounted_binaries.ipfs]
+            curl = "/usr/local/bin/ipfs"
+            "#,
+        )
+        .unwrap();
+        let config: FaaSModuleConfig = config.try_into().unwrap();
+
+        let mut import_names = config.host_imports.keys().cloned().collect::<Vec<_>>();
+        import_names.sort();
+        assert_eq!(
+            import_names,
+            vec![
+                HostImportName::new("host", "curl"),
+                HostImportName::new("ipfs", "curl")
+            ]
+        );
+    }
+
+    fn wasi_config(config: &str) -> crate::Result<crate::FaaSWASIConfig> {
+        use std::convert::TryInto;
+
+        let config: TomlWASIConfig = toml::from_str(config).unwrap();
+        config.try_into()
+    }
+
+    #[test]
+    fn read_only_wasi_paths() {
+        let dir = std::env::temp_dir().join(format!("faas_read_only_{}", std::process::id()));
+        let assets_dir = dir.join("assets");
+        let tmp_dir = dir.join("tmp");
+        std::fs::create_dir_all(&assets_dir).unwrap();
+        std::fs::create_dir_all(&tmp_dir).unwrap();
+
+        let config = wasi_config(&format!(
+            r#"
+            preopened_files = [{{ path = {:?}, read_only = true }}, {:?}]
+            mapped_dirs = {{ "assets" = {{ path = {:?}, read_only = true }}, "tmp" = {:?} }}
+            "#,
+            assets_dir, tmp_dir, assets_dir, tmp_dir
+        ))
+        .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let assets_dir = assets_dir.canonicalize().unwrap_or(assets_dir);
+        assert_eq!(config.mapped_dirs["assets"], assets_dir);
+        assert_eq!(config.preopened_files.len(), 2);
+        assert_eq!(
+            config.read_only_dirs.into_iter().collect::<Vec<_>>(),
+            vec![assets_dir]
+        );
+    }
+
+    #[test]
+    fn invalid_wasi_paths() {
+        let dir = std::env::temp_dir().join(format!("faas_invalid_paths_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sites")).unwrap();
+        std::os::unix::fs::symlink("../..", dir.join("sites").join("escape")).unwrap();
+        std::os::unix::fs::symlink("sites", dir.join("link")).unwrap();
+
+        let mapped_dir = |path: std::path::PathBuf| {
+            wasi_config(&format!(r#"mapped_dirs = {{ "sites" = {:?} }}"#, path))
+        };
+        let relative_path = mapped_dir(dir.join("sites").join("..").join("sites"));
+        let missing_path = mapped_dir(dir.join("missing"));
+        // symlinks inside a dir are checked only when a module resolves them
+        let escaping_symlink = mapped_dir(dir.join("sites"));
+        let symlink = mapped_dir(dir.join("link"));
+        let sites_dir = dir.join("sites").canonicalize().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(relative_path.is_err());
+        assert!(missing_path.is_err());
+        assert!(escaping_symlink.is_ok());
+        assert_eq!(symlink.unwrap().mapped_dirs["sites"], sites_dir);
+
+        // paths of a virtual filesystem aren't checked
+        let virtual_config = wasi_config(
+            r#"
+            mapped_dirs = { "sites" = "./missing" }
+            [vfs]
+            "#,
+        );
+        assert!(virtual_config.is_ok());
+    }
+
+    #[test]
+    fn wasi_capabilities() {
+        use marine::WasiCapabilities;
+
+        let config = wasi_config(
+            r#"
+            [capabilities]
+            clock = false
+            random = false
+            "#,
+        )
+        .unwrap();
+        let expected = WasiCapabilities {
+            clock: false,
+            random: false,
+            ..WasiCapabilities::all()
+        };
+        assert_eq!(config.capabilities, expected);
+
+        let config = wasi_config("").unwrap();
+        assert_eq!(config.capabilities, WasiCapabilities::all());
+    }
 }
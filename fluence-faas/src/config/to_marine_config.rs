@@ -22,19 +22,17 @@ use crate::host_imports::logger::WASM_LOG_ENV_NAME;
 use crate::host_imports::create_call_parameters_import;
 
 use marine::MModuleConfig;
-use wasmer_core::import::ImportObject;
-use wasmer_core::import::Namespace;
-use wasmer_runtime::func;
+use marine::HostImportName;
 
 use std::collections::HashMap;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 /// Make Marine config from provided FaaS config.
 pub(crate) fn make_marine_config(
     module_name: String,
     faas_module_config: Option<FaaSModuleConfig>,
-    call_parameters: Rc<RefCell<marine_rs_sdk::CallParameters>>,
+    call_parameters: Arc<Mutex<marine_rs_sdk::CallParameters>>,
     logger_filter: &LoggerFilter<'_>,
 ) -> Result<MModuleConfig> {
     let mut marine_module_cfg = MModuleConfig::default();
@@ -48,20 +46,35 @@ pub(crate) fn make_marine_config(
         marine_module_cfg.mem_pages_count = mem_pages_count;
     }
 
+    marine_module_cfg.interruptible = faas_module_config.interruptible;
+    marine_module_cfg.reset_after_call = faas_module_config.reset_after_call;
+    marine_module_cfg.pool_size = faas_module_config.pool_size;
+    marine_module_cfg.deterministic = faas_module_config.deterministic;
+
     if let Some(wasi) = faas_module_config.wasi {
         marine_module_cfg.wasi_envs = wasi.envs;
         marine_module_cfg.wasi_preopened_files = wasi.preopened_files;
         marine_module_cfg.wasi_mapped_dirs = wasi.mapped_dirs;
-
-        // create environment variables for all mapped directories
+        let is_virtual = wasi.vfs.is_some();
+        marine_module_cfg.wasi_vfs = wasi.vfs;
+        marine_module_cfg.wasi_disk_quotas = wasi.disk_quotas;
+        marine_module_cfg.wasi_read_only_dirs = wasi.read_only_dirs;
+        marine_module_cfg.wasi_capabilities = wasi.capabilities;
+
+        // create environment variables for all mapped directories,
+        // in a virtual filesystem they point to the virtual directories
         let mapped_dirs = marine_module_cfg
             .wasi_mapped_dirs
             .iter()
             .map(|(from, to)| {
-                (
-                    from.as_bytes().to_vec(),
-                    to.to_string_lossy().as_bytes().to_vec(),
-                )
+                let to = if !is_virtual {
+                    to.to_string_lossy().into_owned()
+                } else if from.starts_with('/') {
+                    from.clone()
+                } else {
+                    format!("/{}", from)
+                };
+                (from.as_bytes().to_vec(), to.into_bytes())
             })
             .collect::<HashMap<_, _>>();
 
@@ -70,11 +83,10 @@ pub(crate) fn make_marine_config(
 
     marine_module_cfg.host_imports = faas_module_config.host_imports;
     marine_module_cfg.host_imports.insert(
-        String::from("get_call_parameters"),
+        HostImportName::from("get_call_parameters"),
         create_call_parameters_import(call_parameters),
     );
 
-    let mut namespace = Namespace::new();
     if faas_module_config.logger_enabled {
         if let Some(level_filter) = logger_filter.module_level(&module_name) {
             let log_level = level_filter.to_level();
@@ -91,16 +103,13 @@ pub(crate) fn make_marine_config(
         }
 
         let logging_mask = faas_module_config.logging_mask;
-        namespace.insert(
+        marine_module_cfg.raw_imports.insert(
+            marine::HOST_IMPORTS_NAMESPACE,
             "log_utf8_string",
-            func!(log_utf8_string_closure(logging_mask, module_name)),
+            log_utf8_string_closure(logging_mask, module_name),
         );
     }
 
-    let mut raw_host_imports = ImportObject::new();
-    raw_host_imports.register("host", namespace);
-    marine_module_cfg.raw_imports = raw_host_imports;
-
     marine_module_cfg.wasi_version = wasmer_wasi::WasiVersion::Latest;
 
     Ok(marine_module_cfg)
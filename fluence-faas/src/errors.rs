@@ -65,6 +65,30 @@ pub enum FaaSError {
     #[error("parsing config error: {0}")]
     ParseConfigError(#[from] toml::de::Error),
 
+    /// A call of a batch failed, modules touched by the batch are rolled back in the atomic mode.
+    #[error("call {call_id} of the batch failed: {error}")]
+    BatchCallError {
+        call_id: usize,
+        error: Box<FaaSError>,
+    },
+
+    /// A call of an atomic batch failed, and then modules touched by the batch
+    /// can't be rolled back, so their state is undefined.
+    #[error("call {call_id} of the batch failed: {error}, and then rolling back failed: {rollback_error}")]
+    BatchRollbackError {
+        call_id: usize,
+        error: Box<FaaSError>,
+        rollback_error: Box<FaaSError>,
+    },
+
+    /// A call was rejected by an interceptor.
+    #[error("call of {module_name}.{function_name} was rejected: {reason}")]
+    CallRejected {
+        module_name: String,
+        function_name: String,
+        reason: String,
+    },
+
     /// Marine errors.
     #[error("engine error: {0}")]
     EngineError(#[from] MError),
@@ -16,6 +16,13 @@
 
 use crate::config::FaaSConfig;
 use crate::faas_interface::FaaSInterface;
+use crate::faas_stats::FaaSStats;
+use crate::interceptor::CallContext;
+use crate::interceptor::CallInterceptor;
+use crate::CallArguments;
+use crate::CallOutputs;
+use crate::CallRequest;
+use crate::TxMode;
 use crate::FaaSError;
 use crate::Result;
 use crate::IValue;
@@ -26,34 +33,39 @@ use crate::host_imports::logger::LoggerFilter;
 use crate::host_imports::logger::WASM_LOG_ENV_NAME;
 
 use marine::Marine;
+use marine::ModuleSnapshot;
+use marine::ModuleCache;
+use marine::CallDeadline;
+use marine::CallTrace;
+use marine::TraceConfig;
 use marine::IFunctionArg;
 use marine_utils::SharedString;
 use marine::MRecordTypes;
 use marine_rs_sdk::CallParameters;
 
 use serde_json::Value as JValue;
-use std::cell::RefCell;
 use std::convert::TryInto;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
 
 struct ModuleInterface {
-    function_signatures: HashMap<SharedString, (Rc<Vec<IFunctionArg>>, Rc<Vec<IType>>)>,
-    record_types: Rc<MRecordTypes>,
+    function_signatures: HashMap<SharedString, (Arc<Vec<IFunctionArg>>, Arc<Vec<IType>>)>,
+    record_types: Arc<MRecordTypes>,
 }
 
-// TODO: remove and use mutex instead
-unsafe impl Send for FluenceFaaS {}
-
 pub struct FluenceFaaS {
     /// Marine instance.
     marine: Marine,
 
     /// Parameters of call accessible by Wasm modules.
-    call_parameters: Rc<RefCell<CallParameters>>,
+    call_parameters: Arc<Mutex<CallParameters>>,
 
     /// Cached module interfaces by names.
     module_interfaces_cache: HashMap<String, ModuleInterface>,
+
+    /// Interceptors invoked around every call in the order of registration.
+    interceptors: Vec<Box<dyn CallInterceptor>>,
 }
 
 impl FluenceFaaS {
@@ -78,9 +90,14 @@ impl FluenceFaaS {
         C: TryInto<FaaSConfig>,
         FaaSError: From<C::Error>,
     {
-        let mut marine = Marine::new();
-        let config = config.try_into()?;
-        let call_parameters = Rc::new(RefCell::new(<_>::default()));
+        let config: FaaSConfig = config.try_into()?;
+        let mut marine = match &config.module_cache_dir {
+            Some(module_cache_dir) => {
+                Marine::with_module_cache(ModuleCache::new(module_cache_dir)?)
+            }
+            None => Marine::new(),
+        };
+        let call_parameters = Arc::new(Mutex::new(<_>::default()));
 
         let modules_dir = config.modules_dir;
 
@@ -110,6 +127,7 @@ impl FluenceFaaS {
             marine,
             call_parameters,
             module_interfaces_cache: HashMap::new(),
+            interceptors: Vec::new(),
         })
     }
 
@@ -138,11 +156,18 @@ impl FluenceFaaS {
         args: &[IValue],
         call_parameters: marine_rs_sdk::CallParameters,
     ) -> Result<Vec<IValue>> {
-        self.call_parameters.replace(call_parameters);
+        self.call_intercepted(
+            module_name.as_ref(),
+            func_name.as_ref(),
+            args.to_vec(),
+            call_parameters,
+            None,
+        )
+    }
 
-        self.marine
-            .call(module_name, func_name, args)
-            .map_err(Into::into)
+    /// Register an interceptor that is invoked around every call made through this FaaS.
+    pub fn add_interceptor(&mut self, interceptor: impl CallInterceptor + 'static) {
+        self.interceptors.push(Box::new(interceptor));
     }
 
     /// Call a specified function of loaded on a startup module by its name.
@@ -152,13 +177,71 @@ impl FluenceFaaS {
         func_name: FN,
         json_args: JValue,
         call_parameters: marine_rs_sdk::CallParameters,
+    ) -> Result<JValue> {
+        self.call_with_json_(
+            module_name.as_ref(),
+            func_name.as_ref(),
+            json_args,
+            call_parameters,
+            None,
+        )
+    }
+
+    /// Call a specified function of loaded on a startup module by its name,
+    /// the call is interrupted when the provided deadline is reached.
+    pub fn call_with_json_and_deadline<MN: AsRef<str>, FN: AsRef<str>>(
+        &mut self,
+        module_name: MN,
+        func_name: FN,
+        json_args: JValue,
+        call_parameters: marine_rs_sdk::CallParameters,
+        deadline: impl Into<CallDeadline>,
+    ) -> Result<JValue> {
+        self.call_with_json_(
+            module_name.as_ref(),
+            func_name.as_ref(),
+            json_args,
+            call_parameters,
+            Some(deadline.into()),
+        )
+    }
+
+    /// Call a specified function of loaded on a startup module by its name and record a trace
+    /// of all module and host import calls made by it. The trace is returned even if the call
+    /// fails, it's None only if the call failed before reaching any module.
+    pub fn call_with_json_traced<MN: AsRef<str>, FN: AsRef<str>>(
+        &mut self,
+        module_name: MN,
+        func_name: FN,
+        json_args: JValue,
+        call_parameters: marine_rs_sdk::CallParameters,
+        trace_config: TraceConfig,
+    ) -> (Result<JValue>, Option<CallTrace>) {
+        self.marine.enable_call_tracing(trace_config);
+        let result = self.call_with_json_(
+            module_name.as_ref(),
+            func_name.as_ref(),
+            json_args,
+            call_parameters,
+            None,
+        );
+        let trace = self.marine.take_last_call_trace();
+        self.marine.disable_call_tracing();
+
+        (result, trace)
+    }
+
+    fn call_with_json_(
+        &mut self,
+        module_name: &str,
+        func_name: &str,
+        json_args: JValue,
+        call_parameters: marine_rs_sdk::CallParameters,
+        deadline: Option<CallDeadline>,
     ) -> Result<JValue> {
         use crate::json::json_to_ivalues;
         use crate::json::ivalues_to_json;
 
-        let module_name = module_name.as_ref();
-        let func_name = func_name.as_ref();
-
         let (func_signature, output_types, record_types) =
             self.lookup_module_interface(module_name, func_name)?;
         let iargs = json_to_ivalues(
@@ -167,12 +250,165 @@ impl FluenceFaaS {
             &record_types,
         )?;
 
-        self.call_parameters.replace(call_parameters);
-        let result = self.marine.call(module_name, func_name, &iargs)?;
+        let result =
+            self.call_intercepted(module_name, func_name, iargs, call_parameters, deadline)?;
 
         ivalues_to_json(result, &output_types, &record_types)
     }
 
+    fn call_intercepted(
+        &mut self,
+        module_name: &str,
+        func_name: &str,
+        mut arguments: Vec<IValue>,
+        call_parameters: marine_rs_sdk::CallParameters,
+        deadline: Option<CallDeadline>,
+    ) -> Result<Vec<IValue>> {
+        let context = CallContext {
+            module_name,
+            function_name: func_name,
+            call_parameters: &call_parameters,
+        };
+
+        let mut entered_count = 0;
+        let mut rejection = None;
+        for interceptor in self.interceptors.iter_mut() {
+            if let Err(reason) = interceptor.before_call(&context, &mut arguments) {
+                rejection = Some(reason);
+                break;
+            }
+            entered_count += 1;
+        }
+
+        let mut result = match rejection {
+            Some(reason) => Err(FaaSError::CallRejected {
+                module_name: module_name.to_string(),
+                function_name: func_name.to_string(),
+                reason,
+            }),
+            None => {
+                *self.call_parameters.lock().unwrap() = call_parameters.clone();
+                self.marine
+                    .set_deterministic_env(crate::host_imports::deterministic_env(
+                        &call_parameters,
+                    ));
+                match deadline {
+                    Some(deadline) => {
+                        self.marine
+                            .call_with_deadline(module_name, func_name, &arguments, deadline)
+                    }
+                    None => self.marine.call(module_name, func_name, &arguments),
+                }
+                .map_err(Into::into)
+            }
+        };
+
+        for interceptor in self.interceptors[..entered_count].iter_mut().rev() {
+            interceptor.after_call(&context, &mut result);
+        }
+
+        result
+    }
+
+    /// Call functions of loaded modules one by one, if a call fails the rest of calls is skipped.
+    /// In the atomic mode all modules touched by the batch are also rolled back
+    /// to their state before the batch.
+    pub fn call_batch(
+        &mut self,
+        requests: Vec<CallRequest>,
+        tx_mode: TxMode,
+    ) -> Result<Vec<CallOutputs>> {
+        let mut snapshots = HashMap::new();
+        let mut outputs = Vec::with_capacity(requests.len());
+
+        for (call_id, request) in requests.into_iter().enumerate() {
+            let result = match tx_mode {
+                TxMode::Atomic => self
+                    .snapshot_touched_modules(&request.module_name, &mut snapshots)
+                    .and_then(|_| self.call_request(request)),
+                TxMode::NonAtomic => self.call_request(request),
+            };
+
+            match result {
+                Ok(call_outputs) => outputs.push(call_outputs),
+                Err(error) if tx_mode == TxMode::Atomic => {
+                    return Err(self.rollback_batch(call_id, error, snapshots))
+                }
+                Err(error) => {
+                    return Err(FaaSError::BatchCallError {
+                        call_id,
+                        error: Box::new(error),
+                    })
+                }
+            }
+        }
+
+        Ok(outputs)
+    }
+
+    fn call_request(&mut self, request: CallRequest) -> Result<CallOutputs> {
+        let CallRequest {
+            module_name,
+            function_name,
+            arguments,
+            call_parameters,
+        } = request;
+
+        match arguments {
+            CallArguments::Json(arguments) => self
+                .call_with_json(module_name, function_name, arguments, call_parameters)
+                .map(CallOutputs::Json),
+            CallArguments::IValues(arguments) => self
+                .call_with_ivalues(module_name, function_name, &arguments, call_parameters)
+                .map(CallOutputs::IValues),
+        }
+    }
+
+    // takes snapshots of the module and all modules it could call,
+    // if they haven't been taken by previous calls of the batch
+    fn snapshot_touched_modules(
+        &mut self,
+        module_name: &str,
+        snapshots: &mut HashMap<String, ModuleSnapshot>,
+    ) -> Result<()> {
+        let mut modules = vec![module_name.to_string()];
+
+        while let Some(module_name) = modules.pop() {
+            if snapshots.contains_key(&module_name) {
+                continue;
+            }
+
+            let snapshot = self.marine.snapshot_module(&module_name)?;
+            modules.extend(self.marine.dependencies(&module_name));
+            snapshots.insert(module_name, snapshot);
+        }
+
+        Ok(())
+    }
+
+    fn rollback_batch(
+        &mut self,
+        call_id: usize,
+        error: FaaSError,
+        snapshots: HashMap<String, ModuleSnapshot>,
+    ) -> FaaSError {
+        let rollback_result = snapshots.iter().try_for_each(|(module_name, snapshot)| {
+            self.marine.restore_module(module_name, snapshot)
+        });
+
+        match rollback_result {
+            Ok(_) => FaaSError::BatchCallError {
+                call_id,
+                error: Box::new(error),
+            },
+            Err(rollback_error) => FaaSError::BatchRollbackError {
+                call_id,
+                error: Box::new(error),
+                rollback_error: Box::new(rollback_error.into()),
+            },
+        }
+    }
+
     /// Return all export functions (name and signatures) of loaded modules.
     pub fn get_interface(&self) -> FaaSInterface<'_> {
         let modules = self.marine.interface().collect();
@@ -180,13 +416,27 @@ impl FluenceFaaS {
         FaaSInterface { modules }
     }
 
+    /// Return resources consumed by loaded modules.
+    pub fn stats(&self) -> FaaSStats {
+        let modules = self
+            .marine
+            .interface()
+            .filter_map(|(name, _)| {
+                let stats = self.marine.module_stats(name)?;
+                Some((name.to_string(), stats))
+            })
+            .collect();
+
+        FaaSStats { modules }
+    }
+
     /// At first, tries to find function signature and record types in module_interface_cache,
     /// if there is no them, tries to look
     fn lookup_module_interface<'faas>(
         &'faas mut self,
         module_name: &str,
         func_name: &str,
-    ) -> Result<(Rc<Vec<IFunctionArg>>, Rc<Vec<IType>>, Rc<MRecordTypes>)> {
+    ) -> Result<(Arc<Vec<IFunctionArg>>, Arc<Vec<IType>>, Arc<MRecordTypes>)> {
         use FaaSError::NoSuchModule;
         use FaaSError::MissingFunctionError;
 
@@ -220,7 +470,7 @@ impl FluenceFaaS {
 
         let arg_types = arg_types.clone();
         let output_types = output_types.clone();
-        let record_types = Rc::new(module_interface.record_types.clone());
+        let record_types = Arc::new(module_interface.record_types.clone());
 
         let module_interface = ModuleInterface {
             function_signatures,
@@ -228,7 +478,7 @@ impl FluenceFaaS {
         };
 
         self.module_interfaces_cache
-            .insert(func_name.to_string(), module_interface);
+            .insert(module_name.to_string(), module_interface);
 
         Ok((arg_types, output_types, record_types))
     }
@@ -246,29 +496,99 @@ impl FluenceFaaS {
         let config = config.map(|c| c.try_into()).transpose()?;
         let name = name.into();
 
+        let marine_module_config = self.make_marine_config(name.clone(), config)?;
+        self.marine
+            .load_module(name, &wasm_bytes, marine_module_config)
+            .map_err(Into::into)
+    }
+
+    /// Replace code of a loaded module keeping its name and optionally migrating its state,
+    /// the old module stays loaded if the upgrade fails.
+    pub fn upgrade_module<S, C>(
+        &mut self,
+        name: S,
+        new_wasm_bytes: &[u8],
+        config: Option<C>,
+    ) -> Result<()>
+    where
+        S: Into<String>,
+        C: TryInto<crate::FaaSModuleConfig>,
+        FaaSError: From<C::Error>,
+    {
+        let config = config.map(|c| c.try_into()).transpose()?;
+        let name = name.into();
+
+        let marine_module_config = self.make_marine_config(name.clone(), config)?;
+        self.marine
+            .upgrade_module(&name, new_wasm_bytes, marine_module_config)?;
+
+        // interface of the new module could differ from the old one
+        self.module_interfaces_cache.remove(&name);
+
+        Ok(())
+    }
+
+    fn make_marine_config(
+        &self,
+        name: String,
+        config: Option<crate::FaaSModuleConfig>,
+    ) -> Result<marine::MModuleConfig> {
         // LoggerFilter can be initialized with an empty string
         let wasm_log_env = std::env::var(WASM_LOG_ENV_NAME).unwrap_or_default();
         let logger_filter = LoggerFilter::from_env_string(&wasm_log_env);
 
-        let marine_module_config = crate::config::make_marine_config(
-            name.clone(),
+        crate::config::make_marine_config(
+            name,
             config,
             self.call_parameters.clone(),
             &logger_filter,
-        )?;
+        )
+    }
+
+    pub fn unload_module<S: AsRef<str>>(&mut self, module_name: S) -> Result<()> {
+        let module_name = module_name.as_ref();
+        self.marine.unload_module(module_name)?;
+        // another module could be loaded with the same name later
+        self.module_interfaces_cache.remove(module_name);
+
+        Ok(())
+    }
+
+    /// Capture linear memory, mutable globals and WASI state of a loaded module.
+    pub fn snapshot_module<S: AsRef<str>>(&mut self, module_name: S) -> Result<ModuleSnapshot> {
+        self.marine.snapshot_module(module_name).map_err(Into::into)
+    }
+
+    /// Restore state of a loaded module from a previously taken snapshot.
+    pub fn restore_module<S: AsRef<str>>(
+        &mut self,
+        module_name: S,
+        snapshot: &ModuleSnapshot,
+    ) -> Result<()> {
         self.marine
-            .load_module(name, &wasm_bytes, marine_module_config)
+            .restore_module(module_name, snapshot)
             .map_err(Into::into)
     }
 
-    pub fn unload_module<S: AsRef<str>>(&mut self, module_name: S) -> Result<()> {
-        self.marine.unload_module(module_name).map_err(Into::into)
+    /// Unload a module along with all modules that depend on it, returns names of unloaded modules.
+    pub fn unload_module_cascade<S: AsRef<str>>(&mut self, module_name: S) -> Result<Vec<String>> {
+        let unloaded = self.marine.unload_module_cascade(module_name)?;
+        for module_name in unloaded.iter() {
+            self.module_interfaces_cache.remove(module_name);
+        }
+
+        Ok(unloaded)
+    }
+
+    /// Return names of modules that import functions of the module with given name.
+    pub fn module_dependents<S: AsRef<str>>(&self, module_name: S) -> Vec<String> {
+        self.marine.dependents(module_name)
     }
 
     pub fn module_wasi_state<S: AsRef<str>>(
         &mut self,
         module_name: S,
-    ) -> Result<&wasmer_wasi::state::WasiState> {
+    ) -> Result<crate::ModuleWasiState<'_>> {
         let module_name = module_name.as_ref();
 
         self.marine
@@ -0,0 +1,63 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::ModuleStats;
+
+use serde::Serialize;
+
+use std::collections::HashMap;
+
+/// Resources consumed by modules of a FaaS instance.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize)]
+pub struct FaaSStats {
+    pub modules: HashMap<String, ModuleStats>,
+}
+
+impl FaaSStats {
+    /// Count of Wasm memory pages currently used by all modules.
+    pub fn memory_pages(&self) -> u64 {
+        self.sum(|stats| stats.memory_pages as u64)
+    }
+
+    /// Sum of peak counts of Wasm memory pages of all modules.
+    pub fn peak_memory_pages(&self) -> u64 {
+        self.sum(|stats| stats.peak_memory_pages as u64)
+    }
+
+    /// Count of calls of all export functions, calls between modules are counted as well.
+    pub fn calls_count(&self) -> u64 {
+        self.sum(|stats| {
+            stats
+                .functions
+                .values()
+                .map(|function_stats| function_stats.calls_count)
+                .sum()
+        })
+    }
+
+    /// Count of invocations of all host imports.
+    pub fn host_import_calls_count(&self) -> u64 {
+        self.sum(|stats| stats.host_imports.values().sum())
+    }
+
+    pub fn traps_count(&self) -> u64 {
+        self.sum(|stats| stats.traps_count)
+    }
+
+    fn sum(&self, value: impl Fn(&ModuleStats) -> u64) -> u64 {
+        self.modules.values().map(value).sum()
+    }
+}
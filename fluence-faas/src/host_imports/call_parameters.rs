@@ -14,28 +14,134 @@
  * limitations under the License.
  */
 
+use super::host_record_type;
+
+use marine::DeterministicEnv;
 use marine::HostImportDescriptor;
+use marine::HostImportError;
+use marine::MRecordTypes;
 use wasmer_core::vm::Ctx;
 use wasmer_it::IValue;
 use wasmer_it::IType;
 
-use std::rc::Rc;
-use std::cell::RefCell;
 use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+const CALL_PARAMETERS_RECORD_ID: u64 = 0;
+const SECURITY_TETRAPLET_RECORD_ID: u64 = 1;
 
 /// Create the import intended for handling get_call_parameters SDK api.
 pub(crate) fn create_call_parameters_import(
-    call_parameters: Rc<RefCell<marine_rs_sdk::CallParameters>>,
+    call_parameters: Arc<Mutex<marine_rs_sdk::CallParameters>>,
 ) -> HostImportDescriptor {
     let call_parameters_closure = move |_ctx: &mut Ctx, _args: Vec<IValue>| {
-        let result = crate::to_interface_value(call_parameters.borrow().deref()).unwrap();
-        Some(result)
+        // the lock can't be poisoned, because nothing panics while holding it
+        let call_parameters = call_parameters.lock().unwrap();
+        match crate::to_interface_value(call_parameters.deref()) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                // it traps the calling module and fails the call with the error
+                marine::fail_host_import(HostImportError::InvalidResult(format!(
+                    "call parameters can't be passed to the module: {}",
+                    e
+                )));
+                None
+            }
+        }
     };
 
     HostImportDescriptor {
         host_exported_func: Box::new(call_parameters_closure),
         argument_types: vec![],
-        output_type: Some(IType::Record(0)),
+        output_type: Some(IType::Record(CALL_PARAMETERS_RECORD_ID)),
         error_handler: None,
+        record_types: call_parameters_record_types(),
+    }
+}
+
+/// Derives time and random seed observed by deterministic modules from call parameters.
+/// The seed depends only on parameters that are the same on all peers replaying a call,
+/// so host_id and service_id are ignored. Call parameters don't contain time,
+/// so the virtual clock always starts at the Unix epoch.
+pub(crate) fn deterministic_env(
+    call_parameters: &marine_rs_sdk::CallParameters,
+) -> DeterministicEnv {
+    // FNV-1a, it's stable across platforms and Rust versions unlike the std hasher
+    let mut seed: u64 = 0xcbf2_9ce4_8422_2325;
+    let fields = [&call_parameters.particle_id, &call_parameters.init_peer_id];
+    for field in fields.iter() {
+        // a zero byte separates fields, so their boundaries affect the seed
+        for &byte in field.as_bytes().iter().chain(&[0]) {
+            seed ^= byte as u64;
+            seed = seed.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    DeterministicEnv {
+        timestamp_ns: 0,
+        seed,
+    }
+}
+
+/// Record types of CallParameters and SecurityTetraplet as they are defined in the SDK.
+fn call_parameters_record_types() -> MRecordTypes {
+    let tetraplets_type = IType::Array(Box::new(IType::Array(Box::new(IType::Record(
+        SECURITY_TETRAPLET_RECORD_ID,
+    )))));
+
+    let call_parameters = host_record_type(
+        "CallParameters",
+        vec![
+            ("init_peer_id", IType::String),
+            ("service_id", IType::String),
+            ("service_creator_peer_id", IType::String),
+            ("host_id", IType::String),
+            ("particle_id", IType::String),
+            ("tetraplets", tetraplets_type),
+        ],
+    );
+    let security_tetraplet = host_record_type(
+        "SecurityTetraplet",
+        vec![
+            ("peer_pk", IType::String),
+            ("service_id", IType::String),
+            ("function_name", IType::String),
+            ("json_path", IType::String),
+        ],
+    );
+
+    let mut record_types = MRecordTypes::new();
+    record_types.insert(CALL_PARAMETERS_RECORD_ID, call_parameters);
+    record_types.insert(SECURITY_TETRAPLET_RECORD_ID, security_tetraplet);
+    record_types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::deterministic_env;
+
+    use marine_rs_sdk::CallParameters;
+
+    #[test]
+    fn deterministic_env_is_the_same_on_all_peers() {
+        let call_parameters = |host_id: &str, particle_id: &str| CallParameters {
+            init_peer_id: String::from("init_peer_id"),
+            service_id: format!("{}_service", host_id),
+            service_creator_peer_id: String::from("service_creator_peer_id"),
+            host_id: host_id.to_string(),
+            particle_id: particle_id.to_string(),
+            tetraplets: vec![],
+        };
+
+        let env = deterministic_env(&call_parameters("peer_1", "particle_1"));
+        assert_eq!(
+            env,
+            deterministic_env(&call_parameters("peer_2", "particle_1"))
+        );
+        assert_ne!(
+            env,
+            deterministic_env(&call_parameters("peer_1", "particle_2"))
+        );
     }
 }
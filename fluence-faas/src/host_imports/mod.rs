@@ -19,4 +19,29 @@ mod call_parameters;
 mod mounted_binaries;
 
 pub(crate) use call_parameters::create_call_parameters_import;
+pub(crate) use call_parameters::deterministic_env;
 pub(crate) use mounted_binaries::create_mounted_binary_import;
+
+use marine::IRecordFieldType;
+use marine::IRecordType;
+use marine::IType;
+use marine::ne_vec::NEVec;
+
+use std::sync::Arc;
+
+/// Creates a record type defined by the host with the provided non-empty fields.
+pub(self) fn host_record_type(name: &str, fields: Vec<(&str, IType)>) -> Arc<IRecordType> {
+    let fields = fields
+        .into_iter()
+        .map(|(name, ty)| IRecordFieldType {
+            name: name.to_string(),
+            ty,
+        })
+        .collect();
+
+    Arc::new(IRecordType {
+        name: name.to_string(),
+        // records of host imports are defined statically with at least one field
+        fields: NEVec::new(fields).unwrap(),
+    })
+}
@@ -14,40 +14,81 @@
  * limitations under the License.
  */
 
+use super::host_record_type;
+
 use marine::HostImportDescriptor;
+use marine::HostImportError;
+use marine::MRecordTypes;
 use marine_rs_sdk::MountedBinaryResult;
 
 use wasmer_core::vm::Ctx;
 use wasmer_it::IValue;
 use wasmer_it::IType;
 
+use std::io::Read;
+use std::process::Command;
+use std::process::Output;
+use std::process::Stdio;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// a call could be cancelled from another thread at any moment, so while a binary is running
+// its exit and interruption of the current call are checked with this interval
+const INTERRUPTION_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+const MOUNTED_BINARY_RESULT_RECORD_ID: u64 = 0;
+
 pub(crate) fn create_mounted_binary_import(mounted_binary_path: String) -> HostImportDescriptor {
     let host_cmd_closure = move |_ctx: &mut Ctx, raw_args: Vec<IValue>| {
         let result =
             mounted_binary_import_impl(&mounted_binary_path, raw_args).unwrap_or_else(Into::into);
 
-        let raw_result = crate::to_interface_value(&result).unwrap();
-
-        Some(raw_result)
+        match crate::to_interface_value(&result) {
+            Ok(raw_result) => Some(raw_result),
+            Err(e) => {
+                // it traps the calling module and fails the call with the error
+                marine::fail_host_import(HostImportError::InvalidResult(format!(
+                    "mounted binary result can't be passed to the module: {}",
+                    e
+                )));
+                None
+            }
+        }
     };
 
     HostImportDescriptor {
         host_exported_func: Box::new(host_cmd_closure),
         argument_types: vec![IType::Array(Box::new(IType::String))],
-        output_type: Some(IType::Record(0)),
+        output_type: Some(IType::Record(MOUNTED_BINARY_RESULT_RECORD_ID)),
         error_handler: None,
+        record_types: mounted_binary_record_types(),
     }
 }
 
+/// Record type of MountedBinaryResult as it's defined in the SDK.
+fn mounted_binary_record_types() -> MRecordTypes {
+    let mounted_binary_result = host_record_type(
+        "MountedBinaryResult",
+        vec![
+            ("ret_code", IType::S32),
+            ("error", IType::String),
+            ("stdout", IType::ByteArray),
+            ("stderr", IType::ByteArray),
+        ],
+    );
+
+    let mut record_types = MRecordTypes::new();
+    record_types.insert(MOUNTED_BINARY_RESULT_RECORD_ID, mounted_binary_result);
+    record_types
+}
+
 pub(self) fn mounted_binary_import_impl(
     mounted_binary_path: &str,
     raw_args: Vec<IValue>,
 ) -> Result<MountedBinaryResult, MountedBinaryResult> {
     let args = parse_args(raw_args)?;
 
-    let result = std::process::Command::new(mounted_binary_path)
-        .args(&args)
-        .output();
+    let result = run_mounted_binary(mounted_binary_path, &args);
 
     let result = match result {
         Ok(output) => {
@@ -84,6 +125,53 @@ pub(self) fn mounted_binary_import_impl(
     Ok(result)
 }
 
+/// Runs a mounted binary and kills it if the current Marine call is interrupted.
+fn run_mounted_binary(mounted_binary_path: &str, args: &[String]) -> std::io::Result<Output> {
+    let mut child = Command::new(mounted_binary_path)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // pipes are read in background to prevent the child from blocking on a full pipe
+    let stdout_reader = read_in_background(child.stdout.take());
+    let stderr_reader = read_in_background(child.stderr.take());
+
+    // try_wait doesn't block, so the child could be killed if the call is interrupted
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if marine::is_current_call_interrupted() {
+            // the child could exit right before killing, so the error is ignored here
+            let _ = child.kill();
+            break child.wait()?;
+        }
+
+        std::thread::sleep(INTERRUPTION_CHECK_INTERVAL);
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+    })
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            // the binary output is returned as is even if it is read partially
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+
+        buffer
+    })
+}
+
 fn parse_args(mut raw_args: Vec<IValue>) -> Result<Vec<String>, MountedBinaryResult> {
     if raw_args.len() != 1 {
         return Err(MountedBinaryResult::from_error(100002, "internal error is encountered while passing arguments to a mounted binary closure, probably you use a not suitable version of rust-sdk"));
@@ -112,6 +200,7 @@ fn parse_args(mut raw_args: Vec<IValue>) -> Result<Vec<String>, MountedBinaryRes
 #[cfg(test)]
 mod tests {
     use super::mounted_binary_import_impl;
+    use super::run_mounted_binary;
 
     #[test]
     fn call_non_existent_binary() {
@@ -120,4 +209,13 @@ mod tests {
 
         assert_eq!(actual.ret_code, 100002);
     }
+
+    #[test]
+    fn run_binary_till_exit() {
+        let args = vec![String::from("-c"), String::from("sleep 0.1; echo done")];
+        let output = run_mounted_binary("sh", &args).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"done\n");
+    }
 }
@@ -0,0 +1,51 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::CallParameters;
+use crate::IValue;
+use crate::FaaSError;
+
+/// Describes a call of a module function made through FaaS.
+#[derive(Debug, Clone, Copy)]
+pub struct CallContext<'c> {
+    pub module_name: &'c str,
+    pub function_name: &'c str,
+    pub call_parameters: &'c CallParameters,
+}
+
+/// Hooks invoked around every call made through FaaS, they allow to check, audit or rewrite
+/// calls without changing the calling code. Interceptors are invoked in the order
+/// of registration before a call and in the reverse order after it.
+pub trait CallInterceptor: Send + Sync {
+    /// Invoked before a call, arguments could be rewritten here. If an error is returned,
+    /// the call is rejected with it and the rest of interceptors isn't invoked.
+    fn before_call(
+        &mut self,
+        _context: &CallContext<'_>,
+        _arguments: &mut Vec<IValue>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Invoked after a call if before_call of this interceptor succeeded, even if the call
+    /// failed or was rejected by a later interceptor. The result could be rewritten here.
+    fn after_call(
+        &mut self,
+        _context: &CallContext<'_>,
+        _result: &mut Result<Vec<IValue>, FaaSError>,
+    ) {
+    }
+}
@@ -24,18 +24,29 @@
     unreachable_patterns
 )]
 
+mod batch;
 mod config;
 mod host_imports;
 mod json;
 mod errors;
 mod faas;
 mod faas_interface;
+mod faas_stats;
+mod interceptor;
 mod module_loading;
 
 pub(crate) type Result<T> = std::result::Result<T, FaaSError>;
 
 pub use faas::FluenceFaaS;
 pub use faas_interface::FaaSInterface;
+pub use faas_stats::FaaSStats;
+pub use interceptor::CallContext;
+pub use interceptor::CallInterceptor;
+
+pub use batch::CallRequest;
+pub use batch::CallArguments;
+pub use batch::CallOutputs;
+pub use batch::TxMode;
 
 pub use config::FaaSConfig;
 pub use config::FaaSModuleConfig;
@@ -46,6 +57,9 @@ pub use config::TomlFaaSConfig;
 pub use config::TomlFaaSModuleConfig;
 pub use config::TomlFaaSNamedModuleConfig;
 pub use config::TomlWASIConfig;
+pub use config::TomlWASIPath;
+pub use config::TomlVfsConfig;
+pub use config::TomlWASICapabilities;
 
 pub use errors::FaaSError;
 
@@ -59,7 +73,27 @@ pub use marine::MFunctionSignature as FaaSFunctionSignature;
 pub use marine::MRecordTypes;
 pub use marine::HostExportedFunc;
 pub use marine::HostImportDescriptor;
+pub use marine::HostImportName;
 pub use marine::HostImportError;
+pub use marine::fail_host_import;
+pub use marine::HostImportValue;
+pub use marine::HostImportRecord;
+pub use marine::HostImportOutput;
+pub use marine::IntoHostImport;
+pub use marine::CallDeadline;
+pub use marine::DeterministicEnv;
+pub use marine::CancellationHandle;
+pub use marine::CallKind;
+pub use marine::CallTrace;
+pub use marine::TraceConfig;
+pub use marine::is_current_call_interrupted;
+pub use marine::ModuleSnapshot;
+pub use marine::ModuleStats;
+pub use marine::ModuleWasiState;
+pub use marine::FunctionStats;
+pub use marine::VfsConfig;
+pub use marine::DiskQuota;
+pub use marine::WasiCapabilities;
 pub use marine::to_interface_value;
 pub use marine::from_interface_values;
 pub use marine::ne_vec;
@@ -67,3 +67,138 @@ pub fn call_parameters() {
         ))]
     );
 }
+
+#[test]
+// test that FaaS instances could be moved to other threads and keep their call parameters apart
+pub fn call_parameters_concurrently() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<FluenceFaaS>();
+
+    const THREADS_COUNT: usize = 4;
+    const CALLS_COUNT: usize = 20;
+
+    let handles = (0..THREADS_COUNT)
+        .map(|thread_id| {
+            let call_parameters_config_raw =
+                std::fs::read("../examples/call_parameters/Config.toml")
+                    .expect("../examples/call_parameters/Config.toml should presence");
+
+            let mut call_parameters_config: fluence_faas::TomlFaaSConfig =
+                toml::from_slice(&call_parameters_config_raw)
+                    .expect("call_parameters config should be well-formed");
+            call_parameters_config.modules_dir =
+                Some(String::from("../examples/call_parameters/artifacts"));
+
+            let mut faas = FluenceFaaS::with_raw_config(call_parameters_config)
+                .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e));
+
+            std::thread::spawn(move || {
+                for call_id in 0..CALLS_COUNT {
+                    let call_parameters = marine_rs_sdk::CallParameters {
+                        init_peer_id: format!("init_peer_id {}", thread_id),
+                        particle_id: format!("particle_id {}", call_id),
+                        ..<_>::default()
+                    };
+
+                    let result = faas
+                        .call_with_ivalues(
+                            "call_parameters",
+                            "call_parameters",
+                            &[],
+                            call_parameters.clone(),
+                        )
+                        .unwrap_or_else(|e| panic!("can't invoke call_parameters: {:?}", e));
+
+                    assert_eq!(
+                        result,
+                        vec![IValue::String(format!(
+                            "{}\n\n\n\n{}\n[]",
+                            call_parameters.init_peer_id, call_parameters.particle_id
+                        ))]
+                    );
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle
+            .join()
+            .expect("thread calling call_parameters shouldn't panic");
+    }
+}
+
+#[test]
+pub fn call_parameters_traced() {
+    let call_parameters_config_raw = std::fs::read("../examples/call_parameters/Config.toml")
+        .expect("../examples/call_parameters/Config.toml should presence");
+
+    let mut call_parameters_config: fluence_faas::TomlFaaSConfig =
+        toml::from_slice(&call_parameters_config_raw)
+            .expect("call_parameters config should be well-formed");
+    call_parameters_config.modules_dir =
+        Some(String::from("../examples/call_parameters/artifacts"));
+
+    let mut faas = FluenceFaaS::with_raw_config(call_parameters_config)
+        .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e));
+
+    let call_parameters = marine_rs_sdk::CallParameters {
+        init_peer_id: String::from("init_peer_id"),
+        ..<_>::default()
+    };
+
+    let (result, trace) = faas.call_with_json_traced(
+        "call_parameters",
+        "call_parameters",
+        serde_json::json!([]),
+        call_parameters,
+        <_>::default(),
+    );
+    let result = result.unwrap_or_else(|e| panic!("can't invoke call_parameters: {:?}", e));
+    let trace = trace.expect("the call should be traced");
+
+    assert_eq!(trace.module_name, "call_parameters");
+    assert_eq!(trace.function_name, "call_parameters");
+    assert_eq!(
+        trace.results,
+        vec![IValue::String(result.as_str().unwrap().to_string())]
+    );
+
+    assert_eq!(trace.nested_calls.len(), 1);
+    let host_call = &trace.nested_calls[0];
+    assert_eq!(host_call.kind, fluence_faas::CallKind::HostImport);
+    assert_eq!(host_call.module_name, "host");
+    assert_eq!(host_call.function_name, "get_call_parameters");
+    assert_eq!(host_call.error, None);
+    assert_eq!(host_call.results.len(), 1);
+}
+
+#[test]
+pub fn call_parameters_stats() {
+    let call_parameters_config_raw = std::fs::read("../examples/call_parameters/Config.toml")
+        .expect("../examples/call_parameters/Config.toml should presence");
+
+    let mut call_parameters_config: fluence_faas::TomlFaaSConfig =
+        toml::from_slice(&call_parameters_config_raw)
+            .expect("call_parameters config should be well-formed");
+    call_parameters_config.modules_dir =
+        Some(String::from("../examples/call_parameters/artifacts"));
+
+    let mut faas = FluenceFaaS::with_raw_config(call_parameters_config)
+        .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e));
+
+    for _ in 0..2 {
+        faas.call_with_ivalues("call_parameters", "call_parameters", &[], <_>::default())
+            .unwrap_or_else(|e| panic!("can't invoke call_parameters: {:?}", e));
+    }
+
+    let stats = faas.stats();
+    let module_stats = &stats.modules["call_parameters"];
+    assert_eq!(module_stats.functions["call_parameters"].calls_count, 2);
+    assert_eq!(module_stats.host_imports["get_call_parameters"], 2);
+
+    assert_eq!(stats.calls_count(), 2);
+    assert_eq!(stats.host_import_calls_count(), 2);
+    assert_eq!(stats.traps_count(), 0);
+    assert_eq!(stats.memory_pages(), module_stats.memory_pages as u64);
+}
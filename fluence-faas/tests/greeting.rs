@@ -20,7 +20,7 @@ use fluence_faas::IValue;
 
 use pretty_assertions::assert_eq;
 
-use std::rc::Rc;
+use std::sync::Arc;
 
 #[test]
 pub fn greeting() {
@@ -81,9 +81,9 @@ pub fn get_interfaces() {
     let output_types = vec![fluence_faas::IType::String];
 
     let greeting_sign = fluence_faas::FaaSFunctionSignature {
-        name: Rc::new(String::from("greeting")),
-        arguments: Rc::new(arguments),
-        outputs: Rc::new(output_types),
+        name: Arc::new(String::from("greeting")),
+        arguments: Arc::new(arguments),
+        outputs: Arc::new(output_types),
     };
 
     let record_types = std::collections::HashMap::new();
@@ -97,3 +97,173 @@ pub fn get_interfaces() {
 
     assert_eq!(interface, fluence_faas::FaaSInterface { modules });
 }
+
+#[test]
+pub fn call_batch() {
+    use fluence_faas::CallOutputs;
+    use fluence_faas::CallRequest;
+    use fluence_faas::TxMode;
+    use serde_json::json;
+
+    let greeting_config_path = "../examples/greeting/Config.toml";
+
+    let greeting_config_raw = std::fs::read(greeting_config_path)
+        .expect("../examples/greeting/Config.toml should presence");
+
+    let mut greeting_config: fluence_faas::TomlFaaSConfig =
+        toml::from_slice(&greeting_config_raw).expect("greeting config should be well-formed");
+    greeting_config.modules_dir = Some(String::from("../examples/greeting/artifacts"));
+
+    let mut faas = FluenceFaaS::with_raw_config(greeting_config)
+        .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e));
+
+    let requests = vec![
+        CallRequest::new(
+            "greeting",
+            "greeting",
+            vec![IValue::String(String::from("Fluence"))].into(),
+            <_>::default(),
+        ),
+        CallRequest::new(
+            "greeting",
+            "greeting",
+            json!(["Marine"]).into(),
+            <_>::default(),
+        ),
+    ];
+    let outputs = faas
+        .call_batch(requests, TxMode::Atomic)
+        .unwrap_or_else(|e| panic!("can't invoke a batch: {:?}", e));
+
+    assert_eq!(
+        outputs,
+        vec![
+            CallOutputs::IValues(vec![IValue::String(String::from("Hi, Fluence"))]),
+            CallOutputs::Json(json!("Hi, Marine")),
+        ]
+    );
+
+    let requests = vec![
+        CallRequest::new(
+            "greeting",
+            "greeting",
+            json!(["Fluence"]).into(),
+            <_>::default(),
+        ),
+        CallRequest::new(
+            "greeting",
+            "non_existent_function",
+            json!([]).into(),
+            <_>::default(),
+        ),
+    ];
+    let result = faas.call_batch(requests, TxMode::Atomic);
+    assert!(matches!(
+        result.err().unwrap(),
+        fluence_faas::FaaSError::BatchCallError { call_id: 1, .. }
+    ));
+
+    // the module is still usable after rolling back
+    let result = faas
+        .call_with_json("greeting", "greeting", json!(["Fluence"]), <_>::default())
+        .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+    assert_eq!(result, json!("Hi, Fluence"));
+}
+
+#[test]
+pub fn interceptors() {
+    use fluence_faas::CallContext;
+    use fluence_faas::CallInterceptor;
+    use fluence_faas::FaaSError;
+
+    use std::sync::Mutex;
+
+    struct Sanitizer;
+
+    impl CallInterceptor for Sanitizer {
+        fn before_call(
+            &mut self,
+            context: &CallContext<'_>,
+            arguments: &mut Vec<IValue>,
+        ) -> Result<(), String> {
+            if context.call_parameters.init_peer_id == "banned" {
+                return Err(String::from("peer is banned"));
+            }
+
+            for argument in arguments.iter_mut() {
+                if let IValue::String(value) = argument {
+                    *value = value.to_uppercase();
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    struct Auditor {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CallInterceptor for Auditor {
+        fn after_call(
+            &mut self,
+            context: &CallContext<'_>,
+            result: &mut Result<Vec<IValue>, FaaSError>,
+        ) {
+            let record = format!(
+                "{}.{} {}",
+                context.module_name,
+                context.function_name,
+                result.is_ok()
+            );
+            self.log.lock().unwrap().push(record);
+        }
+    }
+
+    let greeting_config_raw = std::fs::read("../examples/greeting/Config.toml")
+        .expect("../examples/greeting/Config.toml should presence");
+
+    let mut greeting_config: fluence_faas::TomlFaaSConfig =
+        toml::from_slice(&greeting_config_raw).expect("greeting config should be well-formed");
+    greeting_config.modules_dir = Some(String::from("../examples/greeting/artifacts"));
+
+    let mut faas = FluenceFaaS::with_raw_config(greeting_config)
+        .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e));
+
+    let log = Arc::new(Mutex::new(Vec::new()));
+    faas.add_interceptor(Auditor { log: log.clone() });
+    faas.add_interceptor(Sanitizer);
+
+    let result = faas
+        .call_with_json(
+            "greeting",
+            "greeting",
+            serde_json::json!(["Fluence"]),
+            <_>::default(),
+        )
+        .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+    assert_eq!(result, serde_json::json!("Hi, FLUENCE"));
+
+    let call_parameters = fluence_faas::CallParameters {
+        init_peer_id: String::from("banned"),
+        ..<_>::default()
+    };
+    let result = faas.call_with_ivalues(
+        "greeting",
+        "greeting",
+        &[IValue::String(String::from("Fluence"))],
+        call_parameters,
+    );
+    assert!(matches!(
+        result,
+        Err(FaaSError::CallRejected { reason, .. }) if reason == "peer is banned"
+    ));
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            String::from("greeting.greeting true"),
+            String::from("greeting.greeting false")
+        ]
+    );
+}
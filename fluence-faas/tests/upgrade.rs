@@ -0,0 +1,52 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+#![cfg(feature = "raw-module-api")]
+
+mod utils;
+
+use fluence_faas::FaaSModuleConfig;
+use fluence_faas::FluenceFaaS;
+use fluence_faas::TomlFaaSConfig;
+
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+#[test]
+pub fn upgrade_with_changed_signature() {
+    let old_wasm_bytes = std::fs::read(
+        "./tests/wasm_tests/arguments_passing/artifacts/arguments_passing_effector.wasm",
+    )
+    .expect("arguments_passing_effector.wasm should presence");
+    let new_wasm_bytes =
+        std::fs::read("./tests/wasm_tests/arrays_passing/artifacts/arrays_passing_effector.wasm")
+            .expect("arrays_passing_effector.wasm should presence");
+
+    let mut faas = FluenceFaaS::with_raw_config(TomlFaaSConfig::default())
+        .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e));
+    faas.load_module::<_, FaaSModuleConfig>("effector", &old_wasm_bytes, None)
+        .unwrap_or_else(|e| panic!("can't load a module into FaaS: {:?}", e));
+
+    let result = call_faas!(faas, "effector", "string_type", json!(["Fluence"]));
+    assert_eq!(result, json!("Fluence_Fluence"));
+
+    // string_type accepts and returns an array of strings in the new version
+    faas.upgrade_module::<_, FaaSModuleConfig>("effector", &new_wasm_bytes, None)
+        .unwrap_or_else(|e| panic!("can't upgrade a module in FaaS: {:?}", e));
+
+    let result = call_faas!(faas, "effector", "string_type", json!([["Fluence"]]));
+    assert_eq!(result, json!(["Fluence", "from effector"]));
+}
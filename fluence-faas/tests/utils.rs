@@ -14,6 +14,15 @@
  * limitations under the License.
  */
 
+// each test uses only a part of the fixtures
+#![allow(dead_code)]
+
+use fluence_faas::FluenceFaaS;
+
+use serde_json::json;
+use serde_json::Value as JValue;
+use tempfile::TempDir;
+
 #[macro_export]
 macro_rules! call_faas {
     ($faas:expr, $module_name:expr, $func_name:expr, $args:expr) => {
@@ -22,3 +31,48 @@ macro_rules! call_faas {
             .unwrap_or_else(|e| panic!("faas failed with {:?}", e))
     };
 }
+
+/// Creates a temporary dir, it's removed when the guard is dropped even if the test fails.
+pub fn temp_dir(prefix: &str) -> TempDir {
+    tempfile::Builder::new()
+        .prefix(prefix)
+        .tempdir()
+        .unwrap_or_else(|e| panic!("can't create a temporary dir: {:?}", e))
+}
+
+/// Creates FaaS with the local_storage example module, the provided TOML tables
+/// are added to the config of the module.
+pub fn local_storage(module_config: &str) -> FluenceFaaS {
+    let config = format!(
+        r#"
+        modules_dir = "../examples/url-downloader/artifacts"
+
+        [[module]]
+            name = "local_storage"
+            logger_enabled = true
+
+            {}
+        "#,
+        module_config
+    );
+    let config: fluence_faas::TomlFaaSConfig =
+        toml::from_str(&config).expect("local_storage config should be well-formed");
+
+    FluenceFaaS::with_raw_config(config)
+        .unwrap_or_else(|e| panic!("can't create Fluence FaaS instance: {}", e))
+}
+
+pub fn put(faas: &mut FluenceFaaS, name: &str, content: &[u8]) -> JValue {
+    faas.call_with_json(
+        "local_storage",
+        "put",
+        json!([name, content]),
+        <_>::default(),
+    )
+    .unwrap_or_else(|e| panic!("can't invoke put: {:?}", e))
+}
+
+pub fn get(faas: &mut FluenceFaaS, name: &str) -> JValue {
+    faas.call_with_json("local_storage", "get", json!([name]), <_>::default())
+        .unwrap_or_else(|e| panic!("can't invoke get: {:?}", e))
+}
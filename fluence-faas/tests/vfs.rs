@@ -0,0 +1,99 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod utils;
+
+use utils::get;
+use utils::put;
+
+use fluence_faas::FluenceFaaS;
+
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+use std::path::Path;
+
+// the host dir mapped to the sites dir must never be touched
+fn local_storage(host_sites_dir: &Path, vfs_config: &str) -> FluenceFaaS {
+    utils::local_storage(&format!(
+        r#"
+        [module.wasi]
+        mapped_dirs = {{ "sites" = {:?} }}
+
+        [module.wasi.vfs]
+        {}
+        "#,
+        host_sites_dir, vfs_config
+    ))
+}
+
+#[test]
+pub fn vfs_from_toml_config() {
+    let host_sites_dir = utils::temp_dir("faas_vfs_sites");
+    let mut faas = local_storage(host_sites_dir.path(), "max_size = 16");
+
+    assert_eq!(put(&mut faas, "file", b"content"), json!("Ok"));
+    assert_eq!(get(&mut faas, "file"), json!(b"content"));
+    let host_files = std::fs::read_dir(host_sites_dir.path()).unwrap();
+    assert_eq!(host_files.count(), 0);
+
+    // the quota of the config is applied
+    let result = put(&mut faas, "big", &[0; 16]);
+    assert!(result.as_str().unwrap().contains("No space left on device"));
+}
+
+#[test]
+pub fn call_batch_rollback() {
+    use fluence_faas::CallRequest;
+    use fluence_faas::TxMode;
+
+    let host_sites_dir = utils::temp_dir("faas_batch_sites");
+    let mut faas = local_storage(host_sites_dir.path(), "");
+    put(&mut faas, "file", b"old");
+
+    // the first call changes the module state, the second one fails
+    let requests = || {
+        vec![
+            CallRequest::new(
+                "local_storage",
+                "put",
+                json!(["file", b"new"]).into(),
+                <_>::default(),
+            ),
+            CallRequest::new(
+                "local_storage",
+                "non_existent_function",
+                json!([]).into(),
+                <_>::default(),
+            ),
+        ]
+    };
+
+    let result = faas.call_batch(requests(), TxMode::Atomic);
+    assert!(matches!(
+        result.err().unwrap(),
+        fluence_faas::FaaSError::BatchCallError { call_id: 1, .. }
+    ));
+    assert_eq!(get(&mut faas, "file"), json!(b"old"));
+
+    // changes of a non-atomic batch are kept
+    let result = faas.call_batch(requests(), TxMode::NonAtomic);
+    assert!(matches!(
+        result.err().unwrap(),
+        fluence_faas::FaaSError::BatchCallError { call_id: 1, .. }
+    ));
+    assert_eq!(get(&mut faas, "file"), json!(b"new"));
+}
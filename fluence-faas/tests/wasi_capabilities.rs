@@ -0,0 +1,58 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod utils;
+
+use utils::get;
+use utils::put;
+
+use fluence_faas::FluenceFaaS;
+
+use pretty_assertions::assert_eq;
+use serde_json::json;
+
+use std::path::Path;
+
+fn local_storage(sites_dir: &Path, capabilities: &str) -> FluenceFaaS {
+    utils::local_storage(&format!(
+        r#"
+        [module.wasi]
+        mapped_dirs = {{ "sites" = {:?} }}
+
+        [module.wasi.capabilities]
+        {}
+        "#,
+        sites_dir, capabilities
+    ))
+}
+
+#[test]
+pub fn wasi_capabilities_from_toml_config() {
+    let sites_dir = utils::temp_dir("faas_capabilities");
+    let sites_dir = sites_dir.path();
+
+    // other groups stay enabled, so the filesystem is available
+    let mut faas = local_storage(sites_dir, "clock = false");
+    assert_eq!(put(&mut faas, "allowed", b"content"), json!("Ok"));
+
+    let mut faas = local_storage(sites_dir, "filesystem = false");
+    assert_ne!(put(&mut faas, "disabled", b"content"), json!("Ok"));
+    assert!(!sites_dir.join("disabled").exists());
+    assert_eq!(
+        get(&mut faas, "allowed"),
+        json!(b"error while reading file")
+    );
+}
@@ -0,0 +1,219 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::IValue;
+
+use wasmer_it::NEVec;
+
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::time::Duration;
+use std::time::Instant;
+
+thread_local!(static ACTIVE_TRACES: RefCell<Vec<ActiveTrace>> = RefCell::new(Vec::new()));
+
+/// Describes what is recorded into call traces.
+#[derive(Clone, Debug, Default)]
+pub struct TraceConfig {
+    /// Strings, byte arrays and arrays longer than this are truncated in recorded arguments
+    /// and results. If None, values are recorded as is.
+    pub max_value_len: Option<usize>,
+}
+
+/// Kind of a traced call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallKind {
+    /// Call of an export function of a module.
+    Module,
+
+    /// Call of a host import from a module.
+    HostImport,
+}
+
+/// A call made during a traced call of Marine along with all calls made by it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallTrace {
+    pub kind: CallKind,
+    pub module_name: String,
+    pub function_name: String,
+    pub arguments: Vec<IValue>,
+
+    /// Results of the call, they are empty if the call failed.
+    pub results: Vec<IValue>,
+
+    /// Description of the error if the call failed.
+    pub error: Option<String>,
+
+    pub duration: Duration,
+
+    /// Calls of other modules and host imports in the order they were made.
+    pub nested_calls: Vec<CallTrace>,
+}
+
+struct OpenCall {
+    trace: CallTrace,
+    started: Instant,
+}
+
+struct ActiveTrace {
+    config: TraceConfig,
+    open_calls: Vec<OpenCall>,
+    finished_calls: Vec<CallTrace>,
+}
+
+/// Records calls made on the current thread until the guard is finished or dropped.
+pub(crate) struct TraceGuard {
+    is_finished: bool,
+}
+
+impl TraceGuard {
+    pub(crate) fn activate(config: TraceConfig) -> Self {
+        let trace = ActiveTrace {
+            config,
+            open_calls: Vec::new(),
+            finished_calls: Vec::new(),
+        };
+        ACTIVE_TRACES.with(|traces| traces.borrow_mut().push(trace));
+
+        Self { is_finished: false }
+    }
+
+    /// Returns the trace of the first top-level call made while the guard was active.
+    pub(crate) fn finish(mut self) -> Option<CallTrace> {
+        self.is_finished = true;
+
+        let trace = ACTIVE_TRACES.with(|traces| traces.borrow_mut().pop())?;
+        trace.finished_calls.into_iter().next()
+    }
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        if !self.is_finished {
+            ACTIVE_TRACES.with(|traces| traces.borrow_mut().pop());
+        }
+    }
+}
+
+/// A call that is being recorded, it does nothing if tracing isn't active on the current thread.
+pub(crate) struct CallSpan {
+    is_active: bool,
+}
+
+impl CallSpan {
+    pub(crate) fn enter(
+        kind: CallKind,
+        module_name: &str,
+        function_name: &str,
+        arguments: &[IValue],
+    ) -> Self {
+        let is_active = ACTIVE_TRACES.with(|traces| {
+            let mut traces = traces.borrow_mut();
+            let trace = match traces.last_mut() {
+                Some(trace) => trace,
+                None => return false,
+            };
+
+            let arguments = truncate_values(arguments, &trace.config);
+            let call_trace = CallTrace {
+                kind,
+                module_name: module_name.to_string(),
+                function_name: function_name.to_string(),
+                arguments,
+                results: Vec::new(),
+                error: None,
+                duration: Duration::default(),
+                nested_calls: Vec::new(),
+            };
+            trace.open_calls.push(OpenCall {
+                trace: call_trace,
+                started: Instant::now(),
+            });
+
+            true
+        });
+
+        Self { is_active }
+    }
+
+    pub(crate) fn exit<E: Display>(self, result: Result<&[IValue], E>) {
+        if !self.is_active {
+            return;
+        }
+
+        ACTIVE_TRACES.with(|traces| {
+            let mut traces = traces.borrow_mut();
+            let trace = match traces.last_mut() {
+                Some(trace) => trace,
+                None => return,
+            };
+
+            let OpenCall {
+                trace: mut call_trace,
+                started,
+            } = match trace.open_calls.pop() {
+                Some(open_call) => open_call,
+                None => return,
+            };
+
+            call_trace.duration = started.elapsed();
+            match result {
+                Ok(results) => call_trace.results = truncate_values(results, &trace.config),
+                Err(e) => call_trace.error = Some(e.to_string()),
+            }
+
+            match trace.open_calls.last_mut() {
+                Some(parent) => parent.trace.nested_calls.push(call_trace),
+                None => trace.finished_calls.push(call_trace),
+            }
+        });
+    }
+}
+
+fn truncate_values(values: &[IValue], config: &TraceConfig) -> Vec<IValue> {
+    match config.max_value_len {
+        Some(max_len) => values
+            .iter()
+            .map(|value| truncate_value(value, max_len))
+            .collect(),
+        None => values.to_vec(),
+    }
+}
+
+fn truncate_value(value: &IValue, max_len: usize) -> IValue {
+    match value {
+        IValue::String(value) => IValue::String(value.chars().take(max_len).collect()),
+        IValue::ByteArray(value) => {
+            IValue::ByteArray(value.iter().take(max_len).cloned().collect())
+        }
+        IValue::Array(values) => IValue::Array(
+            values
+                .iter()
+                .take(max_len)
+                .map(|value| truncate_value(value, max_len))
+                .collect(),
+        ),
+        IValue::Record(fields) => {
+            let fields = fields
+                .iter()
+                .map(|field| truncate_value(field, max_len))
+                .collect();
+            // fields are mapped one to one, so they can't become empty
+            IValue::Record(NEVec::new(fields).unwrap())
+        }
+        value => value.clone(),
+    }
+}
@@ -16,20 +16,36 @@
 
 use super::IValue;
 use super::IType;
+use super::MRecordTypes;
 use crate::HostImportError;
+use crate::IntoHostImport;
+use crate::host_imports::HOST_IMPORTS_NAMESPACE;
+use crate::DiskQuota;
+use crate::VfsConfig;
+use crate::WasiCapabilities;
 
 use wasmer_wasi::WasiVersion;
 use wasmer_runtime::ImportObject;
+use wasmer_core::import::Namespace;
+use wasmer_core::typed_func::HostFunction;
+use wasmer_core::typed_func::HostFunctionKind;
+use wasmer_core::typed_func::WasmTypeList;
 use wasmer_core::vm::Ctx;
+use wasmer_core::Func;
 
+use std::fmt;
 use std::path::PathBuf;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::sync::Arc;
 
-pub type HostExportedFunc = Box<dyn Fn(&mut Ctx, Vec<IValue>) -> Option<IValue> + 'static>;
+pub type HostExportedFunc =
+    Box<dyn Fn(&mut Ctx, Vec<IValue>) -> Option<IValue> + Send + Sync + 'static>;
 
 pub struct HostImportDescriptor {
-    /// This closure will be invoked for corresponding import.
+    /// This closure will be invoked for corresponding import. If it fails with fail_host_import,
+    /// the calling module is trapped and the call fails with MError::HostImportFailed.
+    /// The same happens if it panics, but only when it's built with panic = "unwind".
     pub host_exported_func: HostExportedFunc,
 
     /// Type of the closure arguments.
@@ -38,9 +54,108 @@ pub struct HostImportDescriptor {
     /// Types of output of the closure.
     pub output_type: Option<IType>,
 
-    /// If Some, this closure is called with error when errors is encountered while lifting.
-    /// If None, panic will occur.
-    pub error_handler: Option<Box<dyn Fn(&HostImportError) -> Option<IValue> + 'static>>,
+    /// If Some, this closure is called with error when errors is encountered while lifting,
+    /// and its result is returned to the module.
+    /// If None, the module is trapped and the call fails with MError::HostImportFailed.
+    pub error_handler:
+        Option<Box<dyn Fn(&HostImportError) -> Option<IValue> + Send + Sync + 'static>>,
+
+    /// Record types defined by the host, ids of records in the argument and output types refer
+    /// to them. They are matched by name and fields against record types of the importing module
+    /// when it's loaded. If empty, ids of records refer to record types of the importing module.
+    pub record_types: MRecordTypes,
+}
+
+impl HostImportDescriptor {
+    /// Creates a descriptor from a closure, types of the import are inferred from the closure
+    /// arguments and result, which must implement HostImportValue. Values that can't be
+    /// converted trap the calling module.
+    pub fn from_fn<Args, Output>(func: impl IntoHostImport<Args, Output>) -> Self {
+        func.into_host_import()
+    }
+}
+
+/// Namespace and name under which modules import a host import.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HostImportName {
+    pub namespace: String,
+    pub name: String,
+}
+
+impl HostImportName {
+    pub fn new(namespace: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            name: name.into(),
+        }
+    }
+}
+
+/// Names without a namespace refer to imports from the `host` one.
+impl From<&str> for HostImportName {
+    fn from(name: &str) -> Self {
+        Self::new(HOST_IMPORTS_NAMESPACE, name)
+    }
+}
+
+impl From<String> for HostImportName {
+    fn from(name: String) -> Self {
+        Self::new(HOST_IMPORTS_NAMESPACE, name)
+    }
+}
+
+/// Imports from the `host` namespace are shown by their names only, others are prefixed
+/// with their namespace and a dot, the same way they are named in errors and statistics.
+impl fmt::Display for HostImportName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.namespace == HOST_IMPORTS_NAMESPACE {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}.{}", self.namespace, self.name)
+        }
+    }
+}
+
+/// Imports provided to a module as they are, without IT adapters. They are shared by all
+/// instances of the module and called from threads that use Marine, so only Send and Sync
+/// closures are accepted.
+#[derive(Default)]
+pub struct RawImports {
+    namespaces: HashMap<String, Namespace>,
+}
+
+impl RawImports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a function created from the closure the same way as by the func! macro of Wasmer,
+    /// a function previously added with the same namespace and name is replaced.
+    pub fn insert<F, Kind, Args, Rets>(
+        &mut self,
+        namespace: impl Into<String>,
+        name: impl Into<String>,
+        func: F,
+    ) where
+        F: HostFunction<Kind, Args, Rets> + Send + Sync + 'static,
+        Kind: HostFunctionKind,
+        Args: WasmTypeList + 'static,
+        Rets: WasmTypeList + 'static,
+    {
+        self.namespaces
+            .entry(namespace.into())
+            .or_insert_with(Namespace::new)
+            .insert(name, Func::new(func));
+    }
+
+    pub(crate) fn into_import_object(self) -> ImportObject {
+        let mut import_object = ImportObject::new();
+        for (namespace_name, namespace) in self.namespaces {
+            import_object.register(namespace_name, namespace);
+        }
+
+        import_object
+    }
 }
 
 pub struct MModuleConfig {
@@ -48,11 +163,13 @@ pub struct MModuleConfig {
     /// Each Wasm pages is 65536 bytes long.
     pub mem_pages_count: u32,
 
-    /// Import object that will be used in module instantiation process.
-    pub raw_imports: ImportObject,
+    /// Imports without IT adapters that will be used in module instantiation process.
+    pub raw_imports: RawImports,
 
     /// Imports from the host side that will be used in module instantiation process.
-    pub host_imports: HashMap<String, HostImportDescriptor>,
+    /// Their namespaces can't be `env`, `__marine`, start with `wasi_` or be names
+    /// of modules loaded into Marine.
+    pub host_imports: HashMap<HostImportName, HostImportDescriptor>,
 
     /// Desired WASI version.
     pub wasi_version: WasiVersion,
@@ -65,6 +182,45 @@ pub struct MModuleConfig {
 
     /// Mapping between paths.
     pub wasi_mapped_dirs: HashMap<String, PathBuf>,
+
+    /// If Some, the module sees a virtual filesystem instead of host directories, aliases
+    /// of mapped dirs and preopened files become its directories. Requires WASI snapshot1.
+    pub wasi_vfs: Option<VfsConfig>,
+
+    /// Quotas checked by writes of the module to files in host directories, a write fails
+    /// with ENOSPC if it exceeds any quota covering the file. Not applied to a virtual filesystem.
+    pub wasi_disk_quotas: Vec<Arc<DiskQuota>>,
+
+    /// Host paths of preopened files and mapped dirs that the module could only read,
+    /// WASI calls modifying files inside them fail with EROFS. If it isn't empty, paths leading
+    /// outside of preopened dirs through `..` or symlinks fail with ENOTCAPABLE as well.
+    /// Not applied to a virtual filesystem.
+    pub wasi_read_only_dirs: HashSet<PathBuf>,
+
+    /// Groups of WASI functions available to the module, calls of disabled ones fail with ENOSYS,
+    /// a call of disabled proc_exit traps the module.
+    pub wasi_capabilities: WasiCapabilities,
+
+    /// If true, the module will consume one unit of fuel per executed Wasm instruction,
+    /// and calls to it could be limited by Marine::call_with_fuel.
+    pub fuel_metering: bool,
+
+    /// If true, calls of the module could be interrupted by Marine::call_with_deadline.
+    pub interruptible: bool,
+
+    /// If true, linear memory and globals of the module are restored to their state
+    /// right after _start after every call of the module, including calls made by other modules.
+    pub reset_after_call: bool,
+
+    /// Number of pre-instantiated copies of the module, calls made concurrently from several
+    /// threads are handed to free copies, 0 is treated as 1. Copies of a pool with more than
+    /// one instance are always reset after call, so only stateless modules should be pooled.
+    pub pool_size: usize,
+
+    /// If true, NaNs produced by float operations of the module are canonicalized, and WASI
+    /// clocks and random source of the module are replaced with ones provided
+    /// by Marine::set_deterministic_env.
+    pub deterministic: bool,
 }
 
 impl Default for MModuleConfig {
@@ -73,12 +229,21 @@ impl Default for MModuleConfig {
         Self {
             // 65536*1600 ~ 100 Mb
             mem_pages_count: 1600,
-            raw_imports: ImportObject::new(),
+            raw_imports: RawImports::new(),
             host_imports: HashMap::new(),
             wasi_version: WasiVersion::Latest,
             wasi_envs: HashMap::new(),
             wasi_preopened_files: HashSet::new(),
             wasi_mapped_dirs: HashMap::new(),
+            wasi_vfs: None,
+            wasi_disk_quotas: Vec::new(),
+            wasi_read_only_dirs: HashSet::new(),
+            wasi_capabilities: WasiCapabilities::default(),
+            fuel_metering: false,
+            interruptible: false,
+            reset_after_call: false,
+            pool_size: 1,
+            deterministic: false,
         }
     }
 }
@@ -115,4 +280,58 @@ impl MModuleConfig {
         self.wasi_mapped_dirs = mapped_dirs;
         self
     }
+
+    #[allow(dead_code)]
+    pub fn with_wasi_vfs(mut self, vfs: VfsConfig) -> Self {
+        self.wasi_vfs = Some(vfs);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_wasi_disk_quota(mut self, quota: Arc<DiskQuota>) -> Self {
+        self.wasi_disk_quotas.push(quota);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_wasi_read_only_dirs(mut self, read_only_dirs: HashSet<PathBuf>) -> Self {
+        self.wasi_read_only_dirs = read_only_dirs;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_wasi_capabilities(mut self, capabilities: WasiCapabilities) -> Self {
+        self.wasi_capabilities = capabilities;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_fuel_metering(mut self, fuel_metering: bool) -> Self {
+        self.fuel_metering = fuel_metering;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_interruptible(mut self, interruptible: bool) -> Self {
+        self.interruptible = interruptible;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_reset_after_call(mut self, reset_after_call: bool) -> Self {
+        self.reset_after_call = reset_after_call;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
 }
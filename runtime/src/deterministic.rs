@@ -0,0 +1,274 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use wasmer_core::import::ImportObject;
+use wasmer_core::import::Namespace;
+use wasmer_core::vm::Ctx;
+use wasmer_runtime::func;
+
+use std::cell::RefCell;
+
+// the virtual clock advances by this amount of nanoseconds on every read,
+// so modules waiting for some time to pass don't spin forever
+const CLOCK_TICK_NS: u64 = 1_000;
+
+// WASI errno values returned by the virtual imports
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+
+// clock ids from realtime to thread cputime
+const CLOCKS_COUNT: i32 = 4;
+
+thread_local!(static ACTIVE_ENV: RefCell<VirtualEnv> = RefCell::new(VirtualEnv::new(&DeterministicEnv::default())));
+
+/// Time and entropy observed by modules loaded in the deterministic mode.
+/// Each call starts from this environment, so calls made with the same environment
+/// and the same module state give the same results.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeterministicEnv {
+    /// Initial value of all WASI clocks in nanoseconds.
+    pub timestamp_ns: u64,
+
+    /// Seed of the PRNG used instead of the WASI random source.
+    pub seed: u64,
+}
+
+struct VirtualEnv {
+    clock_ns: u64,
+    rng_state: u64,
+}
+
+impl VirtualEnv {
+    fn new(env: &DeterministicEnv) -> Self {
+        Self {
+            clock_ns: env.timestamp_ns,
+            rng_state: env.seed,
+        }
+    }
+
+    fn read_clock(&mut self) -> u64 {
+        let time = self.clock_ns;
+        self.clock_ns = self.clock_ns.wrapping_add(CLOCK_TICK_NS);
+        time
+    }
+
+    // SplitMix64, it's enough for modules that need random values, but shouldn't be used
+    // for cryptography since the seed is known to every peer replaying the call
+    fn next_random(&mut self) -> u64 {
+        self.rng_state = self.rng_state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Makes an environment active for the current thread until the guard is dropped.
+pub(crate) struct DeterministicEnvGuard {
+    previous: Option<VirtualEnv>,
+}
+
+impl DeterministicEnvGuard {
+    pub(crate) fn activate(env: &DeterministicEnv) -> Self {
+        let previous = ACTIVE_ENV.with(|active_env| active_env.replace(VirtualEnv::new(env)));
+
+        Self {
+            previous: Some(previous),
+        }
+    }
+}
+
+impl Drop for DeterministicEnvGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            ACTIVE_ENV.with(|active_env| active_env.replace(previous));
+        }
+    }
+}
+
+/// Creates WASI clock and random imports that use the active deterministic environment.
+pub(crate) fn create_deterministic_wasi_imports(wasi_import_object: &ImportObject) -> ImportObject {
+    let clock_res_get = |ctx: &mut Ctx, clock_id: i32, resolution_ptr: i32| -> i32 {
+        if !(0..CLOCKS_COUNT).contains(&clock_id) {
+            return ERRNO_INVAL;
+        }
+
+        write_to_memory(ctx, resolution_ptr, &CLOCK_TICK_NS.to_le_bytes())
+    };
+
+    let clock_time_get = |ctx: &mut Ctx, clock_id: i32, _precision: i64, time_ptr: i32| -> i32 {
+        if !(0..CLOCKS_COUNT).contains(&clock_id) {
+            return ERRNO_INVAL;
+        }
+
+        write_to_memory(ctx, time_ptr, &read_clock().to_le_bytes())
+    };
+
+    let random_get = |ctx: &mut Ctx, buf_ptr: i32, buf_len: i32| -> i32 {
+        let buf_len = buf_len as u32 as usize;
+        let mut buf = Vec::with_capacity(buf_len + 8);
+        ACTIVE_ENV.with(|env| {
+            let mut env = env.borrow_mut();
+            while buf.len() < buf_len {
+                buf.extend_from_slice(&env.next_random().to_le_bytes());
+            }
+        });
+        buf.truncate(buf_len);
+
+        write_to_memory(ctx, buf_ptr, &buf)
+    };
+
+    let mut import_object = ImportObject::new();
+    for &wasi_namespace in crate::misc::WASI_NAMESPACES.iter() {
+        if !wasi_import_object.contains_namespace(wasi_namespace) {
+            continue;
+        }
+
+        let mut namespace = Namespace::new();
+        namespace.insert("clock_res_get", func!(clock_res_get));
+        namespace.insert("clock_time_get", func!(clock_time_get));
+        namespace.insert("random_get", func!(random_get));
+        import_object.register(wasi_namespace, namespace);
+    }
+
+    import_object
+}
+
+/// Reads the clock of the active environment, it advances on every read.
+pub(crate) fn read_clock() -> u64 {
+    ACTIVE_ENV.with(|env| env.borrow_mut().read_clock())
+}
+
+fn write_to_memory(ctx: &mut Ctx, offset: i32, bytes: &[u8]) -> i32 {
+    let offset = offset as u32 as usize;
+    let view = ctx.memory(0).view::<u8>();
+    let cells = match view.get(offset..offset + bytes.len()) {
+        Some(cells) => cells,
+        None => return ERRNO_FAULT,
+    };
+
+    for (cell, &byte) in cells.iter().zip(bytes) {
+        cell.set(byte);
+    }
+
+    ERRNO_SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parity_wasm::elements::*;
+
+    // a module with functions that return the current time and a random value obtained from WASI
+    fn wasi_consumer() -> Vec<u8> {
+        let time_type = FunctionType::new(
+            vec![ValueType::I32, ValueType::I64, ValueType::I32],
+            Some(ValueType::I32),
+        );
+        let random_type =
+            FunctionType::new(vec![ValueType::I32, ValueType::I32], Some(ValueType::I32));
+        let getter_type = FunctionType::new(vec![], Some(ValueType::I64));
+
+        let import = |name: &str, type_index| {
+            ImportEntry::new(
+                String::from("wasi_snapshot_preview1"),
+                name.to_string(),
+                External::Function(type_index),
+            )
+        };
+        let getter = |mut instructions: Vec<Instruction>| {
+            // the result is written to memory at offset 16 and then loaded from it
+            instructions.extend(vec![
+                Instruction::Drop,
+                Instruction::I32Const(16),
+                Instruction::I64Load(3, 0),
+                Instruction::End,
+            ]);
+            FuncBody::new(vec![], Instructions::new(instructions))
+        };
+
+        let module = Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![
+                Type::Function(time_type),
+                Type::Function(random_type),
+                Type::Function(getter_type),
+            ])),
+            Section::Import(ImportSection::with_entries(vec![
+                import("clock_time_get", 0),
+                import("random_get", 1),
+            ])),
+            Section::Function(FunctionSection::with_entries(vec![
+                Func::new(2),
+                Func::new(2),
+            ])),
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None)])),
+            Section::Export(ExportSection::with_entries(vec![
+                ExportEntry::new(String::from("now"), Internal::Function(2)),
+                ExportEntry::new(String::from("random"), Internal::Function(3)),
+            ])),
+            Section::Code(CodeSection::with_bodies(vec![
+                getter(vec![
+                    Instruction::I32Const(0),
+                    Instruction::I64Const(0),
+                    Instruction::I32Const(16),
+                    Instruction::Call(0),
+                ]),
+                getter(vec![
+                    Instruction::I32Const(16),
+                    Instruction::I32Const(8),
+                    Instruction::Call(1),
+                ]),
+            ])),
+        ]);
+
+        parity_wasm::serialize(module).unwrap()
+    }
+
+    #[test]
+    fn wasi_imports_use_active_env() {
+        let mut import_object = wasmer_wasi::generate_import_object_for_version(
+            wasmer_wasi::WasiVersion::Latest,
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        import_object.extend(create_deterministic_wasi_imports(&import_object));
+        let instance = wasmer_runtime::instantiate(&wasi_consumer(), &import_object).unwrap();
+
+        let now: wasmer_runtime::Func<'_, (), i64> = instance.exports.get("now").unwrap();
+        let random: wasmer_runtime::Func<'_, (), i64> = instance.exports.get("random").unwrap();
+        let env = DeterministicEnv {
+            timestamp_ns: 42,
+            seed: 7,
+        };
+
+        let guard = DeterministicEnvGuard::activate(&env);
+        assert_eq!(now.call().unwrap(), 42);
+        assert_eq!(now.call().unwrap(), 42 + CLOCK_TICK_NS as i64);
+        let first_random = random.call().unwrap();
+        assert_ne!(random.call().unwrap(), first_random);
+        drop(guard);
+
+        // a replayed call observes the same time and random values
+        let _guard = DeterministicEnvGuard::activate(&env);
+        assert_eq!(now.call().unwrap(), 42);
+        assert_eq!(random.call().unwrap(), first_random);
+    }
+}
@@ -0,0 +1,540 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use wasmer_core::import::ImportObject;
+use wasmer_core::import::Namespace;
+use wasmer_core::typed_func::DynamicFunc;
+use wasmer_core::types::FuncSig;
+use wasmer_core::types::Type as WType;
+use wasmer_core::types::Value as WValue;
+use wasmer_core::vm::Ctx;
+use wasmer_wasi::state::get_wasi_state;
+use wasmer_wasi::state::Kind;
+use wasmer_wasi::types::*;
+use wasmer_wasi::WasiVersion;
+
+use std::collections::HashMap;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+type Errno = __wasi_errno_t;
+
+// size of __wasi_ciovec_t: a pointer to a buffer and its length
+const CIOVEC_SIZE: usize = 8;
+
+// directories of a quota are walked by refused writes at most this often
+const DIRS_RECOUNT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limit of disk space taken by files in host directories available to modules through WASI,
+/// writes and resizes of files beyond it fail with ENOSPC. The same quota could be provided
+/// to several modules, then they share it.
+#[derive(Debug)]
+pub struct DiskQuota {
+    max_size: u64,
+    dirs: Vec<PathBuf>,
+    usage: Mutex<QuotaUsage>,
+}
+
+#[derive(Debug)]
+struct QuotaUsage {
+    /// Bytes taken by accounted files, it could be greater than the actual value,
+    /// because files removed not through the quota are noticed only on recount.
+    used: u64,
+
+    /// Bytes charged to every file written by holders of the quota,
+    /// None if all files of the directories are accounted.
+    charged_files: Option<HashMap<PathBuf, u64>>,
+
+    /// Sizes of files written by holders of the quota as they were accounted the last time,
+    /// files could be changed meanwhile not through the quota, e.g. truncated on opening.
+    /// It's used only if all files of the directories are accounted.
+    written_sizes: HashMap<PathBuf, u64>,
+
+    /// When the usage was recounted the last time.
+    recounted_at: Instant,
+}
+
+impl DiskQuota {
+    /// Creates a quota for all files in the provided directories, including the already existing ones.
+    pub fn for_dirs(max_size: u64, dirs: Vec<PathBuf>) -> Self {
+        let used = dirs.iter().map(|dir| dir_size(dir)).sum();
+        let usage = QuotaUsage {
+            used,
+            charged_files: None,
+            written_sizes: HashMap::new(),
+            recounted_at: Instant::now(),
+        };
+
+        Self {
+            max_size,
+            dirs,
+            usage: Mutex::new(usage),
+        }
+    }
+
+    /// Creates a quota for bytes that modules holding it have written to files
+    /// in the provided directories, other files of the directories aren't accounted.
+    pub fn for_writes(max_size: u64, dirs: Vec<PathBuf>) -> Self {
+        let usage = QuotaUsage {
+            used: 0,
+            charged_files: Some(HashMap::new()),
+            written_sizes: HashMap::new(),
+            recounted_at: Instant::now(),
+        };
+
+        Self {
+            max_size,
+            dirs,
+            usage: Mutex::new(usage),
+        }
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// Returns the number of bytes currently taken by accounted files.
+    pub fn usage(&self) -> u64 {
+        let mut usage = self.usage.lock().unwrap();
+        usage.recount(&self.dirs);
+        usage.used
+    }
+
+    fn covers(&self, path: &Path) -> bool {
+        self.dirs.iter().any(|dir| path.starts_with(dir))
+    }
+
+    /// Checks that the file could be resized, the usage is recounted before refusing,
+    /// since some files could have been removed. Quotas for all files of directories
+    /// walk them to recount, so they do it at most once per DIRS_RECOUNT_INTERVAL.
+    fn has_space_for(&self, path: &Path, old_size: u64, new_size: u64) -> bool {
+        let mut usage = self.usage.lock().unwrap();
+        usage.reconcile(path, old_size);
+
+        let growth = new_size.saturating_sub(old_size);
+        if usage.used.saturating_add(growth) <= self.max_size {
+            return true;
+        }
+
+        if usage.charged_files.is_none() && usage.recounted_at.elapsed() < DIRS_RECOUNT_INTERVAL {
+            return false;
+        }
+        usage.recount(&self.dirs);
+        usage.used.saturating_add(growth) <= self.max_size
+    }
+
+    fn charge(&self, path: &Path, old_size: u64, new_size: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = &mut *usage;
+
+        match &mut usage.charged_files {
+            None => {
+                usage.used = (usage.used + new_size).saturating_sub(old_size);
+                usage.written_sizes.insert(path.to_path_buf(), new_size);
+            }
+            Some(charged_files) if new_size >= old_size => {
+                *charged_files.entry(path.to_path_buf()).or_default() += new_size - old_size;
+                usage.used += new_size - old_size;
+            }
+            Some(charged_files) => {
+                if let Some(charged) = charged_files.get_mut(path) {
+                    let released = charged.saturating_sub(new_size);
+                    *charged -= released;
+                    usage.used = usage.used.saturating_sub(released);
+                }
+            }
+        }
+    }
+}
+
+impl QuotaUsage {
+    /// Accounts changes of the file made not through the quota since it was written
+    /// by a holder of the quota the last time.
+    fn reconcile(&mut self, path: &Path, size: u64) {
+        match &mut self.charged_files {
+            None => {
+                if let Some(written_size) = self.written_sizes.insert(path.to_path_buf(), size) {
+                    self.used = (self.used + size).saturating_sub(written_size);
+                }
+            }
+            // a file can't be charged for more than its current size
+            Some(charged_files) => {
+                if let Some(charged) = charged_files.get_mut(path) {
+                    let released = charged.saturating_sub(size);
+                    *charged -= released;
+                    self.used = self.used.saturating_sub(released);
+                }
+            }
+        }
+    }
+
+    fn recount(&mut self, dirs: &[PathBuf]) {
+        self.recounted_at = Instant::now();
+        self.used = match &mut self.charged_files {
+            None => {
+                // sizes of written files are counted again, so they are remembered as they are now
+                self.written_sizes.retain(|path, written_size| {
+                    match std::fs::symlink_metadata(path) {
+                        Ok(metadata) => {
+                            *written_size = metadata.len();
+                            true
+                        }
+                        Err(_) => false,
+                    }
+                });
+                dirs.iter().map(|dir| dir_size(dir)).sum()
+            }
+            Some(charged_files) => {
+                // a file can't be charged for more than its current size
+                charged_files.retain(|path, charged| {
+                    let size = std::fs::symlink_metadata(path).map_or(0, |m| m.len());
+                    *charged = (*charged).min(size);
+                    *charged > 0
+                });
+                charged_files.values().sum()
+            }
+        };
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    // metadata of a dir entry doesn't follow symlinks, so files outside of the dir aren't counted
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// WASI syscalls that change sizes of files, they are checked against quotas covering a file.
+struct QuotaSyscalls {
+    quotas: Vec<Arc<DiskQuota>>,
+}
+
+impl QuotaSyscalls {
+    fn fd_write(
+        &self,
+        ctx: &mut Ctx,
+        fd: u32,
+        iovs: u32,
+        iovs_len: u32,
+        nwritten: u32,
+    ) -> Result<(), Errno> {
+        let data = read_iovecs(ctx, iovs, iovs_len)?;
+        let written = self.write(ctx, fd, None, &data)?;
+        write_u32(ctx, nwritten, written)
+    }
+
+    fn fd_pwrite(
+        &self,
+        ctx: &mut Ctx,
+        fd: u32,
+        iovs: u32,
+        iovs_len: u32,
+        offset: u64,
+        nwritten: u32,
+    ) -> Result<(), Errno> {
+        let data = read_iovecs(ctx, iovs, iovs_len)?;
+        let written = self.write(ctx, fd, Some(offset), &data)?;
+        write_u32(ctx, nwritten, written)
+    }
+
+    fn fd_allocate(&self, ctx: &mut Ctx, fd: u32, offset: u64, len: u64) -> Result<(), Errno> {
+        let end = offset.checked_add(len).ok_or(__WASI_EINVAL)?;
+        // allocation never shrinks a file
+        self.resize(ctx, fd, __WASI_RIGHT_FD_ALLOCATE, |size| size.max(end))
+    }
+
+    fn fd_filestat_set_size(&self, ctx: &mut Ctx, fd: u32, size: u64) -> Result<(), Errno> {
+        self.resize(ctx, fd, __WASI_RIGHT_FD_FILESTAT_SET_SIZE, |_| size)
+    }
+
+    fn write(
+        &self,
+        ctx: &mut Ctx,
+        fd: u32,
+        offset: Option<u64>,
+        data: &[u8],
+    ) -> Result<u32, Errno> {
+        let state = unsafe { get_wasi_state(ctx) };
+
+        let std_output = match fd {
+            __WASI_STDIN_FILENO => return Err(__WASI_EINVAL),
+            __WASI_STDOUT_FILENO => Some(state.fs.stdout_mut()),
+            __WASI_STDERR_FILENO => Some(state.fs.stderr_mut()),
+            _ => None,
+        };
+        if let Some(output) = std_output {
+            let output = output.map_err(|e| e.into_wasi_err())?;
+            let output = output.as_mut().ok_or(__WASI_EBADF)?;
+            output.write_all(data).map_err(|_| __WASI_EIO)?;
+            let _ = output.flush();
+            return Ok(data.len() as u32);
+        }
+
+        let fd_entry = state.fs.fd_map.get_mut(&fd).ok_or(__WASI_EBADF)?;
+        let required_rights = match offset {
+            Some(_) => __WASI_RIGHT_FD_WRITE | __WASI_RIGHT_FD_SEEK,
+            None => __WASI_RIGHT_FD_WRITE,
+        };
+        if fd_entry.rights & required_rights != required_rights {
+            return Err(__WASI_EACCES);
+        }
+
+        let is_append = fd_entry.flags & __WASI_FDFLAG_APPEND != 0;
+        let mut position = offset.unwrap_or(fd_entry.offset);
+        let inode = &mut state.fs.inodes[fd_entry.inode];
+        match &mut inode.kind {
+            Kind::File {
+                handle: Some(handle),
+                path,
+                ..
+            } => {
+                let old_size = handle.size();
+                // files are opened by wasmer-wasi in the append mode of the host, so all writes
+                // go to their end, pwrite included, as Linux does for such files
+                if is_append {
+                    position = old_size;
+                }
+                let end = position.saturating_add(data.len() as u64);
+                self.check_space(path, old_size, old_size.max(end))?;
+
+                let result = handle
+                    .seek(SeekFrom::Start(position))
+                    .and_then(|_| handle.write_all(data))
+                    .and_then(|_| handle.flush());
+                // a failed write could also change the size of the file
+                let new_size = handle.size();
+                self.charge(path, old_size, new_size);
+                inode.stat.st_size = new_size;
+                result.map_err(|_| __WASI_EIO)?;
+            }
+            Kind::File { handle: None, .. } | Kind::Symlink { .. } => return Err(__WASI_EINVAL),
+            Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+            Kind::Buffer { buffer } => {
+                let mut buffer = buffer.get_mut(position as usize..).ok_or(__WASI_EINVAL)?;
+                buffer.write_all(data).map_err(|_| __WASI_EIO)?;
+            }
+        }
+
+        if offset.is_none() {
+            fd_entry.offset = position + data.len() as u64;
+        }
+
+        Ok(data.len() as u32)
+    }
+
+    fn resize(
+        &self,
+        ctx: &mut Ctx,
+        fd: u32,
+        required_right: __wasi_rights_t,
+        new_size: impl FnOnce(u64) -> u64,
+    ) -> Result<(), Errno> {
+        let state = unsafe { get_wasi_state(ctx) };
+        let fd_entry = state.fs.fd_map.get(&fd).ok_or(__WASI_EBADF)?;
+        if fd_entry.rights & required_right == 0 {
+            return Err(__WASI_EACCES);
+        }
+
+        let inode = &mut state.fs.inodes[fd_entry.inode];
+        let new_size = match &mut inode.kind {
+            Kind::File {
+                handle: Some(handle),
+                path,
+                ..
+            } => {
+                let old_size = handle.size();
+                let new_size = new_size(old_size);
+                self.check_space(path, old_size, new_size)?;
+
+                handle.set_len(new_size).map_err(|e| e.into_wasi_err())?;
+                self.charge(path, old_size, new_size);
+                new_size
+            }
+            Kind::File { handle: None, .. } | Kind::Symlink { .. } => return Err(__WASI_EBADF),
+            Kind::Dir { .. } | Kind::Root { .. } => return Err(__WASI_EISDIR),
+            Kind::Buffer { buffer } => {
+                let new_size = new_size(buffer.len() as u64);
+                buffer.resize(new_size as usize, 0);
+                new_size
+            }
+        };
+        inode.stat.st_size = new_size;
+
+        Ok(())
+    }
+
+    fn check_space(&self, path: &Path, old_size: u64, new_size: u64) -> Result<(), Errno> {
+        let has_space = self
+            .quotas
+            .iter()
+            .filter(|quota| quota.covers(path))
+            .all(|quota| quota.has_space_for(path, old_size, new_size));
+
+        if has_space {
+            Ok(())
+        } else {
+            Err(__WASI_ENOSPC)
+        }
+    }
+
+    fn charge(&self, path: &Path, old_size: u64, new_size: u64) {
+        self.quotas
+            .iter()
+            .filter(|quota| quota.covers(path))
+            .for_each(|quota| quota.charge(path, old_size, new_size));
+    }
+}
+
+/// Creates WASI imports that check writes to files against the provided quotas.
+pub(crate) fn create_disk_quota_wasi_imports(
+    wasi_version: WasiVersion,
+    quotas: &[Arc<DiskQuota>],
+) -> ImportObject {
+    let syscalls = Arc::new(QuotaSyscalls {
+        quotas: quotas.to_vec(),
+    });
+
+    // imports are dynamic functions, because typed ones leak their captured environment
+    let import =
+        |params: Vec<WType>,
+         syscall: fn(&QuotaSyscalls, &mut Ctx, &[WValue]) -> Result<(), Errno>| {
+            let syscalls = syscalls.clone();
+            let signature = FuncSig::new(params, vec![WType::I32]);
+            let func = move |ctx: &mut Ctx, args: &[WValue]| -> Vec<WValue> {
+                let errno = match syscall(&syscalls, ctx, args) {
+                    Ok(()) => __WASI_ESUCCESS,
+                    Err(errno) => errno,
+                };
+                vec![WValue::I32(errno as i32)]
+            };
+            DynamicFunc::new(Arc::new(signature), func)
+        };
+
+    let mut namespace = Namespace::new();
+    namespace.insert(
+        "fd_write",
+        import(vec![WType::I32; 4], |syscalls, ctx, args| {
+            syscalls.fd_write(
+                ctx,
+                u32_arg(args, 0),
+                u32_arg(args, 1),
+                u32_arg(args, 2),
+                u32_arg(args, 3),
+            )
+        }),
+    );
+    namespace.insert(
+        "fd_pwrite",
+        import(
+            vec![WType::I32, WType::I32, WType::I32, WType::I64, WType::I32],
+            |syscalls, ctx, args| {
+                syscalls.fd_pwrite(
+                    ctx,
+                    u32_arg(args, 0),
+                    u32_arg(args, 1),
+                    u32_arg(args, 2),
+                    u64_arg(args, 3),
+                    u32_arg(args, 4),
+                )
+            },
+        ),
+    );
+    namespace.insert(
+        "fd_allocate",
+        import(
+            vec![WType::I32, WType::I64, WType::I64],
+            |syscalls, ctx, args| {
+                syscalls.fd_allocate(ctx, u32_arg(args, 0), u64_arg(args, 1), u64_arg(args, 2))
+            },
+        ),
+    );
+    namespace.insert(
+        "fd_filestat_set_size",
+        import(vec![WType::I32, WType::I64], |syscalls, ctx, args| {
+            syscalls.fd_filestat_set_size(ctx, u32_arg(args, 0), u64_arg(args, 1))
+        }),
+    );
+
+    let wasi_namespace = match wasi_version {
+        WasiVersion::Snapshot0 => "wasi_unstable",
+        _ => "wasi_snapshot_preview1",
+    };
+    let mut import_object = ImportObject::new();
+    import_object.register(wasi_namespace, namespace);
+
+    import_object
+}
+
+// arguments are already checked by Wasmer against the signature of an import
+fn u32_arg(args: &[WValue], index: usize) -> u32 {
+    match args.get(index) {
+        Some(WValue::I32(value)) => *value as u32,
+        _ => 0,
+    }
+}
+
+fn u64_arg(args: &[WValue], index: usize) -> u64 {
+    match args.get(index) {
+        Some(WValue::I64(value)) => *value as u64,
+        _ => 0,
+    }
+}
+
+fn read_iovecs(ctx: &Ctx, iovs: u32, iovs_len: u32) -> Result<Vec<u8>, Errno> {
+    let view = ctx.memory(0).view::<u8>();
+    let read = |offset: usize, len: usize| -> Result<Vec<u8>, Errno> {
+        let cells = view.get(offset..offset + len).ok_or(__WASI_EFAULT)?;
+        Ok(cells.iter().map(|cell| cell.get()).collect())
+    };
+
+    let mut data = Vec::new();
+    for index in 0..iovs_len as usize {
+        let iovec = read(iovs as usize + index * CIOVEC_SIZE, CIOVEC_SIZE)?;
+        let buf = u32::from_le_bytes([iovec[0], iovec[1], iovec[2], iovec[3]]);
+        let buf_len = u32::from_le_bytes([iovec[4], iovec[5], iovec[6], iovec[7]]);
+        data.extend(read(buf as usize, buf_len as usize)?);
+    }
+
+    Ok(data)
+}
+
+fn write_u32(ctx: &Ctx, offset: u32, value: u32) -> Result<(), Errno> {
+    let offset = offset as usize;
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view.get(offset..offset + 4).ok_or(__WASI_EFAULT)?;
+    for (cell, byte) in cells.iter().zip(value.to_le_bytes().iter()) {
+        cell.set(*byte);
+    }
+
+    Ok(())
+}
@@ -15,14 +15,33 @@
  */
 
 use super::*;
+use crate::call_trace::TraceGuard;
+use crate::deterministic::DeterministicEnvGuard;
+use crate::fuel::FuelCounter;
+use crate::host_imports::take_host_import_failure;
+use crate::interrupt::DeadlineGuard;
+use crate::module::Linker;
+use crate::module::take_link_failure;
 use crate::module::MModule;
+use crate::module::MModulePool;
 use crate::module::MRecordTypes;
 
 use serde::Serialize;
 
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::rc::Rc;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+/// Name of a function that could be exported by a module to provide its state to
+/// a new version of the module on upgrade.
+pub const STATE_EXPORT_FUNC_NAME: &str = "marine_export_state";
+
+/// Name of a function that could be exported by a module to receive the state
+/// of the previous version of the module on upgrade.
+pub const STATE_MIGRATION_FUNC_NAME: &str = "marine_migrate_state";
 
 /// Represent Marine module interface.
 #[derive(PartialEq, Eq, Debug, Clone, Serialize)]
@@ -31,30 +50,182 @@ pub struct MModuleInterface<'a> {
     pub function_signatures: Vec<MFunctionSignature>,
 }
 
+/// WASI state of a loaded module, the module can't be called while it's held.
+pub struct ModuleWasiState<'a> {
+    module: MutexGuard<'a, MModule>,
+}
+
+impl Deref for ModuleWasiState<'_> {
+    type Target = wasmer_wasi::state::WasiState;
+
+    fn deref(&self) -> &Self::Target {
+        self.module.get_wasi_state()
+    }
+}
+
 /// The base struct of Marine, the Fluence compute runtime.
 pub struct Marine {
-    // set of modules registered inside Marine
-    modules: HashMap<String, MModule>,
+    // set of modules registered inside Marine, each one is a pool of its instances
+    modules: HashMap<String, Arc<MModulePool>>,
+
+    // resolves imports of modules to exports of other modules
+    linker: Linker,
+
+    // fuel consumed by modules with enabled fuel metering
+    fuel_counter: Arc<FuelCounter>,
+
+    // cache of compiled modules, modules are compiled on every load without it
+    module_cache: Option<ModuleCache>,
+
+    // if Some, every call is traced with this config
+    call_tracing: Option<TraceConfig>,
+
+    // trace of the last call made with enabled tracing
+    last_call_trace: Mutex<Option<CallTrace>>,
+
+    // time and entropy observed by deterministic modules, each call starts from it
+    deterministic_env: DeterministicEnv,
 }
 
 impl Marine {
     pub fn new() -> Self {
         Self {
             modules: HashMap::new(),
+            linker: Linker::new(),
+            fuel_counter: Arc::new(FuelCounter::new()),
+            module_cache: None,
+            call_tracing: None,
+            last_call_trace: Mutex::new(None),
+            deterministic_env: DeterministicEnv::default(),
+        }
+    }
+
+    /// Create Marine that stores compiled modules in the provided cache
+    /// and loads them from it instead of compiling again.
+    pub fn with_module_cache(module_cache: ModuleCache) -> Self {
+        Self {
+            module_cache: Some(module_cache),
+            ..Self::new()
         }
     }
 
     /// Invoke a function of a module inside Marine by given function name with given arguments.
+    /// Calls could be made concurrently from several threads, they run in parallel on
+    /// different instances of pooled modules and wait for each other otherwise.
     pub fn call<MN: AsRef<str>, FN: AsRef<str>>(
-        &mut self,
+        &self,
         module_name: MN,
         func_name: FN,
         arguments: &[IValue],
     ) -> MResult<Vec<IValue>> {
-        self.modules.get_mut(module_name.as_ref()).map_or_else(
+        // a failure of a previous call could be already handled by the module itself
+        take_link_failure();
+        take_host_import_failure();
+
+        let trace_guard = self.call_tracing.clone().map(TraceGuard::activate);
+        let _env_guard = DeterministicEnvGuard::activate(&self.deterministic_env);
+        let result = self.modules.get(module_name.as_ref()).map_or_else(
             || Err(MError::NoSuchModule(module_name.as_ref().to_string())),
-            |module| module.call(module_name.as_ref(), func_name.as_ref(), arguments),
-        )
+            |module| module.call(func_name.as_ref(), arguments),
+        );
+        if let Some(trace_guard) = trace_guard {
+            *self.last_call_trace.lock().unwrap() = trace_guard.finish();
+        }
+
+        let result = match (result, take_host_import_failure()) {
+            (Err(_), Some(failure)) => Err(MError::HostImportFailed {
+                import_name: failure.import_name,
+                error: failure.error,
+            }),
+            (result, _) => result,
+        };
+
+        let is_call_failed = result.is_err();
+        let result = self.check_link_failure(result);
+        if result.is_err() && !is_call_failed {
+            // the call of an unresolved import traps the module without failing its call
+            if let Some(module) = self.modules.get(module_name.as_ref()) {
+                module.record_trap();
+            }
+        }
+
+        result
+    }
+
+    /// Set time and random seed observed by modules loaded in the deterministic mode
+    /// in subsequent calls. Every call starts from this environment, so a call replayed
+    /// with the same environment observes the same time and random values.
+    pub fn set_deterministic_env(&mut self, env: DeterministicEnv) {
+        self.deterministic_env = env;
+    }
+
+    /// Enable tracing of calls: every call records a tree of calls of modules and host imports
+    /// made by it, the trace of the last call could be obtained by take_last_call_trace.
+    pub fn enable_call_tracing(&mut self, config: TraceConfig) {
+        self.call_tracing = Some(config);
+    }
+
+    pub fn disable_call_tracing(&mut self) {
+        self.call_tracing = None;
+        *self.last_call_trace.get_mut().unwrap() = None;
+    }
+
+    /// Return the trace of the last call made with enabled tracing, it's None if the call
+    /// failed before reaching any module.
+    pub fn take_last_call_trace(&mut self) -> Option<CallTrace> {
+        self.last_call_trace.get_mut().unwrap().take()
+    }
+
+    /// Invoke a function of a module inside Marine with the limited amount of fuel.
+    /// Fuel is consumed only by modules loaded with enabled fuel metering (including modules
+    /// called by this one), one unit per executed Wasm instruction.
+    /// Returns results of the call along with the amount of consumed fuel.
+    pub fn call_with_fuel<MN: AsRef<str>, FN: AsRef<str>>(
+        &mut self,
+        module_name: MN,
+        func_name: FN,
+        arguments: &[IValue],
+        fuel: u64,
+    ) -> MResult<(Vec<IValue>, u64)> {
+        let module_name = module_name.as_ref();
+
+        self.fuel_counter.reset(Some(fuel));
+        let result = self.call(module_name, func_name, arguments);
+        let consumed_fuel = self.fuel_counter.consumed();
+        let is_exhausted = self.fuel_counter.is_exhausted();
+        self.fuel_counter.reset(None);
+
+        match result {
+            Ok(result) => Ok((result, consumed_fuel)),
+            Err(_) if is_exhausted => Err(MError::OutOfFuel {
+                module: module_name.to_string(),
+                fuel,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Invoke a function of a module inside Marine, that will be interrupted when the provided
+    /// deadline is reached. Wasm code is interrupted only in modules loaded as interruptible,
+    /// host imports could check it with is_current_call_interrupted.
+    pub fn call_with_deadline<MN: AsRef<str>, FN: AsRef<str>>(
+        &self,
+        module_name: MN,
+        func_name: FN,
+        arguments: &[IValue],
+        deadline: impl Into<CallDeadline>,
+    ) -> MResult<Vec<IValue>> {
+        let module_name = module_name.as_ref();
+
+        let deadline_guard = DeadlineGuard::activate(deadline.into());
+        let result = self.call(module_name, func_name, arguments);
+
+        match result {
+            Err(_) if deadline_guard.is_reached() => {
+                Err(MError::CallTimedOut(module_name.to_string()))
+            }
+            result => result,
+        }
     }
 
     /// Load a new module inside Marine.
@@ -73,12 +244,12 @@ impl Marine {
         wasm_bytes: &[u8],
         config: MModuleConfig,
     ) -> MResult<()> {
-        let _prepared_wasm_bytes = crate::misc::prepare_module(wasm_bytes, config.mem_pages_count)?;
-
-        let module = MModule::new(&name, &wasm_bytes, config, &self.modules)?;
+        let module = Arc::new(self.create_module(&name, wasm_bytes, config)?);
 
         match self.modules.entry(name) {
             Entry::Vacant(entry) => {
+                self.linker
+                    .register_module(entry.key().clone(), module.clone());
                 entry.insert(module);
                 Ok(())
             }
@@ -86,22 +257,237 @@ impl Marine {
         }
     }
 
-    /// Unload previously loaded module.
+    /// Replace code of a loaded module keeping its name, modules that import functions of
+    /// the old version are linked with the new one. If the new module exports
+    /// STATE_MIGRATION_FUNC_NAME and the old one exports STATE_EXPORT_FUNC_NAME, the first one
+    /// is called with results of the second one. The old module stays loaded if any of these
+    /// steps fails.
+    pub fn upgrade_module<S: AsRef<str>>(
+        &mut self,
+        name: S,
+        new_wasm_bytes: &[u8],
+        config: MModuleConfig,
+    ) -> MResult<()> {
+        let name = name.as_ref();
+        if !self.modules.contains_key(name) {
+            return Err(MError::NoSuchModule(name.to_string()));
+        }
+
+        let new_module = Arc::new(self.create_module(name, new_wasm_bytes, config)?);
+
+        // dependents must be able to link with the new module
+        for dependent in self.dependents(name) {
+            let dependent_module = self.modules[&dependent].primary();
+            let unresolved_function =
+                dependent_module.find_unresolved_import(name, &new_module.primary());
+
+            if let Some(function_name) = unresolved_function {
+                return Err(MError::UnresolvedImport {
+                    namespace: name.to_string(),
+                    function_name: function_name.to_string(),
+                });
+            }
+        }
+
+        // the state is migrated only if both versions of the module support it
+        if self.modules[name].has_function(STATE_EXPORT_FUNC_NAME)
+            && new_module.has_function(STATE_MIGRATION_FUNC_NAME)
+        {
+            let state = self.call(name, STATE_EXPORT_FUNC_NAME, &[])?;
+
+            take_link_failure();
+            let result = new_module.call(STATE_MIGRATION_FUNC_NAME, &state);
+            self.check_link_failure(result)?;
+        }
+
+        // the old module is dropped here, so imports resolved to it will be resolved again
+        self.linker
+            .register_module(name.to_string(), new_module.clone());
+        self.modules.insert(name.to_string(), new_module);
+
+        Ok(())
+    }
+
+    /// Capture linear memory, mutable globals and WASI state of a loaded module.
+    pub fn snapshot_module<S: AsRef<str>>(&mut self, name: S) -> MResult<ModuleSnapshot> {
+        self.modules.get(name.as_ref()).map_or_else(
+            || Err(MError::NoSuchModule(name.as_ref().to_string())),
+            |module| module.snapshot(),
+        )
+    }
+
+    /// Restore state of a loaded module from a snapshot previously taken from this module
+    /// or from another instance of the same module.
+    pub fn restore_module<S: AsRef<str>>(
+        &mut self,
+        name: S,
+        snapshot: &ModuleSnapshot,
+    ) -> MResult<()> {
+        self.modules.get(name.as_ref()).map_or_else(
+            || Err(MError::NoSuchModule(name.as_ref().to_string())),
+            |module| module.restore(snapshot),
+        )
+    }
+
+    /// Unload previously loaded module, it fails if other modules depend on this one.
     pub fn unload_module<S: AsRef<str>>(&mut self, name: S) -> MResult<()> {
-        // TODO: clean up all reference from adaptors after adding support of lazy linking
-        self.modules
-            .remove(name.as_ref())
-            .map(|_| ())
-            .ok_or_else(|| MError::NoSuchModule(name.as_ref().to_string()))
+        let name = name.as_ref();
+        if !self.modules.contains_key(name) {
+            return Err(MError::NoSuchModule(name.to_string()));
+        }
+
+        let dependents = self.dependents(name);
+        if !dependents.is_empty() {
+            return Err(MError::ModuleHasDependents(dependents));
+        }
+
+        self.unload_module_(name);
+        Ok(())
+    }
+
+    /// Unload previously loaded module along with all modules that depend on it directly or
+    /// transitively. Returns names of all unloaded modules.
+    pub fn unload_module_cascade<S: AsRef<str>>(&mut self, name: S) -> MResult<Vec<String>> {
+        let name = name.as_ref();
+        if !self.modules.contains_key(name) {
+            return Err(MError::NoSuchModule(name.to_string()));
+        }
+
+        // breadth-first traversal of the dependents graph, that also handles cyclic dependencies
+        let mut unloaded = vec![name.to_string()];
+        let mut module_id = 0;
+        while module_id < unloaded.len() {
+            for dependent in self.dependents(&unloaded[module_id]) {
+                if !unloaded.contains(&dependent) {
+                    unloaded.push(dependent);
+                }
+            }
+            module_id += 1;
+        }
+
+        for module_name in unloaded.iter() {
+            self.unload_module_(module_name);
+        }
+
+        Ok(unloaded)
+    }
+
+    /// Return names of loaded modules that import functions of the module with given name.
+    pub fn dependents<S: AsRef<str>>(&self, module_name: S) -> Vec<String> {
+        let module_name = module_name.as_ref();
+
+        let mut dependents = self
+            .modules
+            .iter()
+            .filter(|(_, module)| module.dependencies().any(|name| name == module_name))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        dependents.sort();
+
+        dependents
+    }
+
+    /// Return names of loaded modules which functions are imported by the module with given name.
+    pub fn dependencies<S: AsRef<str>>(&self, module_name: S) -> Vec<String> {
+        let mut dependencies = self
+            .modules
+            .get(module_name.as_ref())
+            .into_iter()
+            .flat_map(|module| module.dependencies())
+            .filter(|name| self.modules.contains_key(*name))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        dependencies.sort();
+
+        dependencies
+    }
+
+    fn create_module(
+        &self,
+        name: &str,
+        wasm_bytes: &[u8],
+        config: MModuleConfig,
+    ) -> MResult<MModulePool> {
+        self.check_host_import_namespaces(name, &config)?;
+
+        let prepared_wasm_bytes = crate::misc::prepare_module(wasm_bytes, name, &config)?;
+        let wasmer_module = match &self.module_cache {
+            Some(module_cache) => module_cache.load_or_compile(&prepared_wasm_bytes)?,
+            None => wasmer_runtime::compile(&prepared_wasm_bytes)?,
+        };
+
+        MModulePool::new(
+            name,
+            wasmer_module,
+            config,
+            &self.linker,
+            self.fuel_counter.clone(),
+        )
+    }
+
+    // host imports are registered in the same import object as WASI, Marine hooks and imports
+    // from other modules, so they would silently replace functions from these namespaces
+    fn check_host_import_namespaces(&self, name: &str, config: &MModuleConfig) -> MResult<()> {
+        let reserved_namespace = |namespace: &str| -> Option<String> {
+            if namespace.starts_with("wasi_") {
+                Some(String::from("reserved for WASI"))
+            } else if namespace == crate::misc::HOOKS_NAMESPACE || namespace == "env" {
+                Some(String::from("reserved for Marine"))
+            } else if namespace == name || self.modules.contains_key(namespace) {
+                Some(format!("the name of module {}", namespace))
+            } else {
+                None
+            }
+        };
+
+        for import in config.host_imports.keys() {
+            if let Some(reason) = reserved_namespace(&import.namespace) {
+                return Err(MError::ReservedHostImportNamespace {
+                    namespace: import.namespace.clone(),
+                    reason,
+                });
+            }
+        }
+
+        // the module mustn't take a namespace of host imports of other loaded modules
+        let importer = self.modules.iter().find(|(module_name, module)| {
+            module_name.as_str() != name
+                && module
+                    .host_import_namespaces()
+                    .any(|namespace| namespace == name)
+        });
+        match importer {
+            Some((importer, _)) => Err(MError::HostImportNamespaceClash {
+                module_name: name.to_string(),
+                importer: importer.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_link_failure<T>(&self, result: MResult<T>) -> MResult<T> {
+        match take_link_failure() {
+            Some(error) => Err(error),
+            None => result,
+        }
+    }
+
+    fn unload_module_(&mut self, name: &str) {
+        // imports resolved to this module hold only weak references to it,
+        // so they become unresolved right after unregistering
+        self.modules.remove(name);
+        self.linker.unregister_module(name);
     }
 
     pub fn module_wasi_state<S: AsRef<str>>(
         &mut self,
         module_name: S,
-    ) -> Option<&wasmer_wasi::state::WasiState> {
+    ) -> Option<ModuleWasiState<'_>> {
         self.modules
-            .get_mut(module_name.as_ref())
-            .map(|module| module.get_wasi_state())
+            .get(module_name.as_ref())
+            .map(|module| ModuleWasiState {
+                module: module.primary(),
+            })
     }
 
     /// Return function signatures of all loaded info Marine modules with their names.
@@ -118,11 +504,18 @@ impl Marine {
             .map(|module| Self::get_module_interface(module))
     }
 
+    /// Return statistics of resources consumed by a module since it has been loaded.
+    pub fn module_stats<S: AsRef<str>>(&self, module_name: S) -> Option<ModuleStats> {
+        self.modules
+            .get(module_name.as_ref())
+            .map(|module| module.stats())
+    }
+
     /// Return record types exported by module with given name.
     pub fn module_record_types<S: AsRef<str>>(&self, module_name: S) -> Option<&MRecordTypes> {
         self.modules
             .get(module_name.as_ref())
-            .map(|module| module.export_record_types())
+            .map(|module| module.record_types())
     }
 
     /// Return record type for supplied record id exported by module with given name.
@@ -130,16 +523,16 @@ impl Marine {
         &self,
         module_name: S,
         record_id: u64,
-    ) -> Option<&Rc<IRecordType>> {
+    ) -> Option<&Arc<IRecordType>> {
         self.modules
             .get(module_name.as_ref())
-            .and_then(|module| module.export_record_type_by_id(record_id))
+            .and_then(|module| module.record_types().get(&record_id))
     }
 
-    fn get_module_interface(module: &MModule) -> MModuleInterface<'_> {
-        let record_types = module.export_record_types();
+    fn get_module_interface(module: &MModulePool) -> MModuleInterface<'_> {
+        let record_types = module.record_types();
 
-        let function_signatures = module.get_exports_signatures().collect::<Vec<_>>();
+        let function_signatures = module.function_signatures().to_vec();
 
         MModuleInterface {
             record_types,
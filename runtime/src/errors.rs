@@ -116,6 +116,85 @@ pub enum MError {
         provided: semver::Version,
     },
 
+    /// A module tried to use more memory than it's allowed by its config.
+    #[error("module with name {module} requested {requested} memory pages, but its limit is {limit} pages")]
+    MemoryLimitExceeded {
+        module: String,
+        requested: u32,
+        limit: u32,
+    },
+
+    /// A call has consumed all the fuel provided to it.
+    #[error("call of module with name {module} has run out of fuel, {fuel} units were provided")]
+    OutOfFuel { module: String, fuel: u64 },
+
+    /// A call has been interrupted by its deadline or cancellation handle.
+    #[error("call of module with name {0} has been interrupted by its deadline")]
+    CallTimedOut(String),
+
+    /// An import of a module refers to a function that isn't exported by any loaded module.
+    #[error("import {namespace}.{function_name} can't be resolved, probably module with name {namespace} isn't loaded in Marine")]
+    UnresolvedImport {
+        namespace: String,
+        function_name: String,
+    },
+
+    /// An import of a module refers to a function that is exported with another signature.
+    #[error("import {namespace}.{function_name} doesn't match the signature of the function exported by module with name {namespace}")]
+    IncompatibleImport {
+        namespace: String,
+        function_name: String,
+    },
+
+    /// A module has been called through imports of other modules while all of its instances
+    /// are busy with calls made by the same thread.
+    #[error("module with name {0} is called again before its call has finished, cyclic calls between modules aren't supported")]
+    CyclicCall(String),
+
+    /// A host import failed and trapped the module that had called it.
+    #[error("host import {import_name} failed: {error}")]
+    HostImportFailed {
+        import_name: String,
+        error: HostImportError,
+    },
+
+    /// Host imports can't be provided from namespaces of WASI, Marine itself
+    /// and modules loaded into Marine.
+    #[error("namespace {namespace} can't be used by host imports, it's {reason}")]
+    ReservedHostImportNamespace { namespace: String, reason: String },
+
+    /// A module can't be loaded with the name of a namespace of host imports of other modules.
+    #[error("module with name {module_name} can't be loaded, host imports of module {importer} are provided from the namespace with this name")]
+    HostImportNamespaceClash {
+        module_name: String,
+        importer: String,
+    },
+
+    /// A record type defined by the host for a host import doesn't match record types
+    /// of the module importing it.
+    #[error("record type {record_name} of host import {import_name} doesn't match record types of the module: {reason}")]
+    IncompatibleHostRecordType {
+        import_name: String,
+        record_name: String,
+        reason: String,
+    },
+
+    /// A module can't be unloaded because other modules import its functions.
+    #[error("module can't be unloaded, because modules {0:?} depend on it")]
+    ModuleHasDependents(Vec<String>),
+
+    /// A snapshot can't be taken, serialized or applied to a module.
+    #[error("{0}")]
+    IncorrectSnapshot(String),
+
+    /// A virtual filesystem of a module can't be created, loaded or saved.
+    #[error("virtual filesystem error: {0}")]
+    VfsError(String),
+
+    /// Errors related to accessing the compiled modules cache.
+    #[error("module cache error: {0}")]
+    ModuleCacheError(String),
+
     /// Module IT versions are incompatible.
     #[error("module with name {module_name} compiled with {provided} IT version, but at least {required} required")]
     IncompatibleITVersions {
@@ -0,0 +1,75 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+/// Returned from the fuel hook to trap a module that has run out of fuel.
+pub(crate) struct FuelExhausted;
+
+/// Counts fuel consumed by modules instrumented with fuel metering.
+/// It's shared between all modules of a Marine instance, so fuel spent by inter-module calls
+/// is charged from the budget of the top-level call.
+pub(crate) struct FuelCounter {
+    consumed: AtomicU64,
+    limit: AtomicU64,
+    exhausted: AtomicBool,
+}
+
+impl FuelCounter {
+    pub(crate) fn new() -> Self {
+        Self {
+            consumed: AtomicU64::new(0),
+            limit: AtomicU64::new(u64::MAX),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Resets consumed fuel and sets a new limit, None means that fuel is unlimited.
+    pub(crate) fn reset(&self, limit: Option<u64>) {
+        self.consumed.store(0, Ordering::Relaxed);
+        self.limit
+            .store(limit.unwrap_or(u64::MAX), Ordering::Relaxed);
+        self.exhausted.store(false, Ordering::Relaxed);
+    }
+
+    pub(crate) fn consume(&self, amount: u64) -> Result<(), FuelExhausted> {
+        let previous = self
+            .consumed
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |consumed| {
+                Some(consumed.saturating_add(amount))
+            })
+            // the closure always returns Some, so fetch_update can't fail
+            .unwrap_or_else(|consumed| consumed);
+        let consumed = previous.saturating_add(amount);
+
+        if consumed > self.limit.load(Ordering::Relaxed) {
+            self.exhausted.store(true, Ordering::Relaxed);
+            return Err(FuelExhausted);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn consumed(&self) -> u64 {
+        self.consumed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
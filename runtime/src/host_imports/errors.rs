@@ -47,4 +47,24 @@ pub enum HostImportError {
 
     #[error("{0}")]
     InvalidUTF8String(#[from] std::string::FromUtf8Error),
+
+    /// A function that must be exported by a module calling a host import is missing.
+    #[error("function {name} isn't exported properly by the module: {reason}")]
+    MissingWasmFunc { name: String, reason: String },
+
+    /// A call of a module function from a host import failed.
+    #[error("call of a module function failed: {0}")]
+    WasmFuncCallFailed(String),
+
+    /// A host closure panicked.
+    #[error("host closure panicked: {0}")]
+    ClosurePanicked(String),
+
+    /// An argument of a typed host import can't be converted to the closure argument type.
+    #[error("argument {position} can't be converted to the host closure argument: {reason}")]
+    InvalidArgument { position: usize, reason: String },
+
+    /// A result of a typed host import can't be converted to an interface value.
+    #[error("result of the host closure can't be converted to an interface value: {0}")]
+    InvalidResult(String),
 }
@@ -0,0 +1,164 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::HostImportDescriptor;
+use crate::IRecordType;
+use crate::IType;
+use crate::MError;
+use crate::MRecordTypes;
+
+use std::collections::HashMap;
+
+/// Types of a host import with ids of records referring to record types of the importing module.
+pub(crate) struct HostImportTypes {
+    pub(crate) argument_types: Vec<IType>,
+    pub(crate) output_type: Option<IType>,
+}
+
+/// Matches record types defined by the host for a host import against record types
+/// of the module importing it.
+pub(crate) fn resolve_host_import_types(
+    import_name: &str,
+    descriptor: &HostImportDescriptor,
+    module_record_types: &MRecordTypes,
+) -> Result<HostImportTypes, MError> {
+    if descriptor.record_types.is_empty() {
+        return Ok(HostImportTypes {
+            argument_types: descriptor.argument_types.clone(),
+            output_type: descriptor.output_type.clone(),
+        });
+    }
+
+    let mut resolver = RecordTypesResolver {
+        host_record_types: &descriptor.record_types,
+        module_record_types,
+        resolved_ids: HashMap::new(),
+    };
+
+    let types = resolver.resolve_types(descriptor);
+    types.map_err(|(record_name, reason)| MError::IncompatibleHostRecordType {
+        import_name: import_name.to_string(),
+        record_name,
+        reason,
+    })
+}
+
+/// Name of the record type that can't be resolved and the reason.
+type ResolveError = (String, String);
+
+struct RecordTypesResolver<'r> {
+    host_record_types: &'r MRecordTypes,
+    module_record_types: &'r MRecordTypes,
+    // host record type id -> module record type id
+    resolved_ids: HashMap<u64, u64>,
+}
+
+impl RecordTypesResolver<'_> {
+    fn resolve_types(
+        &mut self,
+        descriptor: &HostImportDescriptor,
+    ) -> Result<HostImportTypes, ResolveError> {
+        let argument_types = descriptor
+            .argument_types
+            .iter()
+            .map(|ty| self.resolve_type(ty))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let output_type = match &descriptor.output_type {
+            Some(ty) => Some(self.resolve_type(ty)?),
+            None => None,
+        };
+
+        Ok(HostImportTypes {
+            argument_types,
+            output_type,
+        })
+    }
+
+    fn resolve_type(&mut self, ty: &IType) -> Result<IType, ResolveError> {
+        match ty {
+            IType::Record(host_record_id) => {
+                let module_record_id = self.resolve_record(*host_record_id)?;
+                Ok(IType::Record(module_record_id))
+            }
+            IType::Array(ty) => Ok(IType::Array(Box::new(self.resolve_type(ty)?))),
+            ty => Ok(ty.clone()),
+        }
+    }
+
+    fn resolve_record(&mut self, host_record_id: u64) -> Result<u64, ResolveError> {
+        if let Some(module_record_id) = self.resolved_ids.get(&host_record_id) {
+            return Ok(*module_record_id);
+        }
+
+        let host_record = match self.host_record_types.get(&host_record_id) {
+            Some(host_record) => host_record.clone(),
+            None => {
+                return Err((
+                    format!("with id {}", host_record_id),
+                    String::from("it isn't defined by the host"),
+                ))
+            }
+        };
+
+        let candidates = self
+            .module_record_types
+            .iter()
+            .filter(|(_, module_record)| module_record.name == host_record.name)
+            .map(|(id, module_record)| (*id, module_record.clone()))
+            .collect::<Vec<_>>();
+
+        if candidates.is_empty() {
+            return Err((
+                host_record.name.clone(),
+                String::from("the module doesn't have a record type with such name"),
+            ));
+        }
+
+        for (module_record_id, module_record) in candidates {
+            if self.is_record_matched(&host_record, &module_record)? {
+                self.resolved_ids.insert(host_record_id, module_record_id);
+                return Ok(module_record_id);
+            }
+        }
+
+        Err((
+            host_record.name.clone(),
+            String::from("fields of the record type differ from fields of the module one"),
+        ))
+    }
+
+    fn is_record_matched(
+        &mut self,
+        host_record: &IRecordType,
+        module_record: &IRecordType,
+    ) -> Result<bool, ResolveError> {
+        if host_record.fields.len() != module_record.fields.len() {
+            return Ok(false);
+        }
+
+        for (host_field, module_field) in host_record.fields.iter().zip(module_record.fields.iter())
+        {
+            if host_field.name != module_field.name
+                || self.resolve_type(&host_field.ty)? != module_field.ty
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
@@ -21,11 +21,20 @@ use super::lowering::ivalue_to_wvalues;
 use super::lowering::LoHelper;
 use super::utils::itypes_args_to_wtypes;
 use super::utils::itypes_output_to_wtypes;
+use super::trap::trap;
+use super::trap::take_closure_failure;
+use super::trap::panic_message;
 
-use crate::MRecordTypes;
 use crate::init_wasm_func_once;
 use crate::call_wasm_func;
 use crate::HostImportDescriptor;
+use crate::HostImportName;
+use crate::IValue;
+use crate::IType;
+use crate::MRecordTypes;
+use crate::call_trace::CallKind;
+use crate::call_trace::CallSpan;
+use crate::stats::SharedModuleStats;
 
 use wasmer_core::Func;
 use wasmer_core::vm::Ctx;
@@ -36,111 +45,172 @@ use it_lilo::lifter::ILifter;
 use it_lilo::lowerer::ILowerer;
 
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::panic::AssertUnwindSafe;
 use std::ops::Deref;
+use std::sync::Arc;
 
 pub(crate) fn create_host_import_func(
-    descriptor: HostImportDescriptor,
-    record_types: Rc<MRecordTypes>,
+    import: HostImportName,
+    descriptor: Arc<HostImportDescriptor>,
+    types: HostImportTypes,
+    record_types: Arc<MRecordTypes>,
+    stats: SharedModuleStats,
 ) -> DynamicFunc<'static> {
-    let allocate_func: AllocateFunc = Box::new(RefCell::new(None));
-    let set_result_ptr_func: SetResultPtrFunc = Box::new(RefCell::new(None));
-    let set_result_size_func: SetResultSizeFunc = Box::new(RefCell::new(None));
-
-    let HostImportDescriptor {
-        host_exported_func,
-        argument_types,
-        output_type,
-        error_handler,
-    } = descriptor;
-
-    let output_type_to_types = |output_type| match output_type {
-        Some(ty) => vec![ty],
-        None => vec![],
+    let output_types = types.output_type.iter().cloned().collect::<Vec<_>>();
+
+    let raw_args = itypes_args_to_wtypes(&types.argument_types);
+    let raw_output = itypes_output_to_wtypes(&output_types);
+
+    let host_import = HostImport {
+        import_name: import.to_string(),
+        import,
+        descriptor,
+        argument_types: types.argument_types,
+        record_types,
+        stats,
+        allocate_func: Box::new(RefCell::new(None)),
+        set_result_ptr_func: Box::new(RefCell::new(None)),
+        set_result_size_func: Box::new(RefCell::new(None)),
     };
 
-    let raw_args = itypes_args_to_wtypes(&argument_types);
-    let raw_output = itypes_output_to_wtypes(&output_type_to_types(output_type));
-
     let func = move |ctx: &mut Ctx, inputs: &[WValue]| -> Vec<WValue> {
-        let memory_index = 0;
-        let view = ctx.memory(memory_index).view::<u8>();
-        let memory = view.deref();
+        // the result is matched here, so the trap doesn't skip anything that should be dropped
+        match host_import.call(ctx, inputs) {
+            Ok(outputs) => outputs,
+            Err(e) => trap(ctx, &host_import.import_name, e),
+        }
+    };
 
-        let li_helper = LiHelper::new(record_types.clone());
-        let lifter = ILifter::new(memory, &li_helper);
+    DynamicFunc::new(Arc::new(FuncSig::new(raw_args, raw_output)), func)
+}
 
-        let result = match wvalues_to_ivalues(&lifter, inputs, &argument_types) {
-            Ok(ivalues) => host_exported_func(ctx, ivalues),
+struct HostImport {
+    import: HostImportName,
+    // name of the import shown in errors and statistics
+    import_name: String,
+    descriptor: Arc<HostImportDescriptor>,
+    // types of the descriptor with records of the module
+    argument_types: Vec<IType>,
+    record_types: Arc<MRecordTypes>,
+    stats: SharedModuleStats,
+    allocate_func: AllocateFunc,
+    set_result_ptr_func: SetResultPtrFunc,
+    set_result_size_func: SetResultSizeFunc,
+}
+
+impl HostImport {
+    fn call(&self, ctx: &mut Ctx, inputs: &[WValue]) -> HostImportResult<Vec<WValue>> {
+        let result = match self.lift_arguments(ctx, inputs) {
+            Ok(ivalues) => self.call_closure(ctx, ivalues)?,
             Err(e) => {
                 log::error!("error occurred while lifting values in host import: {}", e);
-                error_handler
-                    .as_ref()
-                    .map_or_else(|| default_error_handler(&e), |h| h(&e))
+                let span = CallSpan::enter(
+                    CallKind::HostImport,
+                    &self.import.namespace,
+                    &self.import.name,
+                    &[],
+                );
+                span.exit(Err(&e));
+
+                match &self.descriptor.error_handler {
+                    Some(error_handler) => error_handler(&e),
+                    None => return Err(e),
+                }
             }
         };
 
-        init_wasm_func_once!(allocate_func, ctx, (i32, i32), i32, ALLOCATE_FUNC_NAME, 2);
+        self.lower_result(ctx, result)
+    }
 
-        let lo_helper = LoHelper::new(&ctx, &allocate_func);
-        let t = ILowerer::new(&lo_helper)
-            .map_err(HostImportError::LowererError)
-            .and_then(|lowerer| ivalue_to_wvalues(&lowerer, result));
+    fn lift_arguments(&self, ctx: &Ctx, inputs: &[WValue]) -> HostImportResult<Vec<IValue>> {
+        let memory_index = 0;
+        let view = ctx.memory(memory_index).view::<u8>();
+        let memory = view.deref();
 
-        let wvalues = match t {
-            Ok(wvalues) => wvalues,
-            Err(e) => {
-                log::error!("host closure failed: {}", e);
+        let li_helper = LiHelper::new(self.record_types.clone());
+        let lifter = ILifter::new(memory, &li_helper);
 
-                // returns 0 to a Wasm module in case of errors
-                init_wasm_func_once!(set_result_ptr_func, ctx, i32, (), SET_PTR_FUNC_NAME, 4);
-                init_wasm_func_once!(set_result_size_func, ctx, i32, (), SET_SIZE_FUNC_NAME, 4);
+        wvalues_to_ivalues(&lifter, inputs, &self.argument_types)
+    }
+
+    fn call_closure(
+        &self,
+        ctx: &mut Ctx,
+        ivalues: Vec<IValue>,
+    ) -> HostImportResult<Option<IValue>> {
+        self.stats
+            .lock()
+            .unwrap()
+            .record_host_import_call(&self.import_name);
+        let span = CallSpan::enter(
+            CallKind::HostImport,
+            &self.import.namespace,
+            &self.import.name,
+            &ivalues,
+        );
+
+        // a failure could be left by a closure called outside of a host import
+        take_closure_failure();
+
+        // panics of the closure are caught only if it's built with panic = "unwind",
+        // closures should fail with fail_host_import instead
+        let host_exported_func = &self.descriptor.host_exported_func;
+        let result =
+            std::panic::catch_unwind(AssertUnwindSafe(|| host_exported_func(ctx, ivalues)))
+                .map_err(|payload| HostImportError::ClosurePanicked(panic_message(&*payload)))
+                .and_then(|output| match take_closure_failure() {
+                    Some(error) => Err(error),
+                    None => Ok(output),
+                });
+
+        match &result {
+            Ok(result) => span.exit(Ok::<_, HostImportError>(
+                result.as_ref().map_or(&[][..], std::slice::from_ref),
+            )),
+            Err(e) => span.exit(Err(e)),
+        }
 
-                call_wasm_func!(set_result_ptr_func, 0);
-                call_wasm_func!(set_result_size_func, 0);
-                return vec![WValue::I32(0)];
-            }
-        };
+        result
+    }
+
+    fn lower_result(&self, ctx: &mut Ctx, result: Option<IValue>) -> HostImportResult<Vec<WValue>> {
+        let allocate_func = &self.allocate_func;
+        let set_result_ptr_func = &self.set_result_ptr_func;
+        let set_result_size_func = &self.set_result_size_func;
+
+        init_wasm_func_once!(allocate_func, ctx, (i32, i32), i32, ALLOCATE_FUNC_NAME);
+
+        let lo_helper = LoHelper::new(&ctx, allocate_func);
+        let lowerer = ILowerer::new(&lo_helper).map_err(HostImportError::LowererError)?;
+        let wvalues = ivalue_to_wvalues(&lowerer, result)?;
 
         // TODO: refactor this when multi-value is supported
         match wvalues.len() {
             // strings and arrays are passed back to the Wasm module by pointer and size
             2 => {
-                init_wasm_func_once!(set_result_ptr_func, ctx, i32, (), SET_PTR_FUNC_NAME, 4);
-                init_wasm_func_once!(set_result_size_func, ctx, i32, (), SET_SIZE_FUNC_NAME, 4);
+                init_wasm_func_once!(set_result_ptr_func, ctx, i32, (), SET_PTR_FUNC_NAME);
+                init_wasm_func_once!(set_result_size_func, ctx, i32, (), SET_SIZE_FUNC_NAME);
 
-                call_wasm_func!(set_result_ptr_func, wvalues[0].to_u128() as _);
-                call_wasm_func!(set_result_size_func, wvalues[1].to_u128() as _);
-                vec![]
+                call_wasm_func!(set_result_ptr_func, wvalues[0].to_u128() as _)?;
+                call_wasm_func!(set_result_size_func, wvalues[1].to_u128() as _)?;
+                Ok(vec![])
             }
 
             // records and primitive types are passed to the Wasm module by pointer
             // and value on the stack
             1 => {
-                init_wasm_func_once!(set_result_ptr_func, ctx, i32, (), SET_PTR_FUNC_NAME, 3);
+                init_wasm_func_once!(set_result_ptr_func, ctx, i32, (), SET_PTR_FUNC_NAME);
 
-                call_wasm_func!(set_result_ptr_func, wvalues[0].to_u128() as _);
-                vec![wvalues[0].clone()]
+                call_wasm_func!(set_result_ptr_func, wvalues[0].to_u128() as _)?;
+                Ok(vec![wvalues[0].clone()])
             }
 
             // when None is passed
-            0 => vec![],
+            0 => Ok(vec![]),
 
             // at now while multi-values aren't supported ivalue_to_wvalues returns only Vec with
             // 0, 1, 2 values
             _ => unimplemented!(),
         }
-    };
-
-    DynamicFunc::new(
-        std::sync::Arc::new(FuncSig::new(raw_args, raw_output)),
-        func,
-    )
-}
-
-fn default_error_handler(err: &HostImportError) -> Option<crate::IValue> {
-    panic!(
-        "an error is occurred while lifting values to interface values: {}",
-        err
-    )
+    }
 }
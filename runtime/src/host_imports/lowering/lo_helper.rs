@@ -37,7 +37,8 @@ impl<'c> LoHelper<'c> {
 
 impl Allocatable for LoHelper<'_> {
     fn allocate(&self, size: u32, type_tag: u32) -> Result<usize, AllocatableError> {
-        let offset = call_wasm_func!(self.allocate_func, size as _, type_tag as _);
+        let offset = call_wasm_func!(self.allocate_func, size as _, type_tag as _)
+            .map_err(|_| AllocatableError::AllocateCallFailed)?;
         Ok(offset as _)
     }
 
@@ -15,9 +15,12 @@
  */
 
 mod errors;
+mod host_record_types;
 mod lifting;
 mod lowering;
 mod imports;
+mod trap;
+mod typed_import;
 mod utils;
 
 use std::cell::RefCell;
@@ -25,6 +28,14 @@ use wasmer_core::Func;
 
 pub use errors::HostImportError;
 pub(crate) use imports::create_host_import_func;
+pub(crate) use host_record_types::resolve_host_import_types;
+pub(crate) use host_record_types::HostImportTypes;
+pub(crate) use trap::take_host_import_failure;
+pub use trap::fail_host_import;
+pub use typed_import::HostImportValue;
+pub use typed_import::HostImportRecord;
+pub use typed_import::HostImportOutput;
+pub use typed_import::IntoHostImport;
 
 pub(self) use wasmer_core::types::Value as WValue;
 pub(self) use wasmer_core::types::Type as WType;
@@ -35,6 +46,9 @@ pub(self) type AllocateFunc = WasmModuleFunc<(i32, i32), i32>;
 pub(self) type SetResultPtrFunc = WasmModuleFunc<i32, ()>;
 pub(self) type SetResultSizeFunc = WasmModuleFunc<i32, ()>;
 
+/// Default namespace of host imports inside Wasm modules.
+pub const HOST_IMPORTS_NAMESPACE: &str = "host";
+
 pub(self) const ALLOCATE_FUNC_NAME: &str = "allocate";
 pub(self) const SET_PTR_FUNC_NAME: &str = "set_result_ptr";
 pub(self) const SET_SIZE_FUNC_NAME: &str = "set_result_size";
@@ -0,0 +1,86 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::HostImportError;
+
+use wasmer_core::error::RuntimeError;
+use wasmer_core::vm::Ctx;
+
+use std::any::Any;
+use std::cell::RefCell;
+
+thread_local!(static HOST_IMPORT_FAILURE: RefCell<Option<HostImportFailure>> = RefCell::new(None));
+thread_local!(static CLOSURE_FAILURE: RefCell<Option<HostImportError>> = RefCell::new(None));
+
+/// Describes why a host import has trapped the module called it.
+pub(crate) struct HostImportFailure {
+    pub(crate) import_name: String,
+    pub(crate) error: HostImportError,
+}
+
+/// Payload of the Wasmer trap raised by a failed host import.
+struct HostImportTrap;
+
+/// Traps the module that has called the host import, the error is then returned
+/// from the Marine call on the current thread.
+pub(super) fn trap(ctx: &mut Ctx, import_name: &str, error: HostImportError) -> ! {
+    log::error!("host import {} failed: {}", import_name, error);
+
+    HOST_IMPORT_FAILURE.with(|failure| {
+        let mut failure = failure.borrow_mut();
+        // the first failure is the cause of the others
+        if failure.is_none() {
+            *failure = Some(HostImportFailure {
+                import_name: import_name.to_string(),
+                error,
+            });
+        }
+    });
+
+    // the trap jumps right to the Wasmer call of the module without unwinding, so it works
+    // with panic = "abort" as well, but values owned by the skipped frames aren't dropped,
+    // so the caller mustn't own anything that needs it
+    let trap = RuntimeError::User(Box::new(HostImportTrap));
+    unsafe { (*ctx.module).runnable_module.do_early_trap(trap) }
+}
+
+/// Fails the host import that is being called on the current thread: the calling module
+/// is trapped and the call fails with MError::HostImportFailed as soon as the closure of
+/// the import returns, its result is ignored. Unlike a panic, it works with panic = "abort".
+pub fn fail_host_import(error: HostImportError) {
+    CLOSURE_FAILURE.with(|failure| *failure.borrow_mut() = Some(error));
+}
+
+/// Returns the failure reported by the host import closure called on the current thread.
+pub(super) fn take_closure_failure() -> Option<HostImportError> {
+    CLOSURE_FAILURE.with(|failure| failure.borrow_mut().take())
+}
+
+/// Returns the failure of a host import that has happened on the current thread, if any.
+pub(crate) fn take_host_import_failure() -> Option<HostImportFailure> {
+    HOST_IMPORT_FAILURE.with(|failure| failure.borrow_mut().take())
+}
+
+pub(super) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+
+    match payload.downcast_ref::<String>() {
+        Some(message) => message.clone(),
+        None => String::from("unknown panic payload"),
+    }
+}
@@ -0,0 +1,294 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::HostImportError;
+use super::fail_host_import;
+
+use crate::HostImportDescriptor;
+use crate::IRecordType;
+use crate::IRecordFieldType;
+use crate::IType;
+use crate::IValue;
+use crate::MRecordTypes;
+use crate::from_interface_values;
+use crate::to_interface_value;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasmer_core::vm::Ctx;
+use wasmer_it::NEVec;
+
+use std::sync::Arc;
+
+/// A Rust type that could be passed to or returned from a host import.
+pub trait HostImportValue: Sized {
+    /// Type of the value, record types used by it are added to the provided ones.
+    fn itype(record_types: &mut MRecordTypes) -> IType;
+
+    fn from_ivalue(value: IValue) -> Result<Self, String>;
+
+    fn into_ivalue(self) -> Result<IValue, String>;
+
+    // Vectors are converted through their elements, so that bytes could be passed as byte arrays.
+
+    #[doc(hidden)]
+    fn vec_itype(record_types: &mut MRecordTypes) -> IType {
+        IType::Array(Box::new(Self::itype(record_types)))
+    }
+
+    #[doc(hidden)]
+    fn vec_from_ivalue(value: IValue) -> Result<Vec<Self>, String> {
+        match value {
+            IValue::Array(values) => values.into_iter().map(Self::from_ivalue).collect(),
+            value => Err(format!("expected array, found {:?}", value)),
+        }
+    }
+
+    #[doc(hidden)]
+    fn vec_into_ivalue(values: Vec<Self>) -> Result<IValue, String> {
+        let values = values
+            .into_iter()
+            .map(Self::into_ivalue)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(IValue::Array(values))
+    }
+}
+
+/// A record that could be passed to or returned from a host import,
+/// it's converted with to_interface_value and from_interface_values.
+pub trait HostImportRecord: Serialize + DeserializeOwned {
+    /// Name of the record type, modules should have a record type with the same name and fields.
+    const NAME: &'static str;
+
+    /// Fields of the record type in the order they are serialized, at least one is required.
+    /// Their types should be obtained with HostImportValue::itype.
+    fn fields(record_types: &mut MRecordTypes) -> Vec<IRecordFieldType>;
+}
+
+/// Result of a host import closure, () means that the host import returns nothing.
+pub trait HostImportOutput {
+    fn output_type(record_types: &mut MRecordTypes) -> Option<IType>;
+
+    fn into_output(self) -> Result<Option<IValue>, String>;
+}
+
+/// A closure that could be turned into a host import with HostImportDescriptor::from_fn.
+pub trait IntoHostImport<Args, Output> {
+    fn into_host_import(self) -> HostImportDescriptor;
+}
+
+macro_rules! impl_host_import_value {
+    ($rust_type:ty, $variant:ident) => {
+        impl HostImportValue for $rust_type {
+            fn itype(_record_types: &mut MRecordTypes) -> IType {
+                IType::$variant
+            }
+
+            fn from_ivalue(value: IValue) -> Result<Self, String> {
+                match value {
+                    IValue::$variant(value) => Ok(value),
+                    value => Err(format!(
+                        "expected {}, found {:?}",
+                        stringify!($variant),
+                        value
+                    )),
+                }
+            }
+
+            fn into_ivalue(self) -> Result<IValue, String> {
+                Ok(IValue::$variant(self))
+            }
+        }
+    };
+}
+
+impl_host_import_value!(bool, Boolean);
+impl_host_import_value!(i8, S8);
+impl_host_import_value!(i16, S16);
+impl_host_import_value!(i32, S32);
+impl_host_import_value!(i64, S64);
+impl_host_import_value!(u16, U16);
+impl_host_import_value!(u32, U32);
+impl_host_import_value!(u64, U64);
+impl_host_import_value!(f32, F32);
+impl_host_import_value!(f64, F64);
+impl_host_import_value!(String, String);
+
+impl HostImportValue for u8 {
+    fn itype(_record_types: &mut MRecordTypes) -> IType {
+        IType::U8
+    }
+
+    fn from_ivalue(value: IValue) -> Result<Self, String> {
+        match value {
+            IValue::U8(value) => Ok(value),
+            value => Err(format!("expected U8, found {:?}", value)),
+        }
+    }
+
+    fn into_ivalue(self) -> Result<IValue, String> {
+        Ok(IValue::U8(self))
+    }
+
+    fn vec_itype(_record_types: &mut MRecordTypes) -> IType {
+        IType::ByteArray
+    }
+
+    fn vec_from_ivalue(value: IValue) -> Result<Vec<Self>, String> {
+        match value {
+            IValue::ByteArray(bytes) => Ok(bytes),
+            value => Err(format!("expected byte array, found {:?}", value)),
+        }
+    }
+
+    fn vec_into_ivalue(values: Vec<Self>) -> Result<IValue, String> {
+        Ok(IValue::ByteArray(values))
+    }
+}
+
+impl<T: HostImportValue> HostImportValue for Vec<T> {
+    fn itype(record_types: &mut MRecordTypes) -> IType {
+        T::vec_itype(record_types)
+    }
+
+    fn from_ivalue(value: IValue) -> Result<Self, String> {
+        T::vec_from_ivalue(value)
+    }
+
+    fn into_ivalue(self) -> Result<IValue, String> {
+        T::vec_into_ivalue(self)
+    }
+}
+
+impl<T: HostImportRecord> HostImportValue for T {
+    fn itype(record_types: &mut MRecordTypes) -> IType {
+        let registered_id = record_types
+            .iter()
+            .find(|(_, record_type)| record_type.name == T::NAME)
+            .map(|(id, _)| *id);
+        if let Some(id) = registered_id {
+            return IType::Record(id);
+        }
+
+        // nested record types are registered by fields, so the id is chosen after that
+        let fields = NEVec::new(T::fields(record_types))
+            .unwrap_or_else(|_| panic!("record type {} must have at least one field", T::NAME));
+        let id = record_types.len() as u64;
+        let record_type = IRecordType {
+            name: T::NAME.to_string(),
+            fields,
+        };
+        record_types.insert(id, Arc::new(record_type));
+
+        IType::Record(id)
+    }
+
+    fn from_ivalue(value: IValue) -> Result<Self, String> {
+        from_interface_values(&[value]).map_err(|e| e.to_string())
+    }
+
+    fn into_ivalue(self) -> Result<IValue, String> {
+        to_interface_value(&self).map_err(|e| e.to_string())
+    }
+}
+
+impl HostImportOutput for () {
+    fn output_type(_record_types: &mut MRecordTypes) -> Option<IType> {
+        None
+    }
+
+    fn into_output(self) -> Result<Option<IValue>, String> {
+        Ok(None)
+    }
+}
+
+impl<T: HostImportValue> HostImportOutput for T {
+    fn output_type(record_types: &mut MRecordTypes) -> Option<IType> {
+        Some(T::itype(record_types))
+    }
+
+    fn into_output(self) -> Result<Option<IValue>, String> {
+        self.into_ivalue().map(Some)
+    }
+}
+
+fn next_argument<T: HostImportValue>(
+    arguments: &mut impl Iterator<Item = (usize, IValue)>,
+) -> Result<T, HostImportError> {
+    let (position, argument) = arguments
+        .next()
+        .ok_or(HostImportError::MismatchWValuesCount)?;
+
+    T::from_ivalue(argument).map_err(|reason| HostImportError::InvalidArgument { position, reason })
+}
+
+/// Fails the call of a host import, the error is then returned from the Marine call.
+fn fail(error: HostImportError) -> Option<IValue> {
+    fail_host_import(error);
+    None
+}
+
+macro_rules! impl_into_host_import {
+    ($($arg:ident $value:ident),*) => {
+        impl<Func, Output, $($arg),*> IntoHostImport<($($arg,)*), Output> for Func
+        where
+            Func: Fn(&mut Ctx, $($arg),*) -> Output + Send + Sync + 'static,
+            Output: HostImportOutput,
+            $($arg: HostImportValue,)*
+        {
+            #[allow(unused_variables, unused_mut)]
+            fn into_host_import(self) -> HostImportDescriptor {
+                let host_exported_func = move |ctx: &mut Ctx, arguments: Vec<IValue>| {
+                    let mut arguments = arguments.into_iter().enumerate();
+                    $(
+                        let $value = match next_argument::<$arg>(&mut arguments) {
+                            Ok(value) => value,
+                            Err(e) => return fail(e),
+                        };
+                    )*
+                    let output = self(ctx, $($value),*);
+
+                    output
+                        .into_output()
+                        .unwrap_or_else(|e| fail(HostImportError::InvalidResult(e)))
+                };
+
+                let mut record_types = MRecordTypes::new();
+                let argument_types = vec![$($arg::itype(&mut record_types)),*];
+                let output_type = Output::output_type(&mut record_types);
+
+                HostImportDescriptor {
+                    host_exported_func: Box::new(host_exported_func),
+                    argument_types,
+                    output_type,
+                    error_handler: None,
+                    record_types,
+                }
+            }
+        }
+    };
+}
+
+impl_into_host_import!();
+impl_into_host_import!(A1 a1);
+impl_into_host_import!(A1 a1, A2 a2);
+impl_into_host_import!(A1 a1, A2 a2, A3 a3);
+impl_into_host_import!(A1 a1, A2 a2, A3 a3, A4 a4);
+impl_into_host_import!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5);
+impl_into_host_import!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5, A6 a6);
+impl_into_host_import!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5, A6 a6, A7 a7);
+impl_into_host_import!(A1 a1, A2 a2, A3 a3, A4 a4, A5 a5, A6 a6, A7 a7, A8 a8);
@@ -126,15 +126,16 @@ pub(super) fn itypes_output_to_wtypes(itypes: &[IType]) -> Vec<WType> {
 
 #[macro_export] // https://github.com/rust-lang/rust/issues/57966#issuecomment-461077932
 /// Initialize Wasm function in form of Box<RefCell<Option<Func<'static, args, rets>>>> only once.
+/// Returns MissingWasmFunc from the enclosing function if there is no such function.
 macro_rules! init_wasm_func_once {
-    ($func:ident, $ctx:ident, $args:ty, $rets:ty, $func_name:ident, $ret_error_code: expr) => {
+    ($func:ident, $ctx:ident, $args:ty, $rets:ty, $func_name:ident) => {
         if $func.borrow().is_none() {
-            let raw_func = match unsafe {
-                super::utils::get_export_func_by_name::<$args, $rets>($ctx, $func_name)
-            } {
-                Ok(func) => func,
-                Err(_) => return vec![WValue::I32($ret_error_code)],
-            };
+            let raw_func =
+                unsafe { super::utils::get_export_func_by_name::<$args, $rets>($ctx, $func_name) }
+                    .map_err(|e| $crate::HostImportError::MissingWasmFunc {
+                        name: $func_name.to_string(),
+                        reason: e.to_string(),
+                    })?;
 
             unsafe {
                 // assumed that this function will be used only in the context of closure
@@ -150,9 +151,17 @@ macro_rules! init_wasm_func_once {
 }
 
 #[macro_export]
-/// Call Wasm function that have Box<RefCell<Option<Func<'static, args, rets>>>> type.
+/// Call Wasm function that have Box<RefCell<Option<Func<'static, args, rets>>>> type,
+/// evaluates to Result<rets, HostImportError>.
 macro_rules! call_wasm_func {
     ($func:expr, $($arg:expr),*) => {
-        $func.borrow().as_ref().unwrap().call($($arg),*).unwrap()
+        match $func.borrow().as_ref() {
+            Some(func) => func
+                .call($($arg),*)
+                .map_err(|e| $crate::HostImportError::WasmFuncCallFailed(e.to_string())),
+            None => Err($crate::HostImportError::WasmFuncCallFailed(String::from(
+                "function hasn't been initialized",
+            ))),
+        }
     };
 }
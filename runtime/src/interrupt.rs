@@ -0,0 +1,174 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+// checking the current time is much more expensive than checking the cancellation flag,
+// so the deadline is checked only once per this count of checks
+const DEADLINE_CHECK_PERIOD: u32 = 1024;
+
+thread_local!(static ACTIVE_DEADLINES: RefCell<Vec<ActiveDeadline>> = RefCell::new(Vec::new()));
+
+/// Allows to interrupt a call executed by Marine from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interrupts a call that uses this handle as soon as possible.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Describes when a call executed by Marine should be interrupted.
+#[derive(Clone, Debug)]
+pub enum CallDeadline {
+    /// Interrupt a call if it lasts longer than the provided duration.
+    Timeout(Duration),
+
+    /// Interrupt a call when the provided handle is cancelled.
+    Cancellation(CancellationHandle),
+}
+
+impl From<Duration> for CallDeadline {
+    fn from(timeout: Duration) -> Self {
+        CallDeadline::Timeout(timeout)
+    }
+}
+
+impl From<CancellationHandle> for CallDeadline {
+    fn from(handle: CancellationHandle) -> Self {
+        CallDeadline::Cancellation(handle)
+    }
+}
+
+/// Returns true if the call executed by Marine on the current thread should be interrupted.
+/// Host imports that could run for a long time are supposed to check it periodically.
+pub fn is_current_call_interrupted() -> bool {
+    is_any_deadline_reached(true)
+}
+
+/// The same as is_current_call_interrupted, but checks the current time only periodically,
+/// it's intended to be called from Wasm code on every metering checkpoint.
+pub(crate) fn is_current_call_interrupted_lazy() -> bool {
+    is_any_deadline_reached(false)
+}
+
+fn is_any_deadline_reached(check_time: bool) -> bool {
+    ACTIVE_DEADLINES.with(|deadlines| {
+        deadlines
+            .borrow()
+            .iter()
+            .any(|deadline| deadline.is_reached(check_time))
+    })
+}
+
+struct ActiveDeadline {
+    instant: Option<Instant>,
+    cancellation: CancellationHandle,
+    checks_count: Cell<u32>,
+}
+
+impl ActiveDeadline {
+    fn is_reached(&self, check_time: bool) -> bool {
+        if self.cancellation.is_cancelled() {
+            return true;
+        }
+
+        let instant = match self.instant {
+            Some(instant) => instant,
+            None => return false,
+        };
+
+        if !check_time {
+            let checks_count = self.checks_count.get().wrapping_add(1);
+            self.checks_count.set(checks_count);
+            if checks_count % DEADLINE_CHECK_PERIOD != 0 {
+                return false;
+            }
+        }
+
+        if Instant::now() >= instant {
+            // there is no need to check time again
+            self.cancellation.cancel();
+            return true;
+        }
+
+        false
+    }
+}
+
+/// Makes a deadline active for the current thread until the guard is dropped.
+pub(crate) struct DeadlineGuard {
+    instant: Option<Instant>,
+    cancellation: CancellationHandle,
+}
+
+impl DeadlineGuard {
+    pub(crate) fn activate(deadline: CallDeadline) -> Self {
+        let (instant, cancellation) = match deadline {
+            CallDeadline::Timeout(timeout) => (
+                Instant::now().checked_add(timeout),
+                CancellationHandle::new(),
+            ),
+            CallDeadline::Cancellation(cancellation) => (None, cancellation),
+        };
+
+        let deadline = ActiveDeadline {
+            instant,
+            cancellation: cancellation.clone(),
+            checks_count: Cell::new(0),
+        };
+        ACTIVE_DEADLINES.with(|deadlines| deadlines.borrow_mut().push(deadline));
+
+        Self {
+            instant,
+            cancellation,
+        }
+    }
+
+    /// Returns true if the deadline has been reached while the guard is active.
+    pub(crate) fn is_reached(&self) -> bool {
+        let is_expired = match self.instant {
+            Some(instant) => Instant::now() >= instant,
+            None => false,
+        };
+
+        is_expired || self.cancellation.is_cancelled()
+    }
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        ACTIVE_DEADLINES.with(|deadlines| deadlines.borrow_mut().pop());
+    }
+}
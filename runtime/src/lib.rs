@@ -14,8 +14,6 @@
  * limitations under the License.
  */
 #![warn(rust_2018_idioms)]
-#![feature(get_mut_unchecked)]
-#![feature(new_uninit)]
 #![feature(stmt_expr_attributes)]
 #![deny(
     dead_code,
@@ -27,20 +25,56 @@
     unreachable_patterns
 )]
 
+mod call_trace;
 mod config;
+mod deterministic;
+mod disk_quota;
 mod engine;
 mod errors;
+mod fuel;
+mod interrupt;
 mod host_imports;
 mod misc;
 mod module;
+mod module_cache;
+mod read_only_dirs;
+mod snapshot;
+mod stats;
+mod vfs;
+mod wasi_capabilities;
 
+pub use call_trace::CallKind;
+pub use call_trace::CallTrace;
+pub use call_trace::TraceConfig;
 pub use config::MModuleConfig;
 pub use config::HostExportedFunc;
 pub use config::HostImportDescriptor;
+pub use config::HostImportName;
+pub use config::RawImports;
+pub use deterministic::DeterministicEnv;
+pub use disk_quota::DiskQuota;
 pub use engine::Marine;
 pub use engine::MModuleInterface;
+pub use engine::ModuleWasiState;
+pub use engine::STATE_EXPORT_FUNC_NAME;
+pub use engine::STATE_MIGRATION_FUNC_NAME;
 pub use errors::MError;
+pub use module_cache::ModuleCache;
+pub use snapshot::ModuleSnapshot;
+pub use stats::ModuleStats;
+pub use stats::FunctionStats;
+pub use vfs::VfsConfig;
+pub use wasi_capabilities::WasiCapabilities;
+pub use interrupt::CallDeadline;
+pub use interrupt::CancellationHandle;
+pub use interrupt::is_current_call_interrupted;
 pub use host_imports::HostImportError;
+pub use host_imports::fail_host_import;
+pub use host_imports::HostImportValue;
+pub use host_imports::HostImportRecord;
+pub use host_imports::HostImportOutput;
+pub use host_imports::IntoHostImport;
+pub use host_imports::HOST_IMPORTS_NAMESPACE;
 pub use module::IValue;
 pub use module::IRecordType;
 pub use module::IFunctionArg;
@@ -16,7 +16,14 @@
 
 mod prepare;
 mod version_checker;
+mod wasi_imports;
 
 pub(crate) use prepare::prepare_module;
+pub(crate) use prepare::HOOKS_NAMESPACE;
+pub(crate) use prepare::MEMORY_GROW_HOOK_NAME;
+pub(crate) use prepare::FUEL_HOOK_NAME;
+pub(crate) use prepare::GLOBAL_EXPORT_PREFIX;
 pub(crate) use version_checker::check_sdk_version;
 pub(crate) use version_checker::check_it_version;
+pub(crate) use wasi_imports::WasiImports;
+pub(crate) use wasi_imports::WASI_NAMESPACES;
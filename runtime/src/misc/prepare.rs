@@ -19,50 +19,378 @@
 // https://github.com/nearprotocol/nearcore/blob/master/runtime/near-vm-runner/src/prepare.rs
 
 use crate::MResult;
+use crate::MError;
+use crate::MModuleConfig;
 
-use parity_wasm::{
-    builder, elements,
-    elements::{MemorySection, MemoryType},
-};
+use parity_wasm::elements;
+use parity_wasm::elements::BlockType;
+use parity_wasm::elements::ExportEntry;
+use parity_wasm::elements::ExportSection;
+use parity_wasm::elements::External;
+use parity_wasm::elements::Func;
+use parity_wasm::elements::FuncBody;
+use parity_wasm::elements::FunctionType;
+use parity_wasm::elements::ImportCountType;
+use parity_wasm::elements::ImportEntry;
+use parity_wasm::elements::ImportSection;
+use parity_wasm::elements::Instruction;
+use parity_wasm::elements::Instructions;
+use parity_wasm::elements::Internal;
+use parity_wasm::elements::Local;
+use parity_wasm::elements::MemoryType;
+use parity_wasm::elements::Section;
+use parity_wasm::elements::Type;
+use parity_wasm::elements::TypeSection;
+use parity_wasm::elements::ValueType;
 
+/// Namespace of imports that are injected into a prepared module and provided by Marine itself.
+pub(crate) const HOOKS_NAMESPACE: &str = "__marine";
+
+/// Name of the import that is called by a prepared module when memory.grow fails.
+pub(crate) const MEMORY_GROW_HOOK_NAME: &str = "memory_grow_failed";
+
+/// Name of the import that is called by a prepared module to consume fuel,
+/// it's also used as a point where a call could be interrupted.
+pub(crate) const FUEL_HOOK_NAME: &str = "consume_fuel";
+
+/// Prefix of names under which mutable globals of a prepared module are exported,
+/// it allows Marine to access them while taking snapshots of the module.
+pub(crate) const GLOBAL_EXPORT_PREFIX: &str = "__marine_global_";
+
+// bits of the NaNs that results of float operations are canonicalized to in deterministic modules
+const CANONICAL_F32_NAN: u32 = 0x7fc0_0000;
+const CANONICAL_F64_NAN: u64 = 0x7ff8_0000_0000_0000;
+
+// Note that builder::from_module isn't used here, because it drops all custom sections
+// (including IT and sdk version ones), so the module is patched in place.
 struct ModuleBootstrapper {
     module: elements::Module,
 }
 
-impl<'a> ModuleBootstrapper {
+impl ModuleBootstrapper {
     fn init(module_code: &[u8]) -> MResult<Self> {
         let module = elements::deserialize_buffer(module_code)?;
 
         Ok(Self { module })
     }
 
-    fn set_mem_pages_count(self, mem_pages_count: u32) -> Self {
-        let Self { mut module } = self;
-
-        // At now, there is could be only one memory section, so
-        // it needs just to extract previous initial page count,
-        // delete an old entry and add create a new one with updated limits
-        let mem_initial = match module.memory_section_mut() {
-            Some(section) => match section.entries_mut().pop() {
-                Some(entry) => entry.limits().initial(),
-                None => 0,
-            },
-            None => 0,
+    fn set_mem_pages_count(mut self, module_name: &str, mem_pages_count: u32) -> MResult<Self> {
+        let limit_memory = |memory: &mut MemoryType| -> MResult<()> {
+            let mem_initial = memory.limits().initial();
+            if mem_initial > mem_pages_count {
+                return Err(MError::MemoryLimitExceeded {
+                    module: module_name.to_string(),
+                    requested: mem_initial,
+                    limit: mem_pages_count,
+                });
+            }
+
+            // a module could declare a maximum lower than the limit, then it's kept
+            let mem_maximum = match memory.limits().maximum() {
+                Some(declared_maximum) => declared_maximum.min(mem_pages_count),
+                None => mem_pages_count,
+            };
+            *memory = MemoryType::new(mem_initial, Some(mem_maximum));
+            Ok(())
+        };
+
+        // At now, there is could be only one memory, and it could be either defined
+        // by a module itself or imported from the host side (usually from env)
+        if let Some(section) = self.module.memory_section_mut() {
+            for memory in section.entries_mut() {
+                limit_memory(memory)?;
+            }
+        }
+
+        if let Some(section) = self.module.import_section_mut() {
+            for import in section.entries_mut() {
+                if let External::Memory(memory) = import.external_mut() {
+                    limit_memory(memory)?;
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Replaces each memory.grow instruction with a call of a wrapper function that
+    /// invokes the memory grow hook with a requested pages count if growing fails.
+    fn inject_memory_grow_hook(mut self) -> MResult<Self> {
+        let has_memory_grow = self.module.code_section().map_or(false, |section| {
+            section.bodies().iter().any(|body| {
+                body.code()
+                    .elements()
+                    .iter()
+                    .any(|instruction| matches!(instruction, Instruction::GrowMemory(_)))
+            })
+        });
+        if !has_memory_grow {
+            return Ok(self);
+        }
+
+        let hook_type = self.push_type(FunctionType::new(vec![ValueType::I32], None))?;
+        let wrapper_type = self.push_type(FunctionType::new(
+            vec![ValueType::I32],
+            Some(ValueType::I32),
+        ))?;
+
+        // the hook becomes the last imported function, so all local functions are shifted by one
+        let hook_index = self.module.import_count(ImportCountType::Function) as u32;
+        self.shift_function_indices(hook_index);
+        self.push_import(ImportEntry::new(
+            HOOKS_NAMESPACE.to_string(),
+            MEMORY_GROW_HOOK_NAME.to_string(),
+            External::Function(hook_type),
+        ))?;
+
+        // the wrapper is appended after all local functions
+        let wrapper_index = self.module.functions_space() as u32;
+        if let Some(section) = self.module.code_section_mut() {
+            for body in section.bodies_mut() {
+                for instruction in body.code_mut().elements_mut() {
+                    if let Instruction::GrowMemory(_) = instruction {
+                        *instruction = Instruction::Call(wrapper_index);
+                    }
+                }
+            }
+        }
+
+        // (func (param $delta i32) (result i32) (local $result i32)
+        //   (local.tee $result (memory.grow (local.get $delta)))
+        //   (if (i32.eq (i32.const -1)) (then (call $hook (local.get $delta))))
+        //   (local.get $result))
+        let wrapper_body = FuncBody::new(
+            vec![Local::new(1, ValueType::I32)],
+            Instructions::new(vec![
+                Instruction::GetLocal(0),
+                Instruction::GrowMemory(0),
+                Instruction::TeeLocal(1),
+                Instruction::I32Const(-1),
+                Instruction::I32Eq,
+                Instruction::If(BlockType::NoResult),
+                Instruction::GetLocal(0),
+                Instruction::Call(hook_index),
+                Instruction::End,
+                Instruction::GetLocal(1),
+                Instruction::End,
+            ]),
+        );
+
+        if let Some(section) = self.module.function_section_mut() {
+            section.entries_mut().push(Func::new(wrapper_type));
+        }
+        if let Some(section) = self.module.code_section_mut() {
+            section.bodies_mut().push(wrapper_body);
+        }
+
+        Ok(self)
+    }
+
+    /// Instruments the module with the pwasm-utils gas metering, that charges one unit
+    /// of fuel per executed Wasm instruction by calling the fuel hook.
+    /// It's needed both for fuel metering and for interrupting calls.
+    fn inject_fuel_metering(self, enabled: bool) -> MResult<Self> {
+        if !enabled {
+            return Ok(self);
+        }
+
+        // pwasm-utils rebuilds the module with builder::from_module, so custom sections
+        // should be moved out and then returned back
+        let (custom_sections, sections): (Vec<_>, Vec<_>) = self
+            .module
+            .into_sections()
+            .into_iter()
+            .partition(|section| matches!(section, Section::Custom(_)));
+
+        let rules = pwasm_utils::rules::Set::default();
+        let mut module = pwasm_utils::inject_gas_counter(elements::Module::new(sections), &rules)
+            .map_err(|_| {
+            elements::Error::Other("module contains instructions forbidden by fuel metering")
+        })?;
+
+        // inject_gas_counter always imports env.gas as the last import
+        if let Some(gas_import) = module
+            .import_section_mut()
+            .and_then(|section| section.entries_mut().last_mut())
+        {
+            *gas_import.module_mut() = HOOKS_NAMESPACE.to_string();
+            *gas_import.field_mut() = FUEL_HOOK_NAME.to_string();
+        }
+
+        module.sections_mut().extend(custom_sections);
+
+        Ok(Self { module })
+    }
+
+    /// Canonicalizes results of float operations that could produce NaNs, because bits of these
+    /// NaNs depend on the hardware. Each such operation is followed by a call of a function
+    /// that replaces any NaN with the canonical one.
+    fn canonicalize_nans(mut self, enabled: bool) -> MResult<Self> {
+        let has_nan_producing_ops = self.module.code_section().map_or(false, |section| {
+            section.bodies().iter().any(|body| {
+                body.code()
+                    .elements()
+                    .iter()
+                    .any(|instruction| nan_producing_op_type(instruction).is_some())
+            })
+        });
+        if !enabled || !has_nan_producing_ops {
+            return Ok(self);
+        }
+
+        // canonicalizers are appended after all local functions
+        let f32_canonicalizer_index = self.module.functions_space() as u32;
+        let f64_canonicalizer_index = f32_canonicalizer_index + 1;
+        if let Some(section) = self.module.code_section_mut() {
+            for body in section.bodies_mut() {
+                let instructions = body.code_mut().elements_mut();
+                let mut canonicalized = Vec::with_capacity(instructions.len());
+                for instruction in instructions.drain(..) {
+                    let op_type = nan_producing_op_type(&instruction);
+                    canonicalized.push(instruction);
+                    match op_type {
+                        Some(ValueType::F32) => {
+                            canonicalized.push(Instruction::Call(f32_canonicalizer_index))
+                        }
+                        Some(_) => canonicalized.push(Instruction::Call(f64_canonicalizer_index)),
+                        None => {}
+                    }
+                }
+                *instructions = canonicalized;
+            }
+        }
+
+        // (func (param $value f32) (result f32)
+        //   (select (local.get $value) (f32.const nan) (f32.eq (local.get $value) (local.get $value))))
+        let canonicalizers = [
+            (
+                ValueType::F32,
+                Instruction::F32Const(CANONICAL_F32_NAN),
+                Instruction::F32Eq,
+            ),
+            (
+                ValueType::F64,
+                Instruction::F64Const(CANONICAL_F64_NAN),
+                Instruction::F64Eq,
+            ),
+        ];
+        for (value_type, canonical_nan, eq) in canonicalizers.iter().cloned() {
+            let canonicalizer_type =
+                self.push_type(FunctionType::new(vec![value_type], Some(value_type)))?;
+            let canonicalizer_body = FuncBody::new(
+                vec![],
+                Instructions::new(vec![
+                    Instruction::GetLocal(0),
+                    canonical_nan,
+                    Instruction::GetLocal(0),
+                    Instruction::GetLocal(0),
+                    eq,
+                    Instruction::Select,
+                    Instruction::End,
+                ]),
+            );
+
+            if let Some(section) = self.module.function_section_mut() {
+                section.entries_mut().push(Func::new(canonicalizer_type));
+            }
+            if let Some(section) = self.module.code_section_mut() {
+                section.bodies_mut().push(canonicalizer_body);
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Exports all mutable globals defined by the module, so they could be captured by snapshots.
+    fn export_mutable_globals(mut self) -> MResult<Self> {
+        let imported_globals_count = self.module.import_count(ImportCountType::Global) as u32;
+        let exports = match self.module.global_section() {
+            Some(section) => section
+                .entries()
+                .iter()
+                .enumerate()
+                .filter(|(_, global)| global.global_type().is_mutable())
+                .map(|(id, _)| {
+                    let global_index = imported_globals_count + id as u32;
+                    let name = format!("{}{}", GLOBAL_EXPORT_PREFIX, global_index);
+                    ExportEntry::new(name, Internal::Global(global_index))
+                })
+                .collect::<Vec<_>>(),
+            None => return Ok(self),
         };
 
-        let memory_entry = MemoryType::new(mem_initial, Some(mem_pages_count));
-        let mut default_mem_section = MemorySection::default();
+        match self.module.export_section_mut() {
+            Some(section) => section.entries_mut().extend(exports),
+            None => {
+                let section = ExportSection::with_entries(exports);
+                self.module.insert_section(Section::Export(section))?;
+            }
+        }
 
-        module
-            .memory_section_mut()
-            .unwrap_or(&mut default_mem_section)
-            .entries_mut()
-            .push(memory_entry);
+        Ok(self)
+    }
 
-        let builder = builder::from_module(module);
+    fn push_type(&mut self, func_type: FunctionType) -> MResult<u32> {
+        match self.module.type_section_mut() {
+            Some(section) => {
+                section.types_mut().push(Type::Function(func_type));
+                Ok(section.types().len() as u32 - 1)
+            }
+            None => {
+                let section = TypeSection::with_types(vec![Type::Function(func_type)]);
+                self.module.insert_section(Section::Type(section))?;
+                Ok(0)
+            }
+        }
+    }
 
-        Self {
-            module: builder.build(),
+    fn push_import(&mut self, import: ImportEntry) -> MResult<()> {
+        match self.module.import_section_mut() {
+            Some(section) => section.entries_mut().push(import),
+            None => {
+                let section = ImportSection::with_entries(vec![import]);
+                self.module.insert_section(Section::Import(section))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Increments all function indices that are greater or equal to the inserted one.
+    fn shift_function_indices(&mut self, inserted_index: u32) {
+        let shift = |index: &mut u32| {
+            if *index >= inserted_index {
+                *index += 1;
+            }
+        };
+
+        if let Some(section) = self.module.code_section_mut() {
+            for body in section.bodies_mut() {
+                for instruction in body.code_mut().elements_mut() {
+                    if let Instruction::Call(func_index) = instruction {
+                        shift(func_index);
+                    }
+                }
+            }
+        }
+
+        if let Some(section) = self.module.export_section_mut() {
+            for export in section.entries_mut() {
+                if let Internal::Function(func_index) = export.internal_mut() {
+                    shift(func_index);
+                }
+            }
+        }
+
+        if let Some(section) = self.module.elements_section_mut() {
+            for segment in section.entries_mut() {
+                segment.members_mut().iter_mut().for_each(shift);
+            }
+        }
+
+        if let Some(mut start_index) = self.module.start_section() {
+            shift(&mut start_index);
+            self.module.set_start_section(start_index);
         }
     }
 
@@ -71,10 +399,133 @@ impl<'a> ModuleBootstrapper {
     }
 }
 
+/// Returns the result type of an operation that could produce a NaN with non-deterministic bits.
+fn nan_producing_op_type(instruction: &Instruction) -> Option<ValueType> {
+    use Instruction::*;
+
+    match instruction {
+        F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Sqrt | F32Ceil | F32Floor
+        | F32Trunc | F32Nearest | F32DemoteF64 => Some(ValueType::F32),
+        F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Sqrt | F64Ceil | F64Floor
+        | F64Trunc | F64Nearest | F64PromoteF32 => Some(ValueType::F64),
+        _ => None,
+    }
+}
+
 /// Prepares a Wasm module:
 ///   - set memory page count
-pub(crate) fn prepare_module(module: &[u8], mem_pages_count: u32) -> MResult<Vec<u8>> {
+///   - canonicalize NaNs if the module is deterministic
+///   - inject fuel metering if it's enabled or the module is interruptible
+///   - report failed memory.grow instructions to the runtime
+///   - export mutable globals for snapshots
+pub(crate) fn prepare_module(
+    module: &[u8],
+    module_name: &str,
+    config: &MModuleConfig,
+) -> MResult<Vec<u8>> {
     ModuleBootstrapper::init(module)?
-        .set_mem_pages_count(mem_pages_count)
+        .set_mem_pages_count(module_name, config.mem_pages_count)?
+        .canonicalize_nans(config.deterministic)?
+        .inject_fuel_metering(config.fuel_metering || config.interruptible)?
+        .inject_memory_grow_hook()?
+        .export_mutable_globals()?
         .into_wasm()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleBootstrapper;
+    use super::CANONICAL_F32_NAN;
+    use super::CANONICAL_F64_NAN;
+
+    use parity_wasm::builder;
+    use parity_wasm::elements::Instruction::*;
+    use parity_wasm::elements::Instructions;
+    use parity_wasm::elements::ValueType;
+    use wasmer_runtime::Func;
+
+    #[test]
+    fn declared_memory_maximum_is_kept() {
+        let module = |maximum| {
+            let module = builder::module()
+                .memory()
+                .with_min(1)
+                .with_max(maximum)
+                .build()
+                .build();
+            parity_wasm::serialize(module).unwrap()
+        };
+        let prepared_maximum = |wasm: Vec<u8>| {
+            let prepared_wasm = ModuleBootstrapper::init(&wasm)
+                .and_then(|module| module.set_mem_pages_count("module", 100))
+                .and_then(|module| module.into_wasm())
+                .unwrap();
+            let module: parity_wasm::elements::Module =
+                parity_wasm::deserialize_buffer(&prepared_wasm).unwrap();
+            module.memory_section().unwrap().entries()[0]
+                .limits()
+                .maximum()
+        };
+
+        assert_eq!(prepared_maximum(module(Some(10))), Some(10));
+        assert_eq!(prepared_maximum(module(Some(1000))), Some(100));
+        assert_eq!(prepared_maximum(module(None)), Some(100));
+    }
+
+    #[test]
+    fn nans_are_canonicalized() {
+        // (func (export "div") (param f32 f32) (result f32) (f32.div (local.get 0) (local.get 1)))
+        // (func (export "sqrt") (param f64) (result f64) (f64.sqrt (local.get 0)))
+        let module = builder::module()
+            .function()
+            .signature()
+            .with_params(vec![ValueType::F32, ValueType::F32])
+            .with_return_type(Some(ValueType::F32))
+            .build()
+            .body()
+            .with_instructions(Instructions::new(vec![
+                GetLocal(0),
+                GetLocal(1),
+                F32Div,
+                End,
+            ]))
+            .build()
+            .build()
+            .function()
+            .signature()
+            .with_params(vec![ValueType::F64])
+            .with_return_type(Some(ValueType::F64))
+            .build()
+            .body()
+            .with_instructions(Instructions::new(vec![GetLocal(0), F64Sqrt, End]))
+            .build()
+            .build()
+            .export()
+            .field("div")
+            .internal()
+            .func(0)
+            .build()
+            .export()
+            .field("sqrt")
+            .internal()
+            .func(1)
+            .build()
+            .build();
+        let wasm = parity_wasm::serialize(module).unwrap();
+
+        let prepared_wasm = ModuleBootstrapper::init(&wasm)
+            .and_then(|module| module.canonicalize_nans(true))
+            .and_then(|module| module.into_wasm())
+            .unwrap();
+        let instance =
+            wasmer_runtime::instantiate(&prepared_wasm, &wasmer_runtime::imports! {}).unwrap();
+
+        let div: Func<'_, (f32, f32), f32> = instance.exports.get("div").unwrap();
+        assert_eq!(div.call(0.0, 0.0).unwrap().to_bits(), CANONICAL_F32_NAN);
+        assert_eq!(div.call(1.0, 2.0).unwrap(), 0.5);
+
+        let sqrt: Func<'_, f64, f64> = instance.exports.get("sqrt").unwrap();
+        assert_eq!(sqrt.call(-1.0).unwrap().to_bits(), CANONICAL_F64_NAN);
+        assert_eq!(sqrt.call(4.0).unwrap(), 2.0);
+    }
+}
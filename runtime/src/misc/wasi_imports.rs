@@ -0,0 +1,58 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use wasmer_runtime::ImportObject;
+
+/// Names of the namespaces under which WASI imports are provided for different WASI versions.
+pub(crate) const WASI_NAMESPACES: [&str; 2] = ["wasi_unstable", "wasi_snapshot_preview1"];
+
+/// WASI imports of a module instance along with imports that override some of them,
+/// e.g. with a virtual filesystem, disk quotas or stubs of disabled functions.
+pub(crate) struct WasiImports {
+    import_object: ImportObject,
+
+    // overrides are needed because ImportObject::extend doesn't really deep copy
+    // imports, so the instance should store them to prevent their removing.
+    overrides: Vec<ImportObject>,
+}
+
+impl WasiImports {
+    pub(crate) fn new(import_object: ImportObject) -> Self {
+        Self {
+            import_object,
+            overrides: vec![],
+        }
+    }
+
+    /// Replaces imports with the same names, later overrides take precedence over earlier ones.
+    pub(crate) fn override_with(&mut self, overrides: ImportObject) {
+        self.import_object.extend(overrides.clone());
+        self.overrides.push(overrides);
+    }
+
+    pub(crate) fn import_object(&self) -> &ImportObject {
+        &self.import_object
+    }
+
+    pub(crate) fn import_object_mut(&mut self) -> &mut ImportObject {
+        &mut self.import_object
+    }
+
+    /// Returns overrides that should live as long as the instance created with these imports.
+    pub(crate) fn into_overrides(self) -> Vec<ImportObject> {
+        self.overrides
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::IFunctionArg;
+use super::IType;
+use super::MRecordTypes;
+
+use std::collections::HashSet;
+
+/// Signature of a function imported by a module from another module.
+pub(super) struct ImportedFunction {
+    pub(super) arguments: Vec<IType>,
+    pub(super) outputs: Vec<IType>,
+}
+
+impl ImportedFunction {
+    /// Returns true if the export function with the provided arguments and outputs could be
+    /// called through this import, record types of the modules have their own ids,
+    /// so they are compared by names and fields.
+    pub(super) fn is_compatible_with(
+        &self,
+        export_arguments: &[IFunctionArg],
+        export_outputs: &[IType],
+        import_record_types: &MRecordTypes,
+        export_record_types: &MRecordTypes,
+    ) -> bool {
+        let mut checker = CompatibilityChecker {
+            import_record_types,
+            export_record_types,
+            visited_records: HashSet::new(),
+        };
+
+        let export_arguments = export_arguments.iter().map(|arg| &arg.ty);
+        checker.are_compatible(self.arguments.iter(), export_arguments)
+            && checker.are_compatible(self.outputs.iter(), export_outputs.iter())
+    }
+}
+
+struct CompatibilityChecker<'r> {
+    import_record_types: &'r MRecordTypes,
+    export_record_types: &'r MRecordTypes,
+    // pairs of already compared record ids, it allows recursive record types
+    visited_records: HashSet<(u64, u64)>,
+}
+
+impl CompatibilityChecker<'_> {
+    fn are_compatible<'a>(
+        &mut self,
+        import_types: impl ExactSizeIterator<Item = &'a IType>,
+        export_types: impl ExactSizeIterator<Item = &'a IType>,
+    ) -> bool {
+        import_types.len() == export_types.len()
+            && import_types
+                .zip(export_types)
+                .all(|(import_type, export_type)| self.is_compatible(import_type, export_type))
+    }
+
+    fn is_compatible(&mut self, import_type: &IType, export_type: &IType) -> bool {
+        match (import_type, export_type) {
+            (IType::Record(import_record_id), IType::Record(export_record_id)) => {
+                self.is_record_compatible(*import_record_id, *export_record_id)
+            }
+            (IType::Array(import_type), IType::Array(export_type)) => {
+                self.is_compatible(import_type, export_type)
+            }
+            (import_type, export_type) => import_type == export_type,
+        }
+    }
+
+    fn is_record_compatible(&mut self, import_record_id: u64, export_record_id: u64) -> bool {
+        if !self
+            .visited_records
+            .insert((import_record_id, export_record_id))
+        {
+            return true;
+        }
+
+        let (import_record, export_record) = match (
+            self.import_record_types.get(&import_record_id),
+            self.export_record_types.get(&export_record_id),
+        ) {
+            (Some(import_record), Some(export_record)) => (import_record, export_record),
+            _ => return false,
+        };
+
+        import_record.name == export_record.name
+            && import_record.fields.len() == export_record.fields.len()
+            && import_record
+                .fields
+                .iter()
+                .zip(export_record.fields.iter())
+                .all(|(import_field, export_field)| {
+                    import_field.name == export_field.name
+                        && self.is_compatible(&import_field.ty, &export_field.ty)
+                })
+    }
+}
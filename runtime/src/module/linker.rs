@@ -0,0 +1,135 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::imported_function::ImportedFunction;
+use super::MRecordTypes;
+use super::MModulePool;
+use crate::MError;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+// calls could be made concurrently from several threads, so each one tracks its own failure
+thread_local!(static LINK_FAILURE: RefCell<Option<MError>> = RefCell::new(None));
+
+/// Keeps all modules loaded into Marine, imports of IT modules are resolved through it
+/// on their first call, so modules could be loaded in any order.
+#[derive(Clone, Default)]
+pub(crate) struct Linker {
+    inner: Arc<Mutex<LinkerInner>>,
+}
+
+#[derive(Default)]
+struct LinkerInner {
+    /// Loaded modules by their names.
+    modules: HashMap<String, Arc<MModulePool>>,
+}
+
+impl Linker {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes exports of the module available for imports of other modules.
+    pub(crate) fn register_module(&self, module_name: String, module: Arc<MModulePool>) {
+        self.inner
+            .lock()
+            .unwrap()
+            .modules
+            .insert(module_name, module);
+    }
+
+    /// Removes the module, imports already resolved to it become unresolved.
+    pub(crate) fn unregister_module(&self, module_name: &str) {
+        self.inner.lock().unwrap().modules.remove(module_name);
+    }
+
+    /// Returns a reference to the linker that is kept by imports, it's weak because the linker
+    /// itself keeps modules that these imports belong to.
+    pub(super) fn downgrade(&self) -> LinkerRef {
+        LinkerRef {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct LinkerRef {
+    inner: Weak<Mutex<LinkerInner>>,
+}
+
+impl LinkerRef {
+    /// Returns the module with the provided name if it exports the function with a signature
+    /// compatible with the import.
+    pub(super) fn resolve(
+        &self,
+        namespace: &str,
+        function_name: &str,
+        imported_function: &ImportedFunction,
+        import_record_types: &MRecordTypes,
+    ) -> Option<Arc<MModulePool>> {
+        // the linker could be dropped only along with Marine
+        let inner = self.inner.upgrade()?;
+        let inner = inner.lock().unwrap();
+
+        let module = inner.modules.get(namespace);
+        let signature = module.and_then(|module| {
+            module
+                .function_signatures()
+                .iter()
+                .find(|signature| signature.name.as_str() == function_name)
+        });
+
+        let error = match (module, signature) {
+            (Some(module), Some(signature))
+                if imported_function.is_compatible_with(
+                    &signature.arguments,
+                    &signature.outputs,
+                    import_record_types,
+                    module.record_types(),
+                ) =>
+            {
+                return Some(module.clone())
+            }
+            (Some(_), Some(_)) => MError::IncompatibleImport {
+                namespace: namespace.to_string(),
+                function_name: function_name.to_string(),
+            },
+            _ => MError::UnresolvedImport {
+                namespace: namespace.to_string(),
+                function_name: function_name.to_string(),
+            },
+        };
+        fail_call(error);
+
+        None
+    }
+}
+
+/// Makes the current call fail with the error even if the calling module handles
+/// the failure of the import.
+pub(super) fn fail_call(error: MError) {
+    LINK_FAILURE.with(|failure| *failure.borrow_mut() = Some(error));
+}
+
+/// Returns the last import failure of the current thread since the previous call
+/// of this function.
+pub(crate) fn take_link_failure() -> Option<MError> {
+    LINK_FAILURE.with(|failure| failure.borrow_mut().take())
+}
@@ -15,51 +15,88 @@
  */
 
 use super::wit_prelude::*;
+use super::wit_instance::ITInstanceTemplate;
 use super::MFunctionSignature;
 use super::MRecordTypes;
-use super::{IType, IRecordType, IFunctionArg, IValue, WValue};
+use super::imported_function::ImportedFunction;
+use super::Linker;
+use super::{IType, IFunctionArg, IValue, WValue};
 use crate::MResult;
 use crate::MModuleConfig;
+use crate::HostImportDescriptor;
+use crate::HostImportName;
+use crate::fuel::FuelCounter;
+use crate::call_trace::CallKind;
+use crate::call_trace::CallSpan;
+use crate::stats::SharedModuleStats;
+use crate::snapshot::GlobalValue;
+use crate::snapshot::MemoryImage;
+use crate::snapshot::ModuleSnapshot;
+use crate::vfs::VfsImage;
+use crate::vfs::VfsInstance;
+use crate::vfs::VirtualFs;
+use crate::misc::WasiImports;
 
 use marine_it_interfaces::MITInterfaces;
 use marine_it_parser::extract_it_from_module;
 use marine_utils::SharedString;
 use wasmer_core::Instance as WasmerInstance;
+use wasmer_core::Module as WasmerModule;
 use wasmer_core::import::Namespace;
-use wasmer_runtime::compile;
 use wasmer_runtime::ImportObject;
 use wasmer_it::interpreter::Interpreter;
 
 use std::collections::HashMap;
 use std::convert::TryInto;
-use std::mem::MaybeUninit;
 use std::sync::Arc;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
 use std::rc::Rc;
+use std::time::Instant;
 
 type ITInterpreter =
     Interpreter<ITInstance, ITExport, WITFunction, WITMemory, WITMemoryView<'static>>;
 
-#[derive(Clone)]
 pub(super) struct ITModuleFunc {
-    interpreter: Arc<ITInterpreter>,
-    pub(super) arguments: Rc<Vec<IFunctionArg>>,
-    pub(super) output_types: Rc<Vec<IType>>,
+    interpreter: ITInterpreter,
+    pub(super) arguments: Arc<Vec<IFunctionArg>>,
+    pub(super) output_types: Arc<Vec<IType>>,
 }
 
-#[derive(Clone)]
 pub(super) struct Callable {
-    pub(super) it_instance: Arc<ITInstance>,
+    pub(super) module_name: Arc<String>,
+    pub(super) function_name: Arc<String>,
     pub(super) it_module_func: ITModuleFunc,
+    pub(super) stats: SharedModuleStats,
 }
 
 impl Callable {
-    pub fn call(&mut self, args: &[IValue]) -> MResult<Vec<IValue>> {
+    pub fn call(&self, it_instance: &mut ITInstance, args: &[IValue]) -> MResult<Vec<IValue>> {
+        let span = CallSpan::enter(
+            CallKind::Module,
+            &self.module_name,
+            &self.function_name,
+            args,
+        );
+        let started = Instant::now();
+        let result = self.call_(it_instance, args);
+        self.stats.lock().unwrap().record_call(
+            &self.function_name,
+            started.elapsed(),
+            is_trap(&result),
+        );
+        span.exit(result.as_deref());
+
+        result
+    }
+
+    fn call_(&self, it_instance: &mut ITInstance, args: &[IValue]) -> MResult<Vec<IValue>> {
         use wasmer_it::interpreter::stack::Stackable;
 
         let result = self
             .it_module_func
             .interpreter
-            .run(args, Arc::make_mut(&mut self.it_instance))?
+            .run(args, it_instance)?
             .as_slice()
             .to_owned();
 
@@ -67,14 +104,39 @@ impl Callable {
     }
 }
 
-type ExportFunctions = HashMap<SharedString, Rc<Callable>>;
+// only failures of Wasm functions called by the adapter are traps, other IT errors
+// are caused by incorrect arguments or results
+fn is_trap<T>(result: &MResult<T>) -> bool {
+    use wasmer_it::errors::InstructionErrorKind;
+
+    matches!(
+        result,
+        Err(MError::ITInstructionError(error))
+            if matches!(error.error_kind, InstructionErrorKind::LocalOrImportCall { .. })
+    )
+}
+
+pub(super) type ExportFunctions = HashMap<SharedString, Callable>;
+
+/// Host imports shared between all instances of a module.
+pub(super) type SharedHostImports = HashMap<HostImportName, Arc<HostImportDescriptor>>;
+
+/// Resources shared between all instances of a module.
+pub(super) struct SharedResources {
+    pub(super) host_imports: SharedHostImports,
+    pub(super) raw_imports: ImportObject,
+    pub(super) stats: SharedModuleStats,
+    pub(super) vfs: Option<VirtualFs>,
+}
 
 pub(crate) struct MModule {
-    // wasmer_instance is needed because WITInstance contains dynamic functions
+    // IT instances created for calls contain dynamic functions of wasmer_instance
     // that internally keep pointer to it.
-    #[allow(unused)]
     wasmer_instance: Box<WasmerInstance>,
 
+    /// Parts of IT instances created for calls of this module.
+    it_instance: ITInstanceTemplate,
+
     // import_object is needed because ImportObject::extend doesn't really deep copy
     // imports, so we need to store imports of this module to prevent their removing.
     #[allow(unused)]
@@ -90,60 +152,180 @@ pub(crate) struct MModule {
     #[allow(unused)]
     host_closures_import_object: ImportObject,
 
+    // wasi_overrides are needed because ImportObject::extend doesn't really deep copy
+    // imports, so we need to store imports of this module to prevent their removing.
+    #[allow(unused)]
+    wasi_overrides: Vec<ImportObject>,
+
     // TODO: replace with dyn Trait
     export_funcs: ExportFunctions,
 
-    // TODO: save refs instead copying of a record types HashMap.
     /// Record types used in exported functions as arguments or return values.
     export_record_types: MRecordTypes,
+
+    /// Total pages count requested by the last failed memory.grow of this module,
+    /// a failed memory.grow always requests more than zero pages, so 0 means no failure.
+    memory_grow_failure: Arc<AtomicU32>,
+
+    /// Maximum number of Wasm memory pages that this module can use.
+    mem_pages_count: u32,
+
+    /// Functions imported by this module from other modules by their module names.
+    dependencies: HashMap<String, HashMap<String, ImportedFunction>>,
+
+    /// All record types of the module, signatures of imported functions refer to them.
+    record_types: Arc<MRecordTypes>,
+
+    /// Memory and globals of the module right after _start,
+    /// they are restored after every call if the module is in the reset after call mode.
+    initial_image: Option<MemoryImage>,
+
+    /// Statistics shared with other instances of the same module.
+    stats: SharedModuleStats,
+
+    /// Filesystem state of this instance if the module uses a virtual filesystem.
+    vfs: Option<Arc<VfsInstance>>,
 }
 
 impl MModule {
-    pub(crate) fn new(
+    /// Creates a new instance of the module, host imports of the config are ignored
+    /// in favor of the shared ones.
+    pub(super) fn new(
         name: &str,
-        wasm_bytes: &[u8],
-        config: MModuleConfig,
-        modules: &HashMap<String, MModule>,
+        wasmer_module: &WasmerModule,
+        config: &MModuleConfig,
+        shared: &SharedResources,
+        linker: &Linker,
+        fuel_counter: Arc<FuelCounter>,
     ) -> MResult<Self> {
-        let wasmer_module = compile(&wasm_bytes)?;
-        crate::misc::check_sdk_version(name, &wasmer_module)?;
+        crate::misc::check_sdk_version(name, wasmer_module)?;
 
-        let it = extract_it_from_module(&wasmer_module)?;
+        let it = extract_it_from_module(wasmer_module)?;
         crate::misc::check_it_version(name, &it.version)?;
 
         let mit = MITInterfaces::new(it);
-
-        let mut wit_instance = Arc::new_uninit();
-        let wit_import_object = Self::adjust_wit_imports(&mit, wit_instance.clone())?;
-        let raw_imports = config.raw_imports.clone();
-        let (wasi_import_object, host_closures_import_object) =
-            Self::create_import_objects(config, &mit, wit_import_object.clone())?;
-
-        let wasmer_instance = wasmer_module.instantiate(&wasi_import_object)?;
-        let it_instance = unsafe {
-            // get_mut_unchecked here is safe because currently only this modules have reference to
-            // it and the environment is single-threaded
-            *Arc::get_mut_unchecked(&mut wit_instance) =
-                MaybeUninit::new(ITInstance::new(&wasmer_instance, name, &mit, modules)?);
-            std::mem::transmute::<_, Arc<ITInstance>>(wit_instance)
+        let dependencies = Self::extract_dependencies(name, &mit)?;
+        let record_types = mit
+            .record_types()
+            .map(|(id, record_type)| (id, Arc::new(record_type.as_ref().clone())))
+            .collect::<MRecordTypes>();
+        let record_types = Arc::new(record_types);
+
+        let wit_import_object = Self::adjust_wit_imports(&mit)?;
+        let raw_imports = shared.raw_imports.clone();
+        let mem_pages_count = config.mem_pages_count;
+        let reset_after_call = config.reset_after_call;
+        let memory_grow_failure = Arc::new(AtomicU32::new(0));
+        let stats = shared.stats.clone();
+        let host_imports = Self::imported_host_imports(wasmer_module, &shared.host_imports);
+        let vfs = match &shared.vfs {
+            Some(vfs) => {
+                let preopened_dirs = config.wasi_mapped_dirs.keys().map(String::as_str).chain(
+                    config
+                        .wasi_preopened_files
+                        .iter()
+                        .filter_map(|path| path.to_str()),
+                );
+                Some(vfs.instance(preopened_dirs, config.deterministic)?)
+            }
+            None => None,
+        };
+        let (wasi_import_object, host_closures_import_object) = Self::create_import_objects(
+            config,
+            &host_imports,
+            raw_imports.clone(),
+            &record_types,
+            wit_import_object.clone(),
+            vfs.is_some(),
+            stats.clone(),
+        )?;
+
+        // overrides are applied in this order, so later ones see the earlier ones
+        let mut wasi_imports = WasiImports::new(wasi_import_object);
+        if config.deterministic {
+            let deterministic_imports = crate::deterministic::create_deterministic_wasi_imports(
+                wasi_imports.import_object(),
+            );
+            wasi_imports.override_with(deterministic_imports);
+        }
+        if let Some(vfs) = &vfs {
+            wasi_imports.override_with(crate::vfs::create_vfs_wasi_imports(vfs));
+        }
+        // files of a virtual filesystem aren't on the disk, so quotas are ignored for it
+        if vfs.is_none() && !config.wasi_disk_quotas.is_empty() {
+            wasi_imports.override_with(crate::disk_quota::create_disk_quota_wasi_imports(
+                config.wasi_version,
+                &config.wasi_disk_quotas,
+            ));
+        }
+        if let Some(capabilities_imports) = crate::wasi_capabilities::create_disabled_wasi_imports(
+            wasmer_module,
+            &config.wasi_capabilities,
+        ) {
+            wasi_imports.override_with(capabilities_imports);
+        }
+        // files of a virtual filesystem aren't on the disk, so read-only dirs are ignored for it,
+        // allowed calls are forwarded to the imports overridden so far
+        let read_only_forwarder = match vfs {
+            None if !config.wasi_read_only_dirs.is_empty() => {
+                let (read_only_forwarder, read_only_imports) =
+                    crate::read_only_dirs::create_read_only_wasi_imports(
+                        wasmer_module,
+                        &config.wasi_read_only_dirs,
+                        wasi_imports.import_object(),
+                    )?;
+                wasi_imports.override_with(read_only_imports);
+                Some(read_only_forwarder)
+            }
+            _ => None,
         };
 
-        let (export_funcs, export_record_types) = Self::instantiate_exports(&it_instance, &mit)?;
+        wasi_imports
+            .import_object_mut()
+            .extend(Self::create_hooks_import_object(
+                memory_grow_failure.clone(),
+                fuel_counter,
+            ));
+        Self::provide_env_memory(wasmer_module, wasi_imports.import_object_mut())?;
+
+        let wasmer_instance = wasmer_module.instantiate(wasi_imports.import_object())?;
+        if let Some(read_only_forwarder) = read_only_forwarder {
+            read_only_forwarder.attach(&wasmer_instance)?;
+        }
+        let it_instance =
+            ITInstanceTemplate::new(&wasmer_instance, &mit, linker, record_types.clone())?;
+
+        let (export_funcs, export_record_types) = Self::instantiate_exports(name, &mit, &stats)?;
 
-        // call _start to populate the WASI state of the module
+        // call _start to populate the WASI state of the module, it could call IT imports
         #[rustfmt::skip]
         if let Ok(start_func) = wasmer_instance.exports.get::<wasmer_runtime::Func<'_, (), ()>>("_start") {
-            start_func.call()?;
+            Self::with_it_instance(&it_instance, &wasmer_instance, |_| Ok(start_func.call()?))?;
         }
 
-        Ok(Self {
+        let mut module = Self {
             wasmer_instance: Box::new(wasmer_instance),
+            it_instance,
             it_import_object: wit_import_object,
             host_import_object: raw_imports,
             host_closures_import_object,
+            wasi_overrides: wasi_imports.into_overrides(),
             export_funcs,
             export_record_types,
-        })
+            memory_grow_failure,
+            mem_pages_count,
+            dependencies,
+            record_types,
+            initial_image: None,
+            stats,
+            vfs,
+        };
+
+        if reset_after_call {
+            module.initial_image = Some(module.capture_image()?);
+        }
+
+        Ok(module)
     }
 
     pub(crate) fn call(
@@ -152,15 +334,39 @@ impl MModule {
         function_name: &str,
         args: &[IValue],
     ) -> MResult<Vec<IValue>> {
-        self.export_funcs.get_mut(function_name).map_or_else(
-            || {
-                Err(MError::NoSuchFunction(
-                    module_name.to_string(),
-                    function_name.to_string(),
-                ))
-            },
-            |func| Rc::make_mut(func).call(args),
-        )
+        // a failure of a previous call could be already handled by the module itself
+        self.memory_grow_failure.store(0, Ordering::Relaxed);
+
+        let result = match self.export_funcs.get(function_name) {
+            Some(func) => {
+                Self::with_it_instance(&self.it_instance, &self.wasmer_instance, |it_instance| {
+                    func.call(it_instance, args)
+                })
+            }
+            None => Err(MError::NoSuchFunction(
+                module_name.to_string(),
+                function_name.to_string(),
+            )),
+        };
+
+        let result = match (result, self.memory_grow_failure.swap(0, Ordering::Relaxed)) {
+            (Err(_), requested) if requested != 0 => Err(MError::MemoryLimitExceeded {
+                module: module_name.to_string(),
+                requested,
+                limit: self.mem_pages_count,
+            }),
+            (result, _) => result,
+        };
+
+        let memory_pages = self.memory_pages();
+        self.stats.lock().unwrap().record_memory_pages(memory_pages);
+
+        // the state is reset even after a failed call, to not leak its data to the next one
+        if let Some(initial_image) = &self.initial_image {
+            self.apply_image(module_name, initial_image)?;
+        }
+
+        result
     }
 
     pub(crate) fn get_exports_signatures(&self) -> impl Iterator<Item = MFunctionSignature> + '_ {
@@ -173,51 +379,303 @@ impl MModule {
             })
     }
 
-    pub(crate) fn export_record_types(&self) -> &MRecordTypes {
-        &self.export_record_types
+    pub(crate) fn export_record_types(&self) -> MRecordTypes {
+        self.export_record_types.clone()
+    }
+
+    pub(crate) fn get_wasi_state(&self) -> &wasmer_wasi::state::WasiState {
+        // data of a context of a module instantiated with WASI imports is its WASI state,
+        // it's the same cast as in wasmer_wasi::state::get_wasi_state
+        unsafe { &*(self.wasmer_instance.context().data as *const wasmer_wasi::state::WasiState) }
+    }
+
+    pub(crate) fn snapshot(&mut self) -> MResult<ModuleSnapshot> {
+        let image = self.capture_image()?;
+        let wasi_state = self.get_wasi_state().freeze().ok_or_else(|| {
+            MError::IncorrectSnapshot(String::from("WASI state can't be serialized"))
+        })?;
+
+        let vfs = self.vfs.as_deref().map(VfsImage::capture);
+
+        Ok(ModuleSnapshot {
+            image,
+            wasi_state,
+            vfs,
+        })
     }
 
-    pub(crate) fn export_record_type_by_id(&self, record_type: u64) -> Option<&Rc<IRecordType>> {
-        self.export_record_types.get(&record_type)
+    pub(crate) fn restore(&mut self, module_name: &str, snapshot: &ModuleSnapshot) -> MResult<()> {
+        // check everything before changing the state to not leave the module half-restored
+        let wasi_state =
+            wasmer_wasi::state::WasiState::unfreeze(&snapshot.wasi_state).ok_or_else(|| {
+                MError::IncorrectSnapshot(String::from("WASI state can't be deserialized"))
+            })?;
+        if self.vfs.is_some() != snapshot.vfs.is_some() {
+            return Err(MError::IncorrectSnapshot(String::from(
+                "usage of a virtual filesystem by the module doesn't match the snapshot",
+            )));
+        }
+
+        self.apply_image(module_name, &snapshot.image)?;
+        if let (Some(vfs), Some(image)) = (&self.vfs, &snapshot.vfs) {
+            image.apply(vfs);
+        }
+
+        unsafe {
+            *wasmer_wasi::state::get_wasi_state(self.wasmer_instance.context_mut()) = wasi_state;
+        }
+
+        Ok(())
     }
 
-    pub(crate) fn get_wasi_state(&mut self) -> &wasmer_wasi::state::WasiState {
-        unsafe { wasmer_wasi::state::get_wasi_state(self.wasmer_instance.context_mut()) }
+    fn capture_image(&self) -> MResult<MemoryImage> {
+        use wasmer_core::export::Export;
+
+        let memory = match self.memory() {
+            Some(memory) => {
+                let view = memory.view::<u8>();
+                // it's safe because the memory isn't changed while it's copied
+                let bytes =
+                    unsafe { std::slice::from_raw_parts(view.as_ptr() as *const u8, view.len()) };
+                bytes.to_vec()
+            }
+            None => Vec::new(),
+        };
+
+        let globals = self
+            .wasmer_instance
+            .exports()
+            .filter(|(name, _)| name.starts_with(crate::misc::GLOBAL_EXPORT_PREFIX))
+            .filter_map(|(name, export)| match export {
+                Export::Global(global) => Some((name, global)),
+                _ => None,
+            })
+            .map(
+                |(name, global)| match GlobalValue::from_wvalue(global.get()) {
+                    Some(value) => Ok((name, value)),
+                    None => Err(MError::IncorrectSnapshot(format!(
+                        "global {} has a type that isn't supported by snapshots",
+                        name
+                    ))),
+                },
+            )
+            .collect::<MResult<Vec<_>>>()?;
+
+        Ok(MemoryImage { memory, globals })
     }
 
-    // TODO: change the cloning Callable behaviour after changes of Wasmer API
-    pub(super) fn get_callable(
-        &self,
+    fn apply_image(&self, module_name: &str, image: &MemoryImage) -> MResult<()> {
+        use wasmer_core::export::Export;
+        use wasmer_core::units::Pages;
+        use wasmer_core::units::WASM_PAGE_SIZE;
+
+        let globals = self
+            .wasmer_instance
+            .exports()
+            .filter_map(|(name, export)| match export {
+                Export::Global(global) => Some((name, global)),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        for (name, value) in image.globals.iter() {
+            let value = value.to_wvalue();
+            match globals.get(name) {
+                Some(global) if global.descriptor().ty == value.ty() => {}
+                _ => {
+                    return Err(MError::IncorrectSnapshot(format!(
+                        "module doesn't have a mutable global {} of type {:?}",
+                        name,
+                        value.ty()
+                    )))
+                }
+            }
+        }
+
+        if let Some(memory) = self.memory() {
+            let current_size = memory.size().bytes().0;
+            if image.memory.len() > current_size {
+                let delta = image.memory.len() - current_size;
+                let delta = (delta + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE;
+                let current_pages = memory.size().0;
+
+                memory
+                    .grow(Pages(delta as u32))
+                    .map_err(|_| MError::MemoryLimitExceeded {
+                        module: module_name.to_string(),
+                        requested: current_pages + delta as u32,
+                        limit: self.mem_pages_count,
+                    })?;
+            }
+
+            let view = memory.view::<u8>();
+            // it's safe because the environment is single-threaded and
+            // nothing else has access to the memory while it's overwritten
+            let bytes =
+                unsafe { std::slice::from_raw_parts_mut(view.as_ptr() as *mut u8, view.len()) };
+            let (image_part, rest) = bytes.split_at_mut(image.memory.len());
+            image_part.copy_from_slice(&image.memory);
+            // memory can't shrink, so the rest of it is zeroed
+            rest.iter_mut().for_each(|byte| *byte = 0);
+        } else if !image.memory.is_empty() {
+            return Err(MError::IncorrectSnapshot(String::from(
+                "module doesn't have a memory",
+            )));
+        }
+
+        for (name, value) in image.globals.iter() {
+            globals[name].set(value.to_wvalue());
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn memory_pages(&self) -> u32 {
+        self.memory().map_or(0, |memory| memory.size().0)
+    }
+
+    fn memory(&self) -> Option<&wasmer_core::memory::Memory> {
+        let module = self.wasmer_instance.module();
+        let module_info = module.info();
+        if module_info.memories.is_empty() && module_info.imported_memories.is_empty() {
+            return None;
+        }
+
+        Some(self.wasmer_instance.context().memory(0))
+    }
+
+    pub(crate) fn dependencies(&self) -> impl Iterator<Item = &str> {
+        self.dependencies.keys().map(String::as_str)
+    }
+
+    /// Returns the first function imported by this module from a module with given name,
+    /// that the provided module doesn't export with a compatible signature.
+    pub(crate) fn find_unresolved_import<'a>(
+        &'a self,
         module_name: &str,
-        function_name: &str,
-    ) -> MResult<Rc<Callable>> {
-        match self.export_funcs.get(function_name) {
-            Some(func) => Ok(func.clone()),
-            None => Err(MError::NoSuchFunction(
-                module_name.to_string(),
-                function_name.to_string(),
-            )),
+        exporting_module: &MModule,
+    ) -> Option<&'a str> {
+        let functions = self.dependencies.get(module_name)?;
+
+        functions
+            .iter()
+            .find(|(function_name, imported_function)| {
+                match exporting_module.export_funcs.get(function_name.as_str()) {
+                    Some(callable) => !imported_function.is_compatible_with(
+                        &callable.it_module_func.arguments,
+                        &callable.it_module_func.output_types,
+                        &self.record_types,
+                        &exporting_module.export_record_types,
+                    ),
+                    None => true,
+                }
+            })
+            .map(|(function_name, _)| function_name.as_str())
+    }
+
+    // returns imports that are resolved to other modules by the linker grouped by namespaces
+    fn extract_dependencies(
+        name: &str,
+        mit: &MITInterfaces<'_>,
+    ) -> MResult<HashMap<String, HashMap<String, ImportedFunction>>> {
+        use wasmer_it::ast::Type;
+
+        let imports = mit
+            .imports()
+            .filter(|import| {
+                mit.adapter_types_by_core_type(import.function_type)
+                    .is_some()
+            })
+            .filter(|import| import.namespace != name);
+
+        let mut dependencies = HashMap::new();
+        for import in imports {
+            // the same type is used for imports resolved by the linker, see ITInstance
+            let imported_function = match mit.type_by_idx_r(import.function_type - 2)? {
+                Type::Function {
+                    arguments,
+                    output_types,
+                } => ImportedFunction {
+                    arguments: arguments.iter().map(|arg| arg.ty.clone()).collect(),
+                    outputs: output_types.as_ref().clone(),
+                },
+                ty => {
+                    return Err(MError::IncorrectWIT(format!(
+                        "IT should has Type::Function, but {:?} met",
+                        ty
+                    )))
+                }
+            };
+
+            dependencies
+                .entry(import.namespace.to_string())
+                .or_insert_with(HashMap::new)
+                .insert(import.name.to_string(), imported_function);
         }
+
+        Ok(dependencies)
     }
 
+    // host imports that aren't imported by the module are never called,
+    // so their record types aren't required to be in the module
+    fn imported_host_imports(
+        wasmer_module: &WasmerModule,
+        host_imports: &SharedHostImports,
+    ) -> SharedHostImports {
+        let module_info = wasmer_module.info();
+        module_info
+            .imported_functions
+            .values()
+            .filter_map(|import_name| {
+                let namespace = module_info.namespace_table.get(import_name.namespace_index);
+                let name = module_info.name_table.get(import_name.name_index);
+
+                host_imports.get_key_value(&HostImportName::new(namespace, name))
+            })
+            .map(|(import, descriptor)| (import.clone(), descriptor.clone()))
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn create_import_objects(
-        config: MModuleConfig,
-        mit: &MITInterfaces<'_>,
+        config: &MModuleConfig,
+        host_imports: &SharedHostImports,
+        raw_imports: ImportObject,
+        record_types: &Arc<MRecordTypes>,
         wit_import_object: ImportObject,
+        has_vfs: bool,
+        stats: SharedModuleStats,
     ) -> MResult<(ImportObject, ImportObject)> {
         use crate::host_imports::create_host_import_func;
+        use crate::host_imports::resolve_host_import_types;
 
+        let capabilities = &config.wasi_capabilities;
         let wasi_envs = config
             .wasi_envs
-            .into_iter()
-            .map(|(mut left, right)| {
-                left.push(61); // 61 is ASCII code of '='
-                left.extend(right);
-                left
+            .iter()
+            // environment variables aren't provided to modules without the environ capability
+            .filter(|_| capabilities.environ)
+            .map(|(left, right)| {
+                let mut env = left.clone();
+                env.push(61); // 61 is ASCII code of '='
+                env.extend(right);
+                env
             })
             .collect::<Vec<_>>();
-        let wasi_preopened_files = config.wasi_preopened_files.into_iter().collect::<Vec<_>>();
-        let wasi_mapped_dirs = config.wasi_mapped_dirs.into_iter().collect::<Vec<_>>();
+        // modules with a virtual filesystem or without the filesystem capability
+        // don't have access to host directories at all
+        let (wasi_preopened_files, wasi_mapped_dirs) = if has_vfs || !capabilities.filesystem {
+            (vec![], vec![])
+        } else {
+            (
+                config.wasi_preopened_files.iter().cloned().collect(),
+                config
+                    .wasi_mapped_dirs
+                    .iter()
+                    .map(|(alias, path)| (alias.clone(), path.clone()))
+                    .collect(),
+            )
+        };
 
         let mut wasi_import_object = wasmer_wasi::generate_import_object_for_version(
             config.wasi_version,
@@ -228,32 +686,131 @@ impl MModule {
         )
         .map_err(MError::WASIPrepareError)?;
 
-        let mut host_closures_namespace = Namespace::new();
-        let record_types = mit
-            .record_types()
-            .map(|(id, r)| (id, r.clone()))
-            .collect::<HashMap<_, _>>();
-        let record_types = Rc::new(record_types);
-
-        for (import_name, descriptor) in config.host_imports {
-            let host_import = create_host_import_func(descriptor, record_types.clone());
-            host_closures_namespace.insert(import_name, host_import);
+        let mut host_closures_namespaces = HashMap::new();
+        for (import, descriptor) in host_imports {
+            let types = resolve_host_import_types(&import.to_string(), descriptor, record_types)?;
+            let host_import = create_host_import_func(
+                import.clone(),
+                descriptor.clone(),
+                types,
+                record_types.clone(),
+                stats.clone(),
+            );
+            host_closures_namespaces
+                .entry(import.namespace.clone())
+                .or_insert_with(Namespace::new)
+                .insert(import.name.clone(), host_import);
         }
+
         let mut host_closures_import_object = ImportObject::new();
-        host_closures_import_object.register("host", host_closures_namespace);
+        for (namespace, host_closures_namespace) in host_closures_namespaces {
+            host_closures_import_object.register(namespace, host_closures_namespace);
+        }
 
         wasi_import_object.extend(wit_import_object);
-        wasi_import_object.extend(config.raw_imports);
+        wasi_import_object.extend(raw_imports);
         wasi_import_object.extend(host_closures_import_object.clone());
 
         Ok((wasi_import_object, host_closures_import_object))
     }
 
+    // creates imports that are injected into the module by misc::prepare_module
+    fn create_hooks_import_object(
+        memory_grow_failure: Arc<AtomicU32>,
+        fuel_counter: Arc<FuelCounter>,
+    ) -> ImportObject {
+        use wasmer_core::vm::Ctx;
+        use wasmer_runtime::func;
+
+        let memory_grow_hook = move |ctx: &mut Ctx, delta: i32| {
+            let current_pages = ctx.memory(0).size().0;
+            let requested = current_pages.saturating_add(delta as u32);
+            memory_grow_failure.store(requested, Ordering::Relaxed);
+        };
+
+        // returning an error from this function traps the module
+        let fuel_hook = move |_: &mut Ctx, amount: i32| -> Result<(), &'static str> {
+            fuel_counter
+                .consume(amount as u32 as u64)
+                .map_err(|_| "fuel is exhausted")?;
+
+            if crate::interrupt::is_current_call_interrupted_lazy() {
+                return Err("call is interrupted");
+            }
+
+            Ok(())
+        };
+
+        let mut namespace = Namespace::new();
+        namespace.insert(crate::misc::MEMORY_GROW_HOOK_NAME, func!(memory_grow_hook));
+        namespace.insert(crate::misc::FUEL_HOOK_NAME, func!(fuel_hook));
+
+        let mut import_object = ImportObject::new();
+        import_object.register(crate::misc::HOOKS_NAMESPACE, namespace);
+
+        import_object
+    }
+
+    // modules that import their memory from env receive it from Marine, because the limit
+    // set by misc::prepare_module is enforced only if the memory is created with it
+    fn provide_env_memory(
+        wasmer_module: &WasmerModule,
+        import_object: &mut ImportObject,
+    ) -> MResult<()> {
+        use wasmer_core::memory::Memory;
+
+        let is_provided = import_object
+            .maybe_with_namespace("env", |env| env.get_export("memory"))
+            .is_some();
+        if is_provided {
+            return Ok(());
+        }
+
+        let module_info = wasmer_module.info();
+        let descriptor = module_info
+            .imported_memories
+            .values()
+            .find(|(import_name, _)| {
+                module_info.namespace_table.get(import_name.namespace_index) == "env"
+                    && module_info.name_table.get(import_name.name_index) == "memory"
+            })
+            .map(|(_, descriptor)| *descriptor);
+
+        let descriptor = match descriptor {
+            Some(descriptor) => descriptor,
+            None => return Ok(()),
+        };
+
+        let mut namespace = Namespace::new();
+        namespace.insert("memory", Memory::new(descriptor)?);
+
+        let mut env_import_object = ImportObject::new();
+        env_import_object.register("env", namespace);
+        import_object.extend(env_import_object);
+
+        Ok(())
+    }
+
+    // runs the function with an IT instance created for it, the instance is active during the run,
+    // so adapters of IT imports called by the module use it too
+    fn with_it_instance<T>(
+        template: &ITInstanceTemplate,
+        wasmer_instance: &WasmerInstance,
+        func: impl FnOnce(&mut ITInstance) -> MResult<T>,
+    ) -> MResult<T> {
+        let it_instance = Rc::new(ITInstance::new(template, wasmer_instance)?);
+        let _guard = it_instance.activate();
+
+        func(&mut it_instance.as_ref().clone())
+    }
+
     fn instantiate_exports(
-        it_instance: &Arc<ITInstance>,
+        module_name: &str,
         mit: &MITInterfaces<'_>,
+        stats: &SharedModuleStats,
     ) -> MResult<(ExportFunctions, MRecordTypes)> {
         let module_interface = marine_module_interface::it_interface::get_interface(mit)?;
+        let module_name = Arc::new(module_name.to_string());
 
         let export_funcs = module_interface
             .function_signatures
@@ -262,30 +819,35 @@ impl MModule {
                 let adapter_instructions = mit.adapter_by_type_r(sign.adapter_function_type)?;
 
                 let interpreter: ITInterpreter = adapter_instructions.clone().try_into()?;
+                let function_name = Arc::new(sign.name.to_string());
                 let it_module_func = ITModuleFunc {
-                    interpreter: Arc::new(interpreter),
-                    arguments: sign.arguments.clone(),
-                    output_types: sign.outputs.clone(),
+                    interpreter,
+                    arguments: Arc::new(sign.arguments.as_ref().clone()),
+                    output_types: Arc::new(sign.outputs.as_ref().clone()),
                 };
 
-                let shared_string = SharedString(sign.name);
-                let callable = Rc::new(Callable {
-                    it_instance: it_instance.clone(),
+                let callable = Callable {
+                    module_name: module_name.clone(),
+                    function_name: function_name.clone(),
                     it_module_func,
-                });
+                    stats: stats.clone(),
+                };
 
-                Ok((shared_string, callable))
+                Ok((SharedString(function_name), callable))
             })
             .collect::<MResult<ExportFunctions>>()?;
 
-        Ok((export_funcs, module_interface.export_record_types))
+        let export_record_types = module_interface
+            .export_record_types
+            .iter()
+            .map(|(id, record_type)| (*id, Arc::new(record_type.as_ref().clone())))
+            .collect();
+
+        Ok((export_funcs, export_record_types))
     }
 
     // this function deals only with import functions that have an adaptor implementation
-    fn adjust_wit_imports(
-        wit: &MITInterfaces<'_>,
-        wit_instance: Arc<MaybeUninit<ITInstance>>,
-    ) -> MResult<ImportObject> {
+    fn adjust_wit_imports(wit: &MITInterfaces<'_>) -> MResult<ImportObject> {
         use marine_it_interfaces::ITAstType;
         use wasmer_core::typed_func::DynamicFunc;
         use wasmer_core::vm::Ctx;
@@ -309,7 +871,6 @@ impl MModule {
 
         // creates a closure that is represent a IT module import
         fn create_raw_import(
-            wit_instance: Arc<MaybeUninit<ITInstance>>,
             interpreter: ITInterpreter,
             import_namespace: String,
             import_name: String,
@@ -327,16 +888,21 @@ impl MModule {
                     inputs
                 );
 
-                // copy here because otherwise wit_instance will be consumed by the closure
-                let wit_instance_callable = wit_instance.clone();
-                let wit_inputs = inputs.iter().map(wval_to_ival).collect::<Vec<_>>();
-                let outputs = unsafe {
-                    // error here will be propagated by the special error instruction
-                    interpreter.run(
-                        &wit_inputs,
-                        Arc::make_mut(&mut wit_instance_callable.assume_init()),
-                    )
+                // imports could be called only by the module that is being called
+                let mut wit_instance_callable = match ITInstance::active() {
+                    Some(wit_instance) => wit_instance.as_ref().clone(),
+                    None => {
+                        log::error!(
+                            "raw import for {}.{} called outside of a module call",
+                            import_namespace,
+                            import_name
+                        );
+                        return vec![];
+                    }
                 };
+                let wit_inputs = inputs.iter().map(wval_to_ival).collect::<Vec<_>>();
+                // error here will be propagated by the special error instruction
+                let outputs = interpreter.run(&wit_inputs, &mut wit_instance_callable);
 
                 log::trace!(
                     "\nraw import for {}.{} finished",
@@ -378,7 +944,6 @@ impl MModule {
                         let interpreter: ITInterpreter = adapter_instructions.clone().try_into()?;
 
                         let raw_import = create_raw_import(
-                            wit_instance.clone(),
                             interpreter,
                             import_namespace.to_string(),
                             import_name.to_string(),
@@ -15,8 +15,11 @@
  */
 
 mod exports;
+mod imported_function;
+mod linker;
 mod marine_module;
 mod memory;
+mod module_pool;
 mod wit_function;
 mod wit_instance;
 mod type_converters;
@@ -31,17 +34,20 @@ pub use wasmer_it::to_interface_value;
 
 use serde::Serialize;
 use serde::Deserialize;
-use std::rc::Rc;
+use std::sync::Arc;
 
 /// Represent a function type inside Marine module.
 #[derive(PartialEq, Eq, Debug, Clone, Hash, Serialize, Deserialize)]
 pub struct MFunctionSignature {
-    pub name: Rc<String>,
-    pub arguments: Rc<Vec<IFunctionArg>>,
-    pub outputs: Rc<Vec<IType>>,
+    pub name: Arc<String>,
+    pub arguments: Arc<Vec<IFunctionArg>>,
+    pub outputs: Arc<Vec<IType>>,
 }
 
 pub(crate) use marine_module::MModule;
+pub(crate) use module_pool::MModulePool;
+pub(crate) use linker::Linker;
+pub(crate) use linker::take_link_failure;
 pub(self) use wasmer_core::types::Type as WType;
 pub(self) use wasmer_core::types::Value as WValue;
 
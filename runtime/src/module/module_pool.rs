@@ -0,0 +1,278 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::marine_module::SharedHostImports;
+use super::marine_module::SharedResources;
+use super::IValue;
+use super::Linker;
+use super::MFunctionSignature;
+use super::MModule;
+use super::MRecordTypes;
+use crate::fuel::FuelCounter;
+use crate::stats::ModuleStats;
+use crate::vfs::VirtualFs;
+use crate::MModuleConfig;
+use crate::MError;
+use crate::MResult;
+use crate::ModuleSnapshot;
+
+use wasmer_core::Module as WasmerModule;
+use wasmer_wasi::WasiVersion;
+
+use std::cell::RefCell;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::sync::TryLockError;
+
+thread_local!(static LOCKED_INSTANCES: RefCell<Vec<usize>> = RefCell::new(Vec::new()));
+
+/// Pre-instantiated copies of a module, each one is locked by the call made on it, so
+/// calls from several threads run concurrently on different copies. A call is handed to
+/// a free copy, or waits for one if all of them are busy. Copies of a pool with more than
+/// one instance are reset after every call, so they are indistinguishable for callers.
+/// Metadata and WASI state are served by the first copy. A virtual filesystem, if any,
+/// is shared by all copies.
+pub(crate) struct MModulePool {
+    name: String,
+    instances: Vec<Mutex<MModule>>,
+    next_instance: AtomicUsize,
+    shared: SharedResources,
+    function_signatures: Vec<MFunctionSignature>,
+    record_types: MRecordTypes,
+    dependencies: Vec<String>,
+}
+
+impl MModulePool {
+    pub(crate) fn new(
+        name: &str,
+        wasmer_module: WasmerModule,
+        mut config: MModuleConfig,
+        linker: &Linker,
+        fuel_counter: Arc<FuelCounter>,
+    ) -> MResult<Self> {
+        let pool_size = std::cmp::max(config.pool_size, 1);
+        // state of one copy mustn't be visible through another one
+        config.reset_after_call |= pool_size > 1;
+
+        let host_imports = std::mem::take(&mut config.host_imports)
+            .into_iter()
+            .map(|(import_name, descriptor)| (import_name, Arc::new(descriptor)))
+            .collect::<SharedHostImports>();
+        let raw_imports = std::mem::take(&mut config.raw_imports).into_import_object();
+
+        if config.wasi_vfs.is_some() && config.wasi_version == WasiVersion::Snapshot0 {
+            return Err(MError::VfsError(String::from(
+                "virtual filesystem requires WASI snapshot1",
+            )));
+        }
+        let vfs = config.wasi_vfs.as_ref().map(VirtualFs::new).transpose()?;
+
+        let shared = SharedResources {
+            host_imports,
+            raw_imports,
+            stats: Arc::new(Mutex::new(ModuleStats::default())),
+            vfs,
+        };
+        let instances = (0..pool_size)
+            .map(|_| {
+                MModule::new(
+                    name,
+                    &wasmer_module,
+                    &config,
+                    &shared,
+                    linker,
+                    fuel_counter.clone(),
+                )
+            })
+            .collect::<MResult<Vec<_>>>()?;
+
+        // all copies have the same exports and imports
+        let function_signatures = instances[0].get_exports_signatures().collect();
+        let record_types = instances[0].export_record_types();
+        let dependencies = instances[0]
+            .dependencies()
+            .map(ToString::to_string)
+            .collect();
+
+        let pool = Self {
+            name: name.to_string(),
+            instances: instances.into_iter().map(Mutex::new).collect(),
+            next_instance: AtomicUsize::new(0),
+            shared,
+            function_signatures,
+            record_types,
+            dependencies,
+        };
+        // _start of instances could change the filesystem
+        pool.persist_vfs()?;
+
+        Ok(pool)
+    }
+
+    pub(crate) fn call(&self, function_name: &str, args: &[IValue]) -> MResult<Vec<IValue>> {
+        let mut instance = self.lock_for_call()?;
+
+        let result = instance.module.call(&self.name, function_name, args);
+        // even a failed call could change the filesystem
+        self.persist_vfs()?;
+
+        result
+    }
+
+    /// Returns the copy that represents the whole pool, it mustn't be held during calls.
+    pub(crate) fn primary(&self) -> MutexGuard<'_, MModule> {
+        lock(&self.instances[0])
+    }
+
+    pub(crate) fn function_signatures(&self) -> &[MFunctionSignature] {
+        &self.function_signatures
+    }
+
+    pub(crate) fn record_types(&self) -> &MRecordTypes {
+        &self.record_types
+    }
+
+    pub(crate) fn has_function(&self, function_name: &str) -> bool {
+        self.function_signatures
+            .iter()
+            .any(|signature| signature.name.as_str() == function_name)
+    }
+
+    /// Returns names of modules which functions are imported by this one.
+    pub(crate) fn dependencies(&self) -> impl Iterator<Item = &str> {
+        self.dependencies.iter().map(String::as_str)
+    }
+
+    /// Returns namespaces from which host imports are provided to this module.
+    pub(crate) fn host_import_namespaces(&self) -> impl Iterator<Item = &str> {
+        self.shared
+            .host_imports
+            .keys()
+            .map(|import| import.namespace.as_str())
+    }
+
+    /// Returns statistics of all copies, the memory of the biggest copy is reported.
+    pub(crate) fn stats(&self) -> ModuleStats {
+        let memory_pages = self
+            .instances
+            .iter()
+            .map(|instance| lock(instance).memory_pages())
+            .max()
+            .unwrap_or_default();
+
+        let mut stats = lock(&self.shared.stats).clone();
+        stats.memory_pages = memory_pages;
+        stats.record_memory_pages(memory_pages);
+
+        stats
+    }
+
+    pub(crate) fn record_trap(&self) {
+        lock(&self.shared.stats).traps_count += 1;
+    }
+
+    pub(crate) fn snapshot(&self) -> MResult<ModuleSnapshot> {
+        self.primary().snapshot()
+    }
+
+    /// Restores all copies from the snapshot, the first failure stops restoring.
+    pub(crate) fn restore(&self, snapshot: &ModuleSnapshot) -> MResult<()> {
+        self.instances
+            .iter()
+            .try_for_each(|instance| lock(instance).restore(&self.name, snapshot))?;
+
+        self.persist_vfs()
+    }
+
+    // a module could be called through imports of other modules, so a thread could try
+    // to lock an instance it already holds, such instances are skipped, because waiting
+    // for them would never finish
+    fn lock_for_call(&self) -> MResult<LockedInstance<'_>> {
+        // copies are tried in turn starting from the next one to spread calls between them
+        let first_id = self.next_instance.fetch_add(1, Ordering::Relaxed);
+        let candidates = (0..self.instances.len())
+            .map(|shift| &self.instances[(first_id + shift) % self.instances.len()])
+            .filter(|instance| !is_locked_by_current_thread(instance))
+            .collect::<Vec<_>>();
+
+        let free_instance = candidates.iter().find_map(|&instance| {
+            let module = match instance.try_lock() {
+                Ok(module) => module,
+                Err(TryLockError::Poisoned(error)) => error.into_inner(),
+                Err(TryLockError::WouldBlock) => return None,
+            };
+            Some(LockedInstance::new(instance, module))
+        });
+        if let Some(instance) = free_instance {
+            return Ok(instance);
+        }
+
+        // all copies are busy, so the call waits for one that is held by another thread
+        match candidates.first() {
+            Some(&instance) => Ok(LockedInstance::new(instance, lock(instance))),
+            None => Err(MError::CyclicCall(self.name.clone())),
+        }
+    }
+
+    fn persist_vfs(&self) -> MResult<()> {
+        self.shared.vfs.as_ref().map_or(Ok(()), VirtualFs::persist)
+    }
+}
+
+// panics of host closures are caught, but other ones, e.g. of host import error handlers, unwind
+// through a call and poison the lock of the called instance, the instance stays usable as after
+// a trap, so the poisoning is ignored instead of failing all next calls
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn instance_address(instance: &Mutex<MModule>) -> usize {
+    instance as *const Mutex<MModule> as usize
+}
+
+fn is_locked_by_current_thread(instance: &Mutex<MModule>) -> bool {
+    let address = instance_address(instance);
+    LOCKED_INSTANCES.with(|locked| locked.borrow().contains(&address))
+}
+
+/// Instance locked by the current thread for a call.
+struct LockedInstance<'pool> {
+    module: MutexGuard<'pool, MModule>,
+    address: usize,
+}
+
+impl<'pool> LockedInstance<'pool> {
+    fn new(instance: &'pool Mutex<MModule>, module: MutexGuard<'pool, MModule>) -> Self {
+        let address = instance_address(instance);
+        LOCKED_INSTANCES.with(|locked| locked.borrow_mut().push(address));
+
+        Self { module, address }
+    }
+}
+
+impl Drop for LockedInstance<'_> {
+    fn drop(&mut self) {
+        LOCKED_INSTANCES.with(|locked| {
+            locked
+                .borrow_mut()
+                .retain(|&address| address != self.address)
+        });
+    }
+}
@@ -14,40 +14,47 @@
  * limitations under the License.
  */
 
-use super::marine_module::MModule;
 use super::{IType, IFunctionArg, IValue, WValue};
-use super::marine_module::Callable;
-use crate::MResult;
+use super::linker::LinkerRef;
+use super::linker::fail_call;
+use super::imported_function::ImportedFunction;
+use super::MRecordTypes;
+use super::MModulePool;
+use crate::MError;
 
 use wasmer_it::interpreter::wasm;
 use wasmer_core::instance::DynFunc;
 
-// use std::sync::Arc;
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
 
-#[derive(Clone)]
-enum WITFunctionInner {
-    Export {
-        func: Rc<DynFunc<'static>>,
-    },
-    Import {
-        // TODO: use dyn Callable here
-        callable: Rc<Callable>,
-    },
+/// Name and signature of an IT function, unlike WITFunction it doesn't refer to the Wasmer
+/// instance, so it's kept by a module instance between calls.
+pub(super) struct WITFunctionInfo {
+    name: String,
+    arguments: Vec<IFunctionArg>,
+    outputs: Vec<IType>,
+    kind: WITFunctionKind,
 }
 
-/// Represents all import and export functions that could be called from IT context by call-core.
-#[derive(Clone)]
-pub(super) struct WITFunction {
-    name: String,
-    arguments: Rc<Vec<IFunctionArg>>,
-    outputs: Rc<Vec<IType>>,
-    inner: WITFunctionInner,
+enum WITFunctionKind {
+    Export,
+    Import {
+        linker: LinkerRef,
+        namespace: String,
+        signature: ImportedFunction,
+        // record types of the importing module, the signature refers to them
+        record_types: Arc<MRecordTypes>,
+        // it's weak to not prevent the imported module from unloading
+        module: Mutex<Weak<MModulePool>>,
+    },
 }
 
-impl WITFunction {
-    /// Creates functions from a "usual" (not IT) module export.
-    pub(super) fn from_export(dyn_func: DynFunc<'static>, name: String) -> MResult<Self> {
+impl WITFunctionInfo {
+    /// Creates info of a "usual" (not IT) module export from its Wasmer signature.
+    pub(super) fn from_export(dyn_func: &DynFunc<'_>, name: String) -> Self {
         use super::type_converters::wtype_to_itype;
 
         let signature = dyn_func.signature();
@@ -66,77 +73,140 @@ impl WITFunction {
             .map(wtype_to_itype)
             .collect::<Vec<_>>();
 
-        let inner = WITFunctionInner::Export {
-            func: Rc::new(dyn_func),
-        };
-
-        let arguments = Rc::new(arguments);
-        let outputs = Rc::new(outputs);
-
-        Ok(Self {
+        Self {
             name,
             arguments,
             outputs,
-            inner,
-        })
+            kind: WITFunctionKind::Export,
+        }
     }
 
-    /// Creates function from a module import.
+    /// Creates info of a module import, it's resolved by the linker on the first call.
     pub(super) fn from_import(
-        wit_module: &MModule,
-        module_name: &str,
+        linker: LinkerRef,
+        namespace: &str,
         function_name: &str,
-        arguments: Rc<Vec<IFunctionArg>>,
-        outputs: Rc<Vec<IType>>,
-    ) -> MResult<Self> {
-        let callable = wit_module.get_callable(module_name, function_name)?;
-
-        let inner = WITFunctionInner::Import { callable };
+        arguments: Vec<IFunctionArg>,
+        outputs: Vec<IType>,
+        record_types: Arc<MRecordTypes>,
+    ) -> Self {
+        let signature = ImportedFunction {
+            arguments: arguments.iter().map(|arg| arg.ty.clone()).collect(),
+            outputs: outputs.clone(),
+        };
 
-        let name = function_name.to_string();
+        let kind = WITFunctionKind::Import {
+            linker,
+            namespace: namespace.to_string(),
+            signature,
+            record_types,
+            module: Mutex::new(Weak::new()),
+        };
 
-        Ok(Self {
-            name,
+        Self {
+            name: function_name.to_string(),
             arguments,
             outputs,
-            inner,
-        })
+            kind,
+        }
+    }
+
+    pub(super) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(super) fn is_export(&self) -> bool {
+        matches!(self.kind, WITFunctionKind::Export)
+    }
+
+    fn resolve_import(&self) -> Option<Arc<MModulePool>> {
+        let (linker, namespace, signature, record_types, module) = match &self.kind {
+            WITFunctionKind::Import {
+                linker,
+                namespace,
+                signature,
+                record_types,
+                module,
+            } => (linker, namespace, signature, record_types, module),
+            WITFunctionKind::Export => return None,
+        };
+
+        // a poisoned lock could keep only a dropped module, so it's safe to use
+        let mut module = module
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(module) = module.upgrade() {
+            return Some(module);
+        }
+
+        let resolved = linker.resolve(namespace, &self.name, signature, record_types)?;
+        *module = Arc::downgrade(&resolved);
+
+        Some(resolved)
+    }
+}
+
+/// Represents all import and export functions that could be called from IT context by call-core.
+#[derive(Clone)]
+pub(super) struct WITFunction {
+    info: Arc<WITFunctionInfo>,
+    // only exports have it, it refers to the Wasmer instance, so it's obtained for every call
+    export: Option<Rc<DynFunc<'static>>>,
+}
+
+impl WITFunction {
+    /// Creates function from a "usual" (not IT) module export.
+    pub(super) fn from_export(info: Arc<WITFunctionInfo>, dyn_func: DynFunc<'static>) -> Self {
+        Self {
+            info,
+            export: Some(Rc::new(dyn_func)),
+        }
+    }
+
+    /// Creates function from a module import.
+    pub(super) fn from_import(info: Arc<WITFunctionInfo>) -> Self {
+        Self { info, export: None }
     }
 }
 
 impl wasm::structures::LocalImport for WITFunction {
     fn name(&self) -> &str {
-        self.name.as_str()
+        self.info.name.as_str()
     }
 
     fn inputs_cardinality(&self) -> usize {
-        self.arguments.len()
+        self.info.arguments.len()
     }
 
     fn outputs_cardinality(&self) -> usize {
-        self.outputs.len()
+        self.info.outputs.len()
     }
 
     fn arguments(&self) -> &[IFunctionArg] {
-        &self.arguments
+        &self.info.arguments
     }
 
     fn outputs(&self) -> &[IType] {
-        &self.outputs
+        &self.info.outputs
     }
 
     fn call(&self, arguments: &[IValue]) -> std::result::Result<Vec<IValue>, ()> {
         use super::type_converters::{ival_to_wval, wval_to_ival};
 
-        match &self.inner {
-            WITFunctionInner::Export { func, .. } => func
-                .as_ref()
+        match &self.export {
+            Some(func) => func
                 .call(&arguments.iter().map(ival_to_wval).collect::<Vec<WValue>>())
                 .map(|result| result.iter().map(wval_to_ival).collect())
                 .map_err(|_| ()),
-            WITFunctionInner::Import { callable, .. } => Rc::make_mut(&mut callable.clone())
-                .call(arguments)
-                .map_err(|_| ()),
+            None => {
+                let module = self.info.resolve_import().ok_or(())?;
+                module.call(&self.info.name, arguments).map_err(|e| {
+                    // the calling module could ignore the failed import, but not a cycle
+                    if let MError::CyclicCall(_) = e {
+                        fail_call(e);
+                    }
+                })
+            }
         }
     }
 }
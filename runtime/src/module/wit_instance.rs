@@ -15,62 +15,71 @@
  */
 
 use super::wit_prelude::*;
-use super::marine_module::MModule;
+use super::wit_function::WITFunctionInfo;
 use super::IRecordType;
+use super::Linker;
 use crate::MResult;
 
 use marine_it_interfaces::MITInterfaces;
-use marine_it_interfaces::ITAstType;
 use wasmer_it::interpreter::wasm;
 use wasmer_it::interpreter::wasm::structures::{LocalImportIndex, TypedIndex};
 use wasmer_core::Instance as WasmerInstance;
+use once_cell::unsync::OnceCell;
 
 use std::collections::HashMap;
 use std::cell::Cell;
+use std::cell::RefCell;
 use std::rc::Rc;
+use std::sync::Arc;
 
-pub type MRecordTypes = HashMap<u64, Rc<IRecordType>>;
+pub type MRecordTypes = HashMap<u64, Arc<IRecordType>>;
 
-/// Contains all import and export functions that could be called from IT context by call-core.
-#[derive(Clone)]
-pub(super) struct ITInstance {
-    /// IT functions indexed by id.
-    funcs: HashMap<usize, WITFunction>,
+/// Record types in the form required by wasmer-it, they can't be sent to another thread,
+/// so they are created only for a call.
+type ITRecordTypes = HashMap<u64, Rc<IRecordType>>;
+
+// adapters of IT imports are called by Wasm code, so they find the instance of the module
+// that is being called here, calls of other modules push their own instances on top of it
+thread_local!(static ACTIVE_INSTANCES: RefCell<Vec<Rc<ITInstance>>> = RefCell::new(Vec::new()));
+
+/// Parts of an IT instance that don't refer to the Wasmer instance, so they are kept
+/// by a module instance and could be sent to another thread along with it.
+pub(super) struct ITInstanceTemplate {
+    /// Signatures of IT functions indexed by id.
+    funcs: HashMap<usize, Arc<WITFunctionInfo>>,
 
     /// IT memories.
     memories: Vec<WITMemory>,
 
     /// All record types that instance contains.
-    record_types_by_id: MRecordTypes,
+    record_types: Arc<MRecordTypes>,
 }
 
-impl ITInstance {
+impl ITInstanceTemplate {
     pub(super) fn new(
         wasmer_instance: &WasmerInstance,
-        module_name: &str,
         wit: &MITInterfaces<'_>,
-        modules: &HashMap<String, MModule>,
+        linker: &Linker,
+        record_types: Arc<MRecordTypes>,
     ) -> MResult<Self> {
-        let mut exports = Self::extract_raw_exports(&wasmer_instance, wit)?;
-        let imports = Self::extract_imports(module_name, modules, wit, exports.len())?;
-        let memories = Self::extract_memories(&wasmer_instance);
+        let mut exports = Self::extract_raw_exports(wasmer_instance, wit)?;
+        let imports = Self::extract_imports(linker, wit, exports.len(), record_types.clone())?;
+        let memories = Self::extract_memories(wasmer_instance);
 
         exports.extend(imports);
         let funcs = exports;
 
-        let record_types_by_id = Self::extract_record_types(wit);
-
         Ok(Self {
             funcs,
             memories,
-            record_types_by_id,
+            record_types,
         })
     }
 
     fn extract_raw_exports(
         wasmer_instance: &WasmerInstance,
         it: &MITInterfaces<'_>,
-    ) -> MResult<HashMap<usize, WITFunction>> {
+    ) -> MResult<HashMap<usize, Arc<WITFunctionInfo>>> {
         use wasmer_core::DynFunc;
 
         let module_exports = &wasmer_instance.exports;
@@ -78,61 +87,52 @@ impl ITInstance {
         it.exports()
             .enumerate()
             .map(|(export_id, export)| {
-                let export_func = module_exports.get(export.name)?;
-                unsafe {
-                    // TODO: refactor this with new Wasmer API when it is ready
-                    // here it is safe because dyn func is never lives WITInstance
-                    let export_func =
-                        std::mem::transmute::<DynFunc<'_>, DynFunc<'static>>(export_func);
-                    Ok((
-                        export_id,
-                        WITFunction::from_export(export_func, export.name.to_string())?,
-                    ))
-                }
+                let export_func: DynFunc<'_> = module_exports.get(export.name)?;
+                let info = WITFunctionInfo::from_export(&export_func, export.name.to_string());
+
+                Ok((export_id, Arc::new(info)))
             })
             .collect()
     }
 
-    /// Extracts only those imports that don't have implementations.
+    /// Extracts only those imports that don't have implementations,
+    /// they are resolved by the linker on the first call.
     fn extract_imports(
-        module_name: &str,
-        modules: &HashMap<String, MModule>,
+        linker: &Linker,
         wit: &MITInterfaces<'_>,
         start_index: usize,
-    ) -> MResult<HashMap<usize, WITFunction>> {
+        record_types: Arc<MRecordTypes>,
+    ) -> MResult<HashMap<usize, Arc<WITFunctionInfo>>> {
         wit.imports()
             .filter(|import|
                 // filter out imports that have implementations
                 matches!(wit.adapter_types_by_core_type(import.function_type), Some(_)))
             .enumerate()
-            .map(|(idx, import)| match modules.get(import.namespace) {
-                Some(module) => {
-                    use wasmer_it::ast::Type;
-                    let (arguments, output_types) =
-                        match wit.type_by_idx_r(import.function_type - 2)? {
-                            Type::Function {
-                                arguments,
-                                output_types,
-                            } => (arguments.clone(), output_types.clone()),
-                            ty => {
-                                return Err(MError::IncorrectWIT(format!(
-                                    "IT should has Type::Function, but {:?} met",
-                                    ty
-                                )))
-                            }
-                        };
-
-                    let func = WITFunction::from_import(
-                        module,
-                        module_name,
-                        import.name,
+            .map(|(idx, import)| {
+                use wasmer_it::ast::Type;
+                let (arguments, output_types) = match wit.type_by_idx_r(import.function_type - 2)? {
+                    Type::Function {
                         arguments,
                         output_types,
-                    )?;
+                    } => (arguments.as_ref().clone(), output_types.as_ref().clone()),
+                    ty => {
+                        return Err(MError::IncorrectWIT(format!(
+                            "IT should has Type::Function, but {:?} met",
+                            ty
+                        )))
+                    }
+                };
 
-                    Ok((start_index + idx as usize, func))
-                }
-                None => Err(MError::NoSuchModule(import.namespace.to_string())),
+                let info = WITFunctionInfo::from_import(
+                    linker.downgrade(),
+                    import.namespace,
+                    import.name,
+                    arguments,
+                    output_types,
+                    record_types.clone(),
+                );
+
+                Ok((start_index + idx as usize, Arc::new(info)))
             })
             .collect::<MResult<HashMap<_, _>>>()
     }
@@ -157,22 +157,81 @@ impl ITInstance {
 
         memories
     }
+}
 
-    fn extract_record_types(wit: &MITInterfaces<'_>) -> MRecordTypes {
-        let (record_types_by_id, _) = wit.types().fold(
-            (HashMap::new(), 0u64),
-            |(mut record_types_by_id, id), ty| {
-                match ty {
-                    ITAstType::Record(record_type) => {
-                        record_types_by_id.insert(id, record_type.clone());
-                    }
-                    ITAstType::Function { .. } => {}
-                };
-                (record_types_by_id, id + 1)
-            },
-        );
+/// Contains all import and export functions that could be called from IT context by call-core.
+/// It's created from a template for a call, because functions exported by the Wasmer instance
+/// and record types of wasmer-it can't be sent to another thread.
+#[derive(Clone)]
+pub(super) struct ITInstance {
+    /// IT functions indexed by id.
+    funcs: HashMap<usize, WITFunction>,
+
+    /// IT memories.
+    memories: Vec<WITMemory>,
+
+    /// All record types that instance contains.
+    record_types: Arc<MRecordTypes>,
+
+    /// Record types in the form required by wasmer-it, they are created on the first use
+    /// and shared by clones of the instance made during the call.
+    record_types_by_id: Rc<OnceCell<ITRecordTypes>>,
+}
+
+impl ITInstance {
+    /// Creates an instance of the template, the Wasmer instance must be the one the template
+    /// has been created with, and it must outlive the returned instance.
+    pub(super) fn new(
+        template: &ITInstanceTemplate,
+        wasmer_instance: &WasmerInstance,
+    ) -> MResult<Self> {
+        use wasmer_core::DynFunc;
+
+        let module_exports = &wasmer_instance.exports;
+        let funcs = template
+            .funcs
+            .iter()
+            .map(|(id, info)| {
+                if !info.is_export() {
+                    return Ok((*id, WITFunction::from_import(info.clone())));
+                }
+
+                let export_func: DynFunc<'_> = module_exports.get(info.name())?;
+                // TODO: refactor this with new Wasmer API when it is ready
+                // here it is safe because the IT instance never outlives the Wasmer one
+                let export_func =
+                    unsafe { std::mem::transmute::<DynFunc<'_>, DynFunc<'static>>(export_func) };
+                Ok((*id, WITFunction::from_export(info.clone(), export_func)))
+            })
+            .collect::<MResult<_>>()?;
 
-        record_types_by_id
+        Ok(Self {
+            funcs,
+            memories: template.memories.clone(),
+            record_types: template.record_types.clone(),
+            record_types_by_id: Rc::new(OnceCell::new()),
+        })
+    }
+
+    /// Makes the instance active on the current thread until the returned guard is dropped.
+    pub(super) fn activate(self: &Rc<Self>) -> ActiveInstanceGuard {
+        ACTIVE_INSTANCES.with(|instances| instances.borrow_mut().push(self.clone()));
+
+        ActiveInstanceGuard {}
+    }
+
+    /// Returns the instance of the module that is being called on the current thread.
+    pub(super) fn active() -> Option<Rc<Self>> {
+        ACTIVE_INSTANCES.with(|instances| instances.borrow().last().cloned())
+    }
+}
+
+/// Keeps an IT instance active on the current thread.
+pub(super) struct ActiveInstanceGuard {}
+
+impl Drop for ActiveInstanceGuard {
+    fn drop(&mut self) {
+        ACTIVE_INSTANCES.with(|instances| instances.borrow_mut().pop());
     }
 }
 
@@ -215,6 +274,13 @@ impl wasm::structures::Instance<ITExport, WITFunction, WITMemory, WITMemoryView<
     }
 
     fn wit_record_by_id(&self, index: u64) -> Option<&Rc<IRecordType>> {
-        self.record_types_by_id.get(&index)
+        self.record_types_by_id
+            .get_or_init(|| {
+                self.record_types
+                    .iter()
+                    .map(|(id, record_type)| (*id, Rc::new(record_type.as_ref().clone())))
+                    .collect()
+            })
+            .get(&index)
     }
 }
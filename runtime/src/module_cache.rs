@@ -0,0 +1,199 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::MError;
+use crate::MResult;
+
+use wasmer_core::cache::Artifact;
+use wasmer_core::cache::WasmHash;
+use wasmer_core::Module as WasmerModule;
+use wasmer_runtime::Backend;
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+// size of a hex encoded blake3 hash
+const CHECKSUM_SIZE: usize = 64;
+
+// prefix of names of versioned dirs, only such dirs are removed from the cache dir,
+// so unrelated data isn't lost if the cache dir is shared with something else
+const VERSIONED_DIR_PREFIX: &str = "marine-";
+
+// makes names of temporary files unique among writes of one process
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// On-disk cache of compiled Wasm modules used by Marine::load_module to skip compilation.
+///
+/// Entries are keyed by a hash of the prepared Wasm bytes, that already include memory limits
+/// and injected instrumentation, and stored in a subdirectory specific to the runtime version
+/// and the compiler backend. Each entry contains a checksum of the compiled artifact,
+/// corrupted entries are removed and the module is compiled again.
+#[derive(Clone, Debug)]
+pub struct ModuleCache {
+    dir: PathBuf,
+}
+
+impl ModuleCache {
+    /// Creates a cache in the provided directory, the directory is created if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> MResult<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| cache_error(&dir, e))?;
+
+        Ok(Self { dir })
+    }
+
+    /// Returns the directory where the cache is stored.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Removes all entries created by other versions of the runtime or other compiler backends.
+    /// Returns the count of removed entries. Other files in the cache dir are left intact.
+    pub fn prune_outdated(&self) -> MResult<usize> {
+        let current_dir_name = Self::versioned_dir_name();
+        self.prune_dirs(|dir_name| dir_name != current_dir_name)
+    }
+
+    /// Removes all entries from the cache. Returns the count of removed entries.
+    /// Other files in the cache dir are left intact.
+    pub fn clear(&self) -> MResult<usize> {
+        self.prune_dirs(|_| true)
+    }
+
+    /// Loads a compiled module from the cache, or compiles it and stores to the cache.
+    /// Errors occurred while accessing the cache aren't fatal, they are only logged.
+    pub(crate) fn load_or_compile(&self, wasm_bytes: &[u8]) -> MResult<WasmerModule> {
+        let entry_path = self.entry_path(wasm_bytes);
+
+        match self.load(&entry_path) {
+            Ok(Some(module)) => return Ok(module),
+            Ok(None) => {}
+            Err(e) => {
+                log::warn!(
+                    "cache entry {:?} is corrupted and will be removed: {}",
+                    entry_path,
+                    e
+                );
+                if let Err(e) = fs::remove_file(&entry_path) {
+                    log::warn!("failed to remove cache entry {:?}: {}", entry_path, e);
+                }
+            }
+        }
+
+        let module = wasmer_runtime::compile(wasm_bytes)?;
+        if let Err(e) = self.store(&entry_path, &module) {
+            log::warn!("failed to store cache entry {:?}: {}", entry_path, e);
+        }
+
+        Ok(module)
+    }
+
+    fn load(&self, entry_path: &Path) -> Result<Option<WasmerModule>, String> {
+        let entry = match fs::read(entry_path) {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        if entry.len() < CHECKSUM_SIZE {
+            return Err(String::from("entry is too short"));
+        }
+
+        let (checksum, artifact_bytes) = entry.split_at(CHECKSUM_SIZE);
+        if WasmHash::generate(artifact_bytes).encode().as_bytes() != checksum {
+            return Err(String::from("checksum mismatch"));
+        }
+
+        let artifact = Artifact::deserialize(artifact_bytes).map_err(|e| format!("{:?}", e))?;
+        let backend = Backend::default();
+        let compiler = wasmer_runtime::compiler_for_backend(backend)
+            .ok_or_else(|| format!("backend {} isn't supported", backend.to_string()))?;
+
+        // it's safe, because the artifact has been created by this version of the runtime
+        // and its checksum has been checked above
+        let module = unsafe { wasmer_core::load_cache_with(artifact, compiler.as_ref()) }
+            .map_err(|e| format!("{:?}", e))?;
+
+        Ok(Some(module))
+    }
+
+    fn store(&self, entry_path: &Path, module: &WasmerModule) -> Result<(), String> {
+        let artifact_bytes = module
+            .cache()
+            .and_then(|artifact| artifact.serialize())
+            .map_err(|e| format!("{:?}", e))?;
+
+        let mut entry = WasmHash::generate(&artifact_bytes).encode().into_bytes();
+        entry.extend(artifact_bytes);
+
+        // write to a temporary file first to not leave a partially written entry
+        let entry_dir = entry_path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(entry_dir).map_err(|e| e.to_string())?;
+        let temp_path = entry_path.with_extension(format!(
+            "tmp{}-{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, entry).map_err(|e| e.to_string())?;
+        fs::rename(&temp_path, entry_path).map_err(|e| e.to_string())
+    }
+
+    fn entry_path(&self, wasm_bytes: &[u8]) -> PathBuf {
+        self.dir
+            .join(Self::versioned_dir_name())
+            .join(WasmHash::generate(wasm_bytes).encode())
+    }
+
+    fn versioned_dir_name() -> String {
+        format!(
+            "{}{}-wasmer-{}-{}",
+            VERSIONED_DIR_PREFIX,
+            env!("CARGO_PKG_VERSION"),
+            wasmer_core::VERSION,
+            Backend::default().to_string()
+        )
+    }
+
+    fn prune_dirs(&self, should_remove: impl Fn(&str) -> bool) -> MResult<usize> {
+        let mut removed_entries = 0;
+
+        for dir in fs::read_dir(&self.dir).map_err(|e| cache_error(&self.dir, e))? {
+            let dir = dir.map_err(|e| cache_error(&self.dir, e))?.path();
+            let dir_name = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            let is_versioned = dir_name.starts_with(VERSIONED_DIR_PREFIX);
+            if !dir.is_dir() || !is_versioned || !should_remove(dir_name) {
+                continue;
+            }
+
+            removed_entries += fs::read_dir(&dir)
+                .map_err(|e| cache_error(&dir, e))?
+                .count();
+            fs::remove_dir_all(&dir).map_err(|e| cache_error(&dir, e))?;
+        }
+
+        Ok(removed_entries)
+    }
+}
+
+fn cache_error(path: &Path, error: std::io::Error) -> MError {
+    MError::ModuleCacheError(format!("{:?}: {}", path, error))
+}
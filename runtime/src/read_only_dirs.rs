@@ -0,0 +1,497 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::misc::WASI_NAMESPACES;
+use crate::MResult;
+
+use once_cell::sync::OnceCell;
+use parity_wasm::elements::CodeSection;
+use parity_wasm::elements::External;
+use parity_wasm::elements::ExportEntry;
+use parity_wasm::elements::ExportSection;
+use parity_wasm::elements::Func;
+use parity_wasm::elements::FuncBody;
+use parity_wasm::elements::FunctionSection;
+use parity_wasm::elements::FunctionType;
+use parity_wasm::elements::ImportEntry;
+use parity_wasm::elements::ImportSection;
+use parity_wasm::elements::Instruction;
+use parity_wasm::elements::Instructions;
+use parity_wasm::elements::Internal;
+use parity_wasm::elements::MemoryType;
+use parity_wasm::elements::Section;
+use parity_wasm::elements::Type;
+use parity_wasm::elements::TypeSection;
+use parity_wasm::elements::ValueType;
+use wasmer_core::error::CallError;
+use wasmer_core::error::RuntimeError;
+use wasmer_core::export::Export;
+use wasmer_core::import::ImportObject;
+use wasmer_core::import::Namespace;
+use wasmer_core::typed_func::DynamicFunc;
+use wasmer_core::types::FuncSig;
+use wasmer_core::types::Type as WType;
+use wasmer_core::types::Value as WValue;
+use wasmer_core::vm::Ctx;
+use wasmer_core::Instance as WasmerInstance;
+use wasmer_core::Module as WasmerModule;
+use wasmer_wasi::state::get_wasi_state;
+use wasmer_wasi::state::Kind;
+use wasmer_wasi::state::WasiFs;
+use wasmer_wasi::types::*;
+
+use std::collections::HashSet;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+type Errno = __wasi_errno_t;
+
+// WASI functions that could modify files or resolve a path
+const GUARDED_WASI_FUNCTIONS: [&str; 13] = [
+    "fd_write",
+    "fd_pwrite",
+    "fd_allocate",
+    "fd_filestat_set_size",
+    "fd_filestat_set_times",
+    "path_open",
+    "path_create_directory",
+    "path_remove_directory",
+    "path_unlink_file",
+    "path_rename",
+    "path_link",
+    "path_symlink",
+    "path_filestat_set_times",
+];
+
+// rights that allow an opened file to be modified
+const WRITE_RIGHTS: __wasi_rights_t =
+    __WASI_RIGHT_FD_WRITE | __WASI_RIGHT_FD_ALLOCATE | __WASI_RIGHT_FD_FILESTAT_SET_SIZE;
+
+/// Host directories and files that a module could only read. Paths are compared canonicalized,
+/// so neither symlinks nor `..` lead to a writable alias of a read-only file, and a path that
+/// can't be resolved to a file inside one of the preopened dirs of the module is rejected.
+///
+/// Calls that pass the checks are forwarded to the overridden WASI imports through
+/// a forwarder, an instance of a module that only reexports them. Imported host functions
+/// could be called only by an instance that imports them, and the forwarder shares memory
+/// and the WASI state with the guarded instance, so they see the same files.
+pub(crate) struct ReadOnlyDirs {
+    dirs: Vec<PathBuf>,
+    // canonicalized host paths of preopened dirs, they don't change after instantiation
+    preopened_dirs: OnceCell<Vec<PathBuf>>,
+    // Wasmer instances aren't Sync, it's locked only by calls of the guarded instance
+    forwarder: OnceCell<Mutex<WasmerInstance>>,
+}
+
+/// The forwarder of read-only dirs that isn't instantiated yet.
+pub(crate) struct DetachedForwarder {
+    dirs: Arc<ReadOnlyDirs>,
+    forwarder_module: WasmerModule,
+    // overridden imports by their namespaces and names
+    originals: Vec<(String, &'static str, Export)>,
+}
+
+impl DetachedForwarder {
+    /// Instantiates the forwarder with memory and the WASI state of the guarded instance,
+    /// it should be called right after the instance is created.
+    pub(crate) fn attach(self, instance: &WasmerInstance) -> MResult<()> {
+        let mut forwarder_imports = ImportObject::new();
+        for &namespace_name in WASI_NAMESPACES.iter() {
+            let mut namespace = Namespace::new();
+            for (_, name, original) in self.originals.iter().filter(|o| o.0 == namespace_name) {
+                namespace.insert(*name, original.clone());
+            }
+            forwarder_imports.register(namespace_name, namespace);
+        }
+        let mut env = Namespace::new();
+        env.insert(
+            "memory",
+            Export::Memory(instance.context().memory(0).clone()),
+        );
+        forwarder_imports.register("env", env);
+
+        let mut forwarder = self.forwarder_module.instantiate(&forwarder_imports)?;
+        // the forwarder is created without a WASI state, so there is nothing to finalize
+        forwarder.context_mut().data = instance.context().data;
+        // the cell is set only here, and the forwarder is attached only once
+        let _ = self.dirs.forwarder.set(Mutex::new(forwarder));
+
+        Ok(())
+    }
+}
+
+impl ReadOnlyDirs {
+    fn new(dirs: &HashSet<PathBuf>) -> Self {
+        let dirs = dirs
+            .iter()
+            .map(|dir| std::fs::canonicalize(dir).unwrap_or_else(|_| dir.clone()))
+            .collect();
+
+        Self {
+            dirs,
+            preopened_dirs: OnceCell::new(),
+            forwarder: OnceCell::new(),
+        }
+    }
+
+    fn check(&self, ctx: &mut Ctx, function_name: &str, args: &[WValue]) -> Errno {
+        let arg = |index| u32_arg(args, index);
+
+        match function_name {
+            "fd_write"
+            | "fd_pwrite"
+            | "fd_allocate"
+            | "fd_filestat_set_size"
+            | "fd_filestat_set_times" => self.check_fd(ctx, arg(0)),
+            "path_open" => {
+                let o_flags = arg(4) as __wasi_oflags_t;
+                let rights = u64_arg(args, 5);
+                let fs_flags = arg(7) as __wasi_fdflags_t;
+                let writes = o_flags & (__WASI_O_CREAT | __WASI_O_TRUNC) != 0
+                    || fs_flags & __WASI_FDFLAG_APPEND != 0
+                    || rights & WRITE_RIGHTS != 0;
+
+                self.check_path(ctx, arg(0), arg(1), arg(2), arg(3), writes)
+            }
+            "path_create_directory" | "path_remove_directory" | "path_unlink_file" => {
+                self.check_path(ctx, arg(0), 0, arg(1), arg(2), true)
+            }
+            "path_filestat_set_times" => self.check_path(ctx, arg(0), arg(1), arg(2), arg(3), true),
+            "path_rename" => match self.check_path(ctx, arg(0), 0, arg(1), arg(2), true) {
+                __WASI_ESUCCESS => self.check_path(ctx, arg(3), 0, arg(4), arg(5), true),
+                errno => errno,
+            },
+            // a hard link to a read-only file would allow to modify it
+            "path_link" => match self.check_path(ctx, arg(0), arg(1), arg(2), arg(3), true) {
+                __WASI_ESUCCESS => self.check_path(ctx, arg(4), 0, arg(5), arg(6), true),
+                errno => errno,
+            },
+            "path_symlink" => self.check_path(ctx, arg(2), 0, arg(3), arg(4), true),
+            _ => __WASI_ENOTCAPABLE,
+        }
+    }
+
+    /// Checks the file opened as the provided fd.
+    fn check_fd(&self, ctx: &mut Ctx, fd: u32) -> Errno {
+        let fs = unsafe { &get_wasi_state(ctx).fs };
+        let kind = match fs.fd_map.get(&fd) {
+            Some(fd) => &fs.inodes[fd.inode].kind,
+            None => return __WASI_EBADF,
+        };
+
+        match kind {
+            // stdio isn't on the disk
+            Kind::File { fd: Some(_), .. } => __WASI_ESUCCESS,
+            // the file has been checked while it was opened, but it could be moved since then
+            Kind::File { path, .. } | Kind::Dir { path, .. } => {
+                self.check_writable(&canonical_path(path, true))
+            }
+            _ => __WASI_ENOTCAPABLE,
+        }
+    }
+
+    /// Checks the file at the path relative to the provided fd.
+    fn check_path(
+        &self,
+        ctx: &mut Ctx,
+        fd: u32,
+        lookup_flags: __wasi_lookupflags_t,
+        path: u32,
+        path_len: u32,
+        writes: bool,
+    ) -> Errno {
+        let path = match read_str(ctx, path, path_len) {
+            Ok(path) => path,
+            Err(errno) => return errno,
+        };
+        let fs = unsafe { &get_wasi_state(ctx).fs };
+
+        let host_path = match resolve_host_path(fs, fd, &path) {
+            Some(host_path) => host_path,
+            None => return __WASI_ENOTCAPABLE,
+        };
+        let follow_symlinks = lookup_flags & __WASI_LOOKUP_SYMLINK_FOLLOW != 0;
+        let host_path = canonical_path(&host_path, follow_symlinks);
+
+        let preopened_dirs = self.preopened_dirs.get_or_init(|| preopened_dirs(fs));
+        if !preopened_dirs.iter().any(|dir| host_path.starts_with(dir)) {
+            return __WASI_ENOTCAPABLE;
+        }
+
+        if writes {
+            self.check_writable(&host_path)
+        } else {
+            __WASI_ESUCCESS
+        }
+    }
+
+    fn check_writable(&self, canonical_path: &Path) -> Errno {
+        if self.dirs.iter().any(|dir| canonical_path.starts_with(dir)) {
+            __WASI_EROFS
+        } else {
+            __WASI_ESUCCESS
+        }
+    }
+
+    fn forward(&self, ctx: &mut Ctx, function_name: &str, args: &[WValue]) -> Vec<WValue> {
+        let forwarder = match self.forwarder.get() {
+            // a poisoned lock means only that a previous call has panicked
+            Some(forwarder) => forwarder
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+            None => return vec![WValue::I32(__WASI_ENOTCAPABLE as i32)],
+        };
+
+        match forwarder.call(function_name, args) {
+            Ok(results) => results,
+            // the forwarded import has trapped, so should the guarded instance
+            Err(CallError::Runtime(error)) => unsafe {
+                (*ctx.module).runnable_module.do_early_trap(error)
+            },
+            Err(CallError::Resolve(error)) => unsafe {
+                let error = RuntimeError::User(Box::new(error.to_string()));
+                (*ctx.module).runnable_module.do_early_trap(error)
+            },
+        }
+    }
+}
+
+/// Creates WASI imports that fail calls modifying files in read-only dirs with EROFS
+/// and calls with paths leading outside of preopened dirs with ENOTCAPABLE. Other calls
+/// are forwarded to the imports of the WASI import object, the returned forwarder
+/// should be attached to the instance.
+pub(crate) fn create_read_only_wasi_imports(
+    wasmer_module: &WasmerModule,
+    read_only_dirs: &HashSet<PathBuf>,
+    wasi_import_object: &ImportObject,
+) -> MResult<(DetachedForwarder, ImportObject)> {
+    let module_info = wasmer_module.info();
+
+    let mut guarded = Vec::new();
+    for (func_index, import_name) in module_info.imported_functions.iter() {
+        let namespace = module_info.namespace_table.get(import_name.namespace_index);
+        let name = module_info.name_table.get(import_name.name_index);
+        let name = match GUARDED_WASI_FUNCTIONS
+            .iter()
+            .find(|&&guarded| guarded == name)
+        {
+            Some(name) if WASI_NAMESPACES.contains(&namespace) => *name,
+            _ => continue,
+        };
+        let original = match wasi_import_object
+            .maybe_with_namespace(namespace, |namespace| namespace.get_export(name))
+        {
+            Some(original) => original,
+            None => continue,
+        };
+
+        let sig_index = module_info.func_assoc[func_index.convert_up(module_info)];
+        let signature = module_info.signatures[sig_index].clone();
+        guarded.push((namespace.to_string(), name, signature, original));
+    }
+
+    let forwarder_module = wasmer_runtime::compile(&forwarder_module(&guarded))?;
+    let originals = guarded
+        .iter()
+        .map(|(namespace, name, _, original)| (namespace.clone(), *name, original.clone()))
+        .collect();
+    let dirs = Arc::new(ReadOnlyDirs::new(read_only_dirs));
+
+    let mut overrides = ImportObject::new();
+    for &namespace_name in WASI_NAMESPACES.iter() {
+        let mut namespace = Namespace::new();
+        for (_, name, signature, _) in guarded.iter().filter(|g| g.0 == namespace_name) {
+            let dirs = dirs.clone();
+            let name = *name;
+            // imports are dynamic functions, because typed ones leak their captured environment
+            let func = move |ctx: &mut Ctx, args: &[WValue]| -> Vec<WValue> {
+                match dirs.check(ctx, name, args) {
+                    __WASI_ESUCCESS => dirs.forward(ctx, name, args),
+                    errno => vec![WValue::I32(errno as i32)],
+                }
+            };
+            namespace.insert(name, DynamicFunc::new(Arc::new(signature.clone()), func));
+        }
+        overrides.register(namespace_name, namespace);
+    }
+
+    let forwarder = DetachedForwarder {
+        dirs,
+        forwarder_module,
+        originals,
+    };
+
+    Ok((forwarder, overrides))
+}
+
+/// Creates a module that imports memory from env and the provided functions,
+/// and exports these functions under their names.
+fn forwarder_module(functions: &[(String, &str, FuncSig, Export)]) -> Vec<u8> {
+    let value_type = |ty: &WType| match ty {
+        WType::I64 => ValueType::I64,
+        WType::F32 => ValueType::F32,
+        WType::F64 => ValueType::F64,
+        // WASI functions have no other parameters
+        _ => ValueType::I32,
+    };
+
+    let mut types = Vec::with_capacity(functions.len());
+    let mut imports = vec![ImportEntry::new(
+        String::from("env"),
+        String::from("memory"),
+        External::Memory(MemoryType::new(0, None)),
+    )];
+    let mut exports = Vec::with_capacity(functions.len());
+    for (func_index, (namespace, name, signature, _)) in functions.iter().enumerate() {
+        types.push(Type::Function(FunctionType::new(
+            signature.params().iter().map(value_type).collect(),
+            signature.returns().first().map(value_type),
+        )));
+        imports.push(ImportEntry::new(
+            namespace.clone(),
+            name.to_string(),
+            External::Function(func_index as u32),
+        ));
+        exports.push(ExportEntry::new(
+            name.to_string(),
+            Internal::Function(func_index as u32),
+        ));
+    }
+
+    // Wasmer can't compile a module without local functions, so there is an empty one
+    let empty_type_index = types.len() as u32;
+    types.push(Type::Function(FunctionType::new(vec![], None)));
+    let empty_body = FuncBody::new(vec![], Instructions::new(vec![Instruction::End]));
+
+    let module = parity_wasm::elements::Module::new(vec![
+        Section::Type(TypeSection::with_types(types)),
+        Section::Import(ImportSection::with_entries(imports)),
+        Section::Function(FunctionSection::with_entries(vec![Func::new(
+            empty_type_index,
+        )])),
+        Section::Export(ExportSection::with_entries(exports)),
+        Section::Code(CodeSection::with_bodies(vec![empty_body])),
+    ]);
+
+    // the module is built from valid sections, so it's always serialized
+    parity_wasm::serialize(module).unwrap_or_default()
+}
+
+/// Returns canonicalized host paths of dirs preopened for a module.
+fn preopened_dirs(fs: &WasiFs) -> Vec<PathBuf> {
+    fs.preopen_fds
+        .iter()
+        .filter_map(|fd| match &fs.inodes[fs.fd_map.get(fd)?.inode].kind {
+            Kind::Dir { path, .. } => {
+                Some(std::fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the host path of a file at the path relative to the fd. It's resolved like WASI
+/// does: `..` of a preopened directory leads to the root, and the root contains preopened dirs.
+fn resolve_host_path(fs: &WasiFs, fd: u32, path: &str) -> Option<PathBuf> {
+    let mut inode = fs.fd_map.get(&fd)?.inode;
+    // names below the inode that haven't been loaded into the inode tree yet
+    let mut tail = Vec::new();
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if !tail.is_empty() => {
+                tail.pop();
+            }
+            Component::ParentDir => match &fs.inodes[inode].kind {
+                Kind::Dir {
+                    parent: Some(parent),
+                    ..
+                } => inode = *parent,
+                Kind::Root { .. } => {}
+                _ => return None,
+            },
+            Component::Normal(name) if tail.is_empty() => {
+                let entries = match &fs.inodes[inode].kind {
+                    Kind::Dir { entries, .. } | Kind::Root { entries } => entries,
+                    _ => return None,
+                };
+                // symlinks are left to the host, it resolves them while canonicalizing
+                match entries.get(name.to_str()?) {
+                    Some(entry) if !matches!(fs.inodes[*entry].kind, Kind::Symlink { .. }) => {
+                        inode = *entry
+                    }
+                    _ => tail.push(name),
+                }
+            }
+            Component::Normal(name) => tail.push(name),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    let base = match &fs.inodes[inode].kind {
+        Kind::Dir { path, .. } | Kind::File { path, .. } => path.clone(),
+        _ => return None,
+    };
+
+    Some(tail.into_iter().fold(base, |path, name| path.join(name)))
+}
+
+/// Canonicalizes the path, the last component is resolved only if symlinks should be followed.
+/// Components that don't exist yet are appended as is.
+fn canonical_path(path: &Path, follow_symlinks: bool) -> PathBuf {
+    if follow_symlinks || path.file_name().is_none() {
+        if let Ok(path) = std::fs::canonicalize(path) {
+            return path;
+        }
+    }
+
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            canonical_path(parent, true).join(name)
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+fn read_str(ctx: &Ctx, offset: u32, len: u32) -> Result<String, Errno> {
+    let offset = offset as usize;
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view
+        .get(offset..offset + len as usize)
+        .ok_or(__WASI_EFAULT)?;
+    let bytes = cells.iter().map(|cell| cell.get()).collect();
+
+    String::from_utf8(bytes).map_err(|_| __WASI_EINVAL)
+}
+
+// arguments are already checked by Wasmer against the signature of an import
+fn u32_arg(args: &[WValue], index: usize) -> u32 {
+    match args.get(index) {
+        Some(WValue::I32(value)) => *value as u32,
+        _ => 0,
+    }
+}
+
+fn u64_arg(args: &[WValue], index: usize) -> u64 {
+    match args.get(index) {
+        Some(WValue::I64(value)) => *value as u64,
+        _ => 0,
+    }
+}
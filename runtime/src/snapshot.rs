@@ -0,0 +1,99 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::MError;
+use crate::MResult;
+use crate::vfs::VfsImage;
+
+use serde::Deserialize;
+use serde::Serialize;
+use wasmer_core::types::Value as WValue;
+
+/// State of a module captured by Marine::snapshot_module, it could be applied to the same
+/// module by Marine::restore_module, even in another Marine instance.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModuleSnapshot {
+    /// Linear memory and mutable globals of the module.
+    pub(crate) image: MemoryImage,
+
+    /// Serialized WASI state of the module including its file descriptors table.
+    pub(crate) wasi_state: Vec<u8>,
+
+    /// Virtual filesystem of the module and its file descriptors, if the module uses one.
+    pub(crate) vfs: Option<VfsImage>,
+}
+
+/// Linear memory and mutable globals of a module.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct MemoryImage {
+    /// Content of the module linear memory.
+    pub(crate) memory: Vec<u8>,
+
+    /// Values of the module mutable globals by their export names.
+    pub(crate) globals: Vec<(String, GlobalValue)>,
+}
+
+/// Value of a Wasm global, floats are kept as bits to be restored exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum GlobalValue {
+    I32(i32),
+    I64(i64),
+    F32(u32),
+    F64(u64),
+}
+
+impl ModuleSnapshot {
+    /// Serializes the snapshot, so it could be persisted.
+    pub fn to_bytes(&self) -> MResult<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| MError::IncorrectSnapshot(e.to_string()))
+    }
+
+    /// Deserializes a snapshot previously serialized by to_bytes.
+    pub fn from_bytes(bytes: &[u8]) -> MResult<Self> {
+        bincode::deserialize(bytes).map_err(|e| MError::IncorrectSnapshot(e.to_string()))
+    }
+
+    /// Returns size of the captured linear memory in bytes.
+    pub fn memory_size(&self) -> usize {
+        self.image.memory.len()
+    }
+
+    /// Returns the captured linear memory.
+    pub fn memory(&self) -> &[u8] {
+        &self.image.memory
+    }
+}
+
+impl GlobalValue {
+    pub(crate) fn from_wvalue(value: WValue) -> Option<Self> {
+        match value {
+            WValue::I32(value) => Some(GlobalValue::I32(value)),
+            WValue::I64(value) => Some(GlobalValue::I64(value)),
+            WValue::F32(value) => Some(GlobalValue::F32(value.to_bits())),
+            WValue::F64(value) => Some(GlobalValue::F64(value.to_bits())),
+            WValue::V128(_) => None,
+        }
+    }
+
+    pub(crate) fn to_wvalue(self) -> WValue {
+        match self {
+            GlobalValue::I32(value) => WValue::I32(value),
+            GlobalValue::I64(value) => WValue::I64(value),
+            GlobalValue::F32(bits) => WValue::F32(f32::from_bits(bits)),
+            GlobalValue::F64(bits) => WValue::F64(f64::from_bits(bits)),
+        }
+    }
+}
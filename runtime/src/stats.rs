@@ -0,0 +1,88 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::Serialize;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Statistics of a module, shared between all instances of the module and their imports.
+pub(crate) type SharedModuleStats = Arc<Mutex<ModuleStats>>;
+
+/// Resources consumed by a module since it has been loaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ModuleStats {
+    /// Count of Wasm memory pages the module currently has.
+    pub memory_pages: u32,
+
+    /// Maximum count of Wasm memory pages the module had after its calls.
+    pub peak_memory_pages: u32,
+
+    /// Statistics of calls of export functions, including calls from other modules.
+    pub functions: HashMap<String, FunctionStats>,
+
+    /// Count of invocations of each host import by the module.
+    pub host_imports: HashMap<String, u64>,
+
+    /// Count of calls of export functions that trapped while executing Wasm code, calls that
+    /// failed because of incorrect arguments or results aren't counted.
+    pub traps_count: u64,
+}
+
+/// Statistics of calls of an export function.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FunctionStats {
+    pub calls_count: u64,
+    pub total_call_time: Duration,
+    pub max_call_time: Duration,
+}
+
+impl ModuleStats {
+    pub(crate) fn record_call(
+        &mut self,
+        function_name: &str,
+        call_time: Duration,
+        is_trapped: bool,
+    ) {
+        let function_stats = match self.functions.get_mut(function_name) {
+            Some(function_stats) => function_stats,
+            None => self.functions.entry(function_name.to_string()).or_default(),
+        };
+
+        function_stats.calls_count += 1;
+        function_stats.total_call_time += call_time;
+        function_stats.max_call_time = std::cmp::max(function_stats.max_call_time, call_time);
+
+        if is_trapped {
+            self.traps_count += 1;
+        }
+    }
+
+    pub(crate) fn record_host_import_call(&mut self, import_name: &str) {
+        match self.host_imports.get_mut(import_name) {
+            Some(calls_count) => *calls_count += 1,
+            None => {
+                self.host_imports.insert(import_name.to_string(), 1);
+            }
+        }
+    }
+
+    pub(crate) fn record_memory_pages(&mut self, memory_pages: u32) {
+        self.peak_memory_pages = std::cmp::max(self.peak_memory_pages, memory_pages);
+    }
+}
@@ -0,0 +1,264 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::tree::PersistedVfsTreeChanges;
+use super::tree::VfsTree;
+use crate::MError;
+use crate::MResult;
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+// every journal starts with the generation of the snapshot it continues
+const GENERATION_SIZE: usize = std::mem::size_of::<u64>();
+// every record of a journal is prefixed with its size
+const RECORD_SIZE_SIZE: usize = std::mem::size_of::<u64>();
+
+/// File where a virtual filesystem is persisted. It contains a snapshot of the whole tree,
+/// and changes made after the snapshot are appended to a journal next to it, so only inodes
+/// changed since the previous save are written. The journal is merged into the snapshot
+/// when it outgrows the snapshot.
+///
+/// Both files are tagged with a generation, which is incremented on every merge, so
+/// a journal left by a merge interrupted after the snapshot was replaced is ignored.
+/// A record partially appended to a journal is ignored as well.
+pub(crate) struct VfsFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    journal_path: PathBuf,
+    generation: u64,
+    snapshot_size: u64,
+    journal_size: u64,
+    // the snapshot doesn't exist, or the journal is stale or has a partial record at its end
+    needs_merge: bool,
+}
+
+impl VfsFile {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self {
+            tmp_path: with_suffix(&path, ".tmp"),
+            journal_path: with_suffix(&path, ".journal"),
+            path,
+            generation: 0,
+            snapshot_size: 0,
+            journal_size: 0,
+            needs_merge: true,
+        }
+    }
+
+    /// Loads the tree from the snapshot and its journal, None if the snapshot doesn't exist.
+    pub(crate) fn load(&mut self) -> MResult<Option<VfsTree>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+
+        let bytes = std::fs::read(&self.path).map_err(|e| self.error("read", e))?;
+        let (generation, mut tree): (u64, VfsTree) = bincode::deserialize(&bytes).map_err(|e| {
+            MError::VfsError(format!("{:?} isn't a virtual filesystem: {}", self.path, e))
+        })?;
+
+        let journal = match std::fs::read(&self.journal_path) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(self.error("read", e)),
+        };
+        let is_complete = apply_journal(&mut tree, generation, &journal);
+
+        self.generation = generation;
+        self.snapshot_size = bytes.len() as u64;
+        self.journal_size = journal.len() as u64;
+        self.needs_merge = !is_complete;
+
+        Ok(Some(tree))
+    }
+
+    /// Appends changes of the tree to the journal, or merges them into the snapshot.
+    pub(crate) fn save(&mut self, tree: &VfsTree) -> MResult<()> {
+        if self.needs_merge || self.journal_size > self.snapshot_size {
+            return self.merge(tree);
+        }
+
+        let record =
+            bincode::serialize(&tree.changes()).map_err(|e| MError::VfsError(e.to_string()))?;
+        let mut bytes = Vec::with_capacity(RECORD_SIZE_SIZE + record.len());
+        bytes.extend_from_slice(&(record.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&record);
+
+        let result = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.journal_path)
+            .and_then(|mut journal| journal.write_all(&bytes));
+        if let Err(e) = result {
+            // a part of the record could have been written
+            self.needs_merge = true;
+            return Err(self.error("written", e));
+        }
+        self.journal_size += bytes.len() as u64;
+
+        Ok(())
+    }
+
+    // the snapshot is replaced atomically, so a failure doesn't leave it corrupted
+    fn merge(&mut self, tree: &VfsTree) -> MResult<()> {
+        let generation = self.generation + 1;
+        let bytes =
+            bincode::serialize(&(generation, tree)).map_err(|e| MError::VfsError(e.to_string()))?;
+
+        std::fs::write(&self.tmp_path, &bytes)
+            .and_then(|_| std::fs::rename(&self.tmp_path, &self.path))
+            .map_err(|e| self.error("written", e))?;
+        std::fs::write(&self.journal_path, generation.to_le_bytes())
+            .map_err(|e| self.error("written", e))?;
+
+        self.generation = generation;
+        self.snapshot_size = bytes.len() as u64;
+        self.journal_size = GENERATION_SIZE as u64;
+        self.needs_merge = false;
+
+        Ok(())
+    }
+
+    fn error(&self, action: &str, error: std::io::Error) -> MError {
+        MError::VfsError(format!("{:?} can't be {}: {}", self.path, action, error))
+    }
+}
+
+// returns false if the journal doesn't belong to the snapshot or has a partial record
+fn apply_journal(tree: &mut VfsTree, generation: u64, journal: &[u8]) -> bool {
+    if journal.len() < GENERATION_SIZE || read_u64(journal) != generation {
+        return false;
+    }
+
+    let mut records = &journal[GENERATION_SIZE..];
+    while !records.is_empty() {
+        if records.len() < RECORD_SIZE_SIZE {
+            return false;
+        }
+
+        let size = read_u64(records) as usize;
+        let record = match records[RECORD_SIZE_SIZE..].get(..size) {
+            Some(record) => record,
+            None => return false,
+        };
+        match bincode::deserialize::<PersistedVfsTreeChanges>(record) {
+            Ok(changes) => tree.apply_changes(changes),
+            Err(_) => return false,
+        }
+
+        records = &records[RECORD_SIZE_SIZE + size..];
+    }
+
+    true
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(value)
+}
+
+// unlike Path::with_extension, this keeps the whole file name, so the result never
+// coincides with the path itself
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::tree::VfsLimits;
+    use crate::vfs::tree::ROOT_INODE;
+
+    fn vfs_file(name: &str) -> VfsFile {
+        let path = std::env::temp_dir().join(format!(
+            "marine_vfs_file_{}_{}.vfs",
+            name,
+            std::process::id()
+        ));
+        VfsFile::new(path)
+    }
+
+    fn remove(file: &VfsFile) {
+        let _ = std::fs::remove_file(&file.path);
+        let _ = std::fs::remove_file(&file.journal_path);
+    }
+
+    fn tree() -> VfsTree {
+        VfsTree::new(VfsLimits {
+            max_size: 1024,
+            max_inodes: 16,
+        })
+    }
+
+    #[test]
+    fn changes_are_journaled() {
+        let mut file = vfs_file("journaled");
+        let mut tree = tree();
+        let inode = tree.create_file(ROOT_INODE, "file", 0).unwrap();
+        file.save(&tree).unwrap();
+        tree.clear_changes();
+
+        tree.write(inode, 0, b"content").unwrap();
+        file.save(&tree).unwrap();
+        tree.clear_changes();
+        let snapshot_size = std::fs::metadata(&file.path).unwrap().len();
+
+        let loaded = vfs_file("journaled").load().unwrap().unwrap();
+        remove(&file);
+
+        // the second save is appended to the journal
+        assert_eq!(snapshot_size, file.snapshot_size);
+        assert!(file.journal_size > GENERATION_SIZE as u64);
+        assert_eq!(loaded.read(inode, 0, 16), Ok(&b"content"[..]));
+        assert_eq!(loaded, tree);
+    }
+
+    #[test]
+    fn partial_records_are_ignored() {
+        let mut file = vfs_file("partial");
+        let mut tree = tree();
+        let inode = tree.create_file(ROOT_INODE, "file", 0).unwrap();
+        file.save(&tree).unwrap();
+        tree.clear_changes();
+
+        tree.write(inode, 0, b"content").unwrap();
+        file.save(&tree).unwrap();
+        let journal = std::fs::read(&file.journal_path).unwrap();
+        std::fs::write(&file.journal_path, &journal[..journal.len() - 1]).unwrap();
+
+        let mut loaded_file = vfs_file("partial");
+        let loaded = loaded_file.load().unwrap().unwrap();
+        remove(&file);
+
+        assert_eq!(loaded.read(inode, 0, 16), Ok(&b""[..]));
+        assert!(loaded_file.needs_merge);
+    }
+
+    #[test]
+    fn suffixes_keep_file_name() {
+        let file = VfsFile::new(PathBuf::from("/var/lib/service.tmp"));
+
+        assert_eq!(file.tmp_path, PathBuf::from("/var/lib/service.tmp.tmp"));
+        assert_eq!(
+            file.journal_path,
+            PathBuf::from("/var/lib/service.tmp.journal")
+        );
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod file;
+mod tree;
+mod wasi;
+
+pub(crate) use wasi::create_vfs_wasi_imports;
+pub(crate) use wasi::FdTable;
+pub(crate) use wasi::VfsInstance;
+
+use file::VfsFile;
+use tree::VfsLimits;
+use tree::VfsTree;
+use crate::MError;
+use crate::MResult;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+pub(crate) type Errno = i32;
+
+pub(crate) type SharedVfsTree = Arc<Mutex<VfsTree>>;
+
+/// Only WASI snapshot1 syscalls are provided by the virtual filesystem.
+pub(crate) const VFS_WASI_NAMESPACE: &str = "wasi_snapshot_preview1";
+
+// WASI errno values returned by the virtual filesystem
+pub(crate) mod errno {
+    pub(crate) const ERRNO_SUCCESS: i32 = 0;
+    pub(crate) const ERRNO_BADF: i32 = 8;
+    pub(crate) const ERRNO_EXIST: i32 = 20;
+    pub(crate) const ERRNO_FAULT: i32 = 21;
+    pub(crate) const ERRNO_FBIG: i32 = 22;
+    pub(crate) const ERRNO_ILSEQ: i32 = 25;
+    pub(crate) const ERRNO_INVAL: i32 = 28;
+    pub(crate) const ERRNO_IO: i32 = 29;
+    pub(crate) const ERRNO_ISDIR: i32 = 31;
+    pub(crate) const ERRNO_LOOP: i32 = 32;
+    pub(crate) const ERRNO_NOENT: i32 = 44;
+    pub(crate) const ERRNO_NOSPC: i32 = 51;
+    pub(crate) const ERRNO_NOTDIR: i32 = 54;
+    pub(crate) const ERRNO_NOTEMPTY: i32 = 55;
+    pub(crate) const ERRNO_PERM: i32 = 63;
+    pub(crate) const ERRNO_SPIPE: i32 = 70;
+    pub(crate) const ERRNO_NOTCAPABLE: i32 = 76;
+}
+
+/// Virtual filesystem that a module sees instead of host directories. Preopened files
+/// and mapped dirs of the module become directories of the virtual filesystem
+/// with the same names, their host paths aren't accessed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VfsConfig {
+    /// If Some, the filesystem is loaded from this file when the module is loaded
+    /// and saved to it after calls that changed it, otherwise it lives only in memory.
+    /// Changes are appended to a journal file next to it with the `.journal` suffix,
+    /// which is merged into this file from time to time.
+    pub file: Option<PathBuf>,
+
+    /// Maximum total size of files and symlinks in bytes, writes beyond it fail with ENOSPC.
+    pub max_size: u64,
+
+    /// Maximum number of files, directories and symlinks including the root directory.
+    pub max_inodes: u64,
+}
+
+impl Default for VfsConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            // 100 Mb
+            max_size: 100 * 1024 * 1024,
+            max_inodes: 10_000,
+        }
+    }
+}
+
+/// Content of a virtual filesystem and file descriptors of a module using it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VfsImage {
+    tree: VfsTree,
+    fds: FdTable,
+}
+
+/// Virtual filesystem shared between all instances of a module.
+pub(crate) struct VirtualFs {
+    tree: SharedVfsTree,
+    file: Option<Mutex<VfsFile>>,
+}
+
+impl VirtualFs {
+    /// Creates an empty filesystem or loads it from the file of the config if it exists.
+    pub(crate) fn new(config: &VfsConfig) -> MResult<Self> {
+        let limits = VfsLimits {
+            max_size: config.max_size,
+            max_inodes: config.max_inodes,
+        };
+
+        let mut file = config.file.clone().map(VfsFile::new);
+        let tree = match file.as_mut().map(VfsFile::load).transpose()?.flatten() {
+            Some(mut tree) => {
+                tree.set_limits(limits);
+                tree
+            }
+            None => VfsTree::new(limits),
+        };
+
+        Ok(Self {
+            tree: Arc::new(Mutex::new(tree)),
+            file: file.map(Mutex::new),
+        })
+    }
+
+    /// Creates filesystem state of a new module instance, every preopened directory
+    /// is created if it doesn't exist.
+    pub(crate) fn instance<'a>(
+        &self,
+        preopens: impl Iterator<Item = &'a str>,
+        deterministic: bool,
+    ) -> MResult<Arc<VfsInstance>> {
+        let mut preopens = preopens.map(ToString::to_string).collect::<Vec<_>>();
+        preopens.sort();
+        preopens.dedup();
+
+        let mut tree = self.tree.lock().unwrap();
+        let preopens = preopens
+            .into_iter()
+            .map(|name| match tree.create_dir_all(&name, 0) {
+                Ok(inode) => Ok((name, inode)),
+                Err(errno) => Err(MError::VfsError(format!(
+                    "preopened directory {} can't be created, WASI errno {}",
+                    name, errno
+                ))),
+            })
+            .collect::<MResult<Vec<_>>>()?;
+
+        let instance = VfsInstance::new(self.tree.clone(), FdTable::new(preopens), deterministic);
+        Ok(Arc::new(instance))
+    }
+
+    /// Saves inodes changed since the last save to the file of the filesystem, if any.
+    pub(crate) fn persist(&self) -> MResult<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        // the tree is kept locked, so changes made meanwhile by other instances aren't lost
+        let mut tree = self.tree.lock().unwrap();
+        if !tree.has_changes() {
+            return Ok(());
+        }
+
+        file.lock().unwrap().save(&tree)?;
+        tree.clear_changes();
+
+        Ok(())
+    }
+}
+
+impl VfsImage {
+    pub(crate) fn capture(instance: &VfsInstance) -> Self {
+        Self {
+            tree: instance.tree().lock().unwrap().clone(),
+            fds: instance.fds(),
+        }
+    }
+
+    /// Restores the filesystem content shared by all instances and fds of the provided one,
+    /// limits of the filesystem are kept.
+    pub(crate) fn apply(&self, instance: &VfsInstance) {
+        instance.tree().lock().unwrap().replace(&self.tree);
+
+        instance.set_fds(self.fds.clone());
+    }
+}
@@ -0,0 +1,646 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::errno::*;
+use super::Errno;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+pub(crate) type InodeId = u64;
+
+pub(crate) const ROOT_INODE: InodeId = 1;
+
+// symlinks referring to other symlinks are followed up to this depth
+const MAX_SYMLINKS_DEPTH: u32 = 32;
+
+/// Size and inodes limits of a virtual filesystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VfsLimits {
+    pub(crate) max_size: u64,
+    pub(crate) max_inodes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum InodeKind {
+    Directory {
+        parent: InodeId,
+        entries: BTreeMap<String, InodeId>,
+    },
+    File {
+        content: Vec<u8>,
+    },
+    Symlink {
+        target: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Inode {
+    pub(crate) kind: InodeKind,
+    pub(crate) nlink: u64,
+    pub(crate) atime: u64,
+    pub(crate) mtime: u64,
+    pub(crate) ctime: u64,
+}
+
+/// Tree of directories, files and symlinks of a virtual filesystem.
+/// Sizes of file contents and symlink targets count towards the size quota,
+/// and every inode including directories counts towards the inodes quota.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct VfsTree {
+    inodes: BTreeMap<InodeId, Inode>,
+    next_inode: InodeId,
+    used_size: u64,
+    limits: VfsLimits,
+
+    /// Inodes changed since the tree was persisted the last time.
+    #[serde(skip)]
+    changed_inodes: BTreeSet<InodeId>,
+}
+
+/// Inodes changed since the tree was persisted the last time, removed ones are None.
+#[derive(Serialize)]
+pub(crate) struct VfsTreeChanges<'tree> {
+    inodes: Vec<(InodeId, Option<&'tree Inode>)>,
+    next_inode: InodeId,
+    used_size: u64,
+}
+
+/// Changes of a tree read back after they have been persisted.
+#[derive(Deserialize)]
+pub(crate) struct PersistedVfsTreeChanges {
+    inodes: Vec<(InodeId, Option<Inode>)>,
+    next_inode: InodeId,
+    used_size: u64,
+}
+
+impl Inode {
+    fn new(kind: InodeKind, time: u64) -> Self {
+        Self {
+            kind,
+            nlink: 1,
+            atime: time,
+            mtime: time,
+            ctime: time,
+        }
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        match &self.kind {
+            InodeKind::Directory { .. } => 0,
+            InodeKind::File { content } => content.len() as u64,
+            InodeKind::Symlink { target } => target.len() as u64,
+        }
+    }
+
+    pub(crate) fn is_dir(&self) -> bool {
+        matches!(self.kind, InodeKind::Directory { .. })
+    }
+}
+
+impl VfsTree {
+    pub(crate) fn new(limits: VfsLimits) -> Self {
+        let root = Inode::new(
+            InodeKind::Directory {
+                parent: ROOT_INODE,
+                entries: BTreeMap::new(),
+            },
+            0,
+        );
+
+        let mut inodes = BTreeMap::new();
+        inodes.insert(ROOT_INODE, root);
+
+        Self {
+            inodes,
+            next_inode: ROOT_INODE + 1,
+            used_size: 0,
+            limits,
+            changed_inodes: BTreeSet::new(),
+        }
+    }
+
+    /// Limits aren't a part of the state, so they are kept when the tree is restored.
+    pub(crate) fn set_limits(&mut self, limits: VfsLimits) {
+        self.limits = limits;
+    }
+
+    pub(crate) fn inodes_count(&self) -> u64 {
+        self.inodes.len() as u64
+    }
+
+    /// Replaces content of the tree keeping its limits, all inodes of both trees
+    /// are considered changed.
+    pub(crate) fn replace(&mut self, other: &VfsTree) {
+        let limits = self.limits;
+        let mut changed_inodes = std::mem::take(&mut self.changed_inodes);
+        changed_inodes.extend(self.inodes.keys());
+        changed_inodes.extend(other.inodes.keys());
+
+        *self = other.clone();
+        self.limits = limits;
+        self.changed_inodes = changed_inodes;
+    }
+
+    pub(crate) fn has_changes(&self) -> bool {
+        !self.changed_inodes.is_empty()
+    }
+
+    pub(crate) fn changes(&self) -> VfsTreeChanges<'_> {
+        let inodes = self
+            .changed_inodes
+            .iter()
+            .map(|id| (*id, self.inodes.get(id)))
+            .collect();
+
+        VfsTreeChanges {
+            inodes,
+            next_inode: self.next_inode,
+            used_size: self.used_size,
+        }
+    }
+
+    pub(crate) fn clear_changes(&mut self) {
+        self.changed_inodes.clear();
+    }
+
+    /// Applies changes persisted after the tree had been persisted the last time.
+    pub(crate) fn apply_changes(&mut self, changes: PersistedVfsTreeChanges) {
+        for (id, inode) in changes.inodes {
+            match inode {
+                Some(inode) => self.inodes.insert(id, inode),
+                None => self.inodes.remove(&id),
+            };
+        }
+        self.next_inode = changes.next_inode;
+        self.used_size = changes.used_size;
+    }
+
+    pub(crate) fn inode(&self, id: InodeId) -> Result<&Inode, Errno> {
+        self.inodes.get(&id).ok_or(ERRNO_BADF)
+    }
+
+    fn inode_mut(&mut self, id: InodeId) -> Result<&mut Inode, Errno> {
+        let inode = self.inodes.get_mut(&id).ok_or(ERRNO_BADF)?;
+        self.changed_inodes.insert(id);
+
+        Ok(inode)
+    }
+
+    fn entries(&self, dir: InodeId) -> Result<&BTreeMap<String, InodeId>, Errno> {
+        match &self.inode(dir)?.kind {
+            InodeKind::Directory { entries, .. } => Ok(entries),
+            _ => Err(ERRNO_NOTDIR),
+        }
+    }
+
+    fn entries_mut(&mut self, dir: InodeId) -> Result<&mut BTreeMap<String, InodeId>, Errno> {
+        match &mut self.inode_mut(dir)?.kind {
+            InodeKind::Directory { entries, .. } => Ok(entries),
+            _ => Err(ERRNO_NOTDIR),
+        }
+    }
+
+    fn parent(&self, dir: InodeId) -> Result<InodeId, Errno> {
+        match &self.inode(dir)?.kind {
+            InodeKind::Directory { parent, .. } => Ok(*parent),
+            _ => Err(ERRNO_NOTDIR),
+        }
+    }
+
+    /// Returns the inode at the path relative to the provided directory, absolute paths
+    /// are resolved from the root of the tree.
+    pub(crate) fn lookup(
+        &self,
+        dir: InodeId,
+        path: &str,
+        follow_symlinks: bool,
+    ) -> Result<InodeId, Errno> {
+        self.lookup_(dir, path, follow_symlinks, 0)
+    }
+
+    fn lookup_(
+        &self,
+        dir: InodeId,
+        path: &str,
+        follow_symlinks: bool,
+        depth: u32,
+    ) -> Result<InodeId, Errno> {
+        if depth > MAX_SYMLINKS_DEPTH {
+            return Err(ERRNO_LOOP);
+        }
+
+        let mut current = if path.starts_with('/') {
+            ROOT_INODE
+        } else {
+            dir
+        };
+        let components = path
+            .split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+            .collect::<Vec<_>>();
+
+        for (position, component) in components.iter().enumerate() {
+            if *component == ".." {
+                current = self.parent(current)?;
+                continue;
+            }
+
+            let child = *self.entries(current)?.get(*component).ok_or(ERRNO_NOENT)?;
+            let is_last = position + 1 == components.len();
+            current = match &self.inode(child)?.kind {
+                InodeKind::Symlink { target } if !is_last || follow_symlinks => {
+                    self.lookup_(current, target, true, depth + 1)?
+                }
+                _ => child,
+            };
+        }
+
+        Ok(current)
+    }
+
+    /// Splits the path into the directory containing its last component and the component.
+    pub(crate) fn lookup_parent(
+        &self,
+        dir: InodeId,
+        path: &str,
+    ) -> Result<(InodeId, String), Errno> {
+        let path = path.trim_end_matches('/');
+        let (parent_path, name) = match path.rfind('/') {
+            Some(position) => (&path[..position + 1], &path[position + 1..]),
+            None => ("", path),
+        };
+
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(ERRNO_INVAL);
+        }
+
+        let parent = self.lookup(dir, parent_path, true)?;
+        if !self.inode(parent)?.is_dir() {
+            return Err(ERRNO_NOTDIR);
+        }
+
+        Ok((parent, name.to_string()))
+    }
+
+    /// Returns the child of the directory with the provided name if it exists.
+    pub(crate) fn child(&self, dir: InodeId, name: &str) -> Result<Option<InodeId>, Errno> {
+        Ok(self.entries(dir)?.get(name).copied())
+    }
+
+    /// Returns entries of the directory including "." and "..".
+    pub(crate) fn read_dir(&self, dir: InodeId) -> Result<Vec<(String, InodeId)>, Errno> {
+        let entries = self.entries(dir)?;
+        let mut result = Vec::with_capacity(entries.len() + 2);
+        result.push((String::from("."), dir));
+        result.push((String::from(".."), self.parent(dir)?));
+        result.extend(entries.iter().map(|(name, id)| (name.clone(), *id)));
+
+        Ok(result)
+    }
+
+    /// Creates all directories of the path that don't exist yet.
+    pub(crate) fn create_dir_all(&mut self, path: &str, time: u64) -> Result<InodeId, Errno> {
+        let mut current = ROOT_INODE;
+        for component in path
+            .split('/')
+            .filter(|component| !component.is_empty() && *component != ".")
+        {
+            current = match self.child(current, component)? {
+                Some(child) => child,
+                None => self.create_dir(current, component, time)?,
+            };
+        }
+
+        Ok(current)
+    }
+
+    pub(crate) fn create_dir(
+        &mut self,
+        parent: InodeId,
+        name: &str,
+        time: u64,
+    ) -> Result<InodeId, Errno> {
+        let kind = InodeKind::Directory {
+            parent,
+            entries: BTreeMap::new(),
+        };
+        self.insert(parent, name, Inode::new(kind, time))
+    }
+
+    pub(crate) fn create_file(
+        &mut self,
+        parent: InodeId,
+        name: &str,
+        time: u64,
+    ) -> Result<InodeId, Errno> {
+        let kind = InodeKind::File {
+            content: Vec::new(),
+        };
+        self.insert(parent, name, Inode::new(kind, time))
+    }
+
+    pub(crate) fn create_symlink(
+        &mut self,
+        parent: InodeId,
+        name: &str,
+        target: &str,
+        time: u64,
+    ) -> Result<InodeId, Errno> {
+        self.reserve_size(target.len() as u64)?;
+        let kind = InodeKind::Symlink {
+            target: target.to_string(),
+        };
+        let id = self.insert(parent, name, Inode::new(kind, time))?;
+        self.used_size += target.len() as u64;
+
+        Ok(id)
+    }
+
+    fn insert(&mut self, parent: InodeId, name: &str, inode: Inode) -> Result<InodeId, Errno> {
+        if self.entries(parent)?.contains_key(name) {
+            return Err(ERRNO_EXIST);
+        }
+        if self.inodes_count() >= self.limits.max_inodes {
+            return Err(ERRNO_NOSPC);
+        }
+
+        let id = self.next_inode;
+        self.next_inode += 1;
+        self.inodes.insert(id, inode);
+        self.changed_inodes.insert(id);
+        self.entries_mut(parent)?.insert(name.to_string(), id);
+
+        Ok(id)
+    }
+
+    /// Adds one more name to an existing file.
+    pub(crate) fn link(
+        &mut self,
+        parent: InodeId,
+        name: &str,
+        target: InodeId,
+    ) -> Result<(), Errno> {
+        if self.inode(target)?.is_dir() {
+            return Err(ERRNO_PERM);
+        }
+        if self.entries(parent)?.contains_key(name) {
+            return Err(ERRNO_EXIST);
+        }
+
+        self.inode_mut(target)?.nlink += 1;
+        self.entries_mut(parent)?.insert(name.to_string(), target);
+
+        Ok(())
+    }
+
+    /// Removes a file or a symlink from the directory.
+    pub(crate) fn unlink(&mut self, parent: InodeId, name: &str) -> Result<(), Errno> {
+        let child = self.child(parent, name)?.ok_or(ERRNO_NOENT)?;
+        if self.inode(child)?.is_dir() {
+            return Err(ERRNO_ISDIR);
+        }
+
+        self.entries_mut(parent)?.remove(name);
+        self.release(child)
+    }
+
+    /// Removes an empty directory.
+    pub(crate) fn remove_dir(&mut self, parent: InodeId, name: &str) -> Result<(), Errno> {
+        let child = self.child(parent, name)?.ok_or(ERRNO_NOENT)?;
+        if !self.entries(child)?.is_empty() {
+            return Err(ERRNO_NOTEMPTY);
+        }
+
+        self.entries_mut(parent)?.remove(name);
+        self.release(child)
+    }
+
+    // drops one link to the inode, the inode is freed when it has no links left
+    fn release(&mut self, id: InodeId) -> Result<(), Errno> {
+        let inode = self.inode_mut(id)?;
+        inode.nlink = inode.nlink.saturating_sub(1);
+        if inode.nlink == 0 {
+            let size = inode.size();
+            self.inodes.remove(&id);
+            self.used_size -= size;
+        }
+
+        Ok(())
+    }
+
+    /// Moves an entry, an existing destination is replaced if it's of the same kind.
+    pub(crate) fn rename(
+        &mut self,
+        old_parent: InodeId,
+        old_name: &str,
+        new_parent: InodeId,
+        new_name: &str,
+    ) -> Result<(), Errno> {
+        let source = self.child(old_parent, old_name)?.ok_or(ERRNO_NOENT)?;
+        let is_dir = self.inode(source)?.is_dir();
+        // the destination must be a directory, and a directory can't be moved into itself
+        self.entries(new_parent)?;
+        if is_dir && self.is_ancestor(source, new_parent)? {
+            return Err(ERRNO_INVAL);
+        }
+
+        if let Some(destination) = self.child(new_parent, new_name)? {
+            if destination == source {
+                return Ok(());
+            }
+
+            match (is_dir, self.inode(destination)?.is_dir()) {
+                (true, true) => self.remove_dir(new_parent, new_name)?,
+                (false, false) => self.unlink(new_parent, new_name)?,
+                (true, false) => return Err(ERRNO_NOTDIR),
+                (false, true) => return Err(ERRNO_ISDIR),
+            }
+        }
+
+        self.entries_mut(old_parent)?.remove(old_name);
+        self.entries_mut(new_parent)?
+            .insert(new_name.to_string(), source);
+        if let InodeKind::Directory { parent, .. } = &mut self.inode_mut(source)?.kind {
+            *parent = new_parent;
+        }
+
+        Ok(())
+    }
+
+    fn is_ancestor(&self, ancestor: InodeId, mut dir: InodeId) -> Result<bool, Errno> {
+        loop {
+            if dir == ancestor {
+                return Ok(true);
+            }
+            if dir == ROOT_INODE {
+                return Ok(false);
+            }
+            dir = self.parent(dir)?;
+        }
+    }
+
+    pub(crate) fn read(&self, file: InodeId, offset: u64, len: usize) -> Result<&[u8], Errno> {
+        let content = self.content(file)?;
+        let start = std::cmp::min(offset, content.len() as u64) as usize;
+        let end = std::cmp::min(start + len, content.len());
+
+        Ok(&content[start..end])
+    }
+
+    pub(crate) fn write(&mut self, file: InodeId, offset: u64, data: &[u8]) -> Result<(), Errno> {
+        let end = offset.checked_add(data.len() as u64).ok_or(ERRNO_FBIG)?;
+        if end > self.content(file)?.len() as u64 {
+            self.set_size(file, end)?;
+        }
+
+        let content = self.content_mut(file)?;
+        content[offset as usize..end as usize].copy_from_slice(data);
+
+        Ok(())
+    }
+
+    /// Truncates or extends the file with zeroes.
+    pub(crate) fn set_size(&mut self, file: InodeId, size: u64) -> Result<(), Errno> {
+        let current_size = self.content(file)?.len() as u64;
+        if size > current_size {
+            self.reserve_size(size - current_size)?;
+        }
+
+        self.content_mut(file)?.resize(size as usize, 0);
+        self.used_size = self.used_size + size - current_size;
+
+        Ok(())
+    }
+
+    fn reserve_size(&self, additional: u64) -> Result<(), Errno> {
+        match self.used_size.checked_add(additional) {
+            Some(size) if size <= self.limits.max_size => Ok(()),
+            _ => Err(ERRNO_NOSPC),
+        }
+    }
+
+    fn content(&self, file: InodeId) -> Result<&Vec<u8>, Errno> {
+        match &self.inode(file)?.kind {
+            InodeKind::File { content } => Ok(content),
+            InodeKind::Directory { .. } => Err(ERRNO_ISDIR),
+            InodeKind::Symlink { .. } => Err(ERRNO_INVAL),
+        }
+    }
+
+    fn content_mut(&mut self, file: InodeId) -> Result<&mut Vec<u8>, Errno> {
+        match &mut self.inode_mut(file)?.kind {
+            InodeKind::File { content } => Ok(content),
+            InodeKind::Directory { .. } => Err(ERRNO_ISDIR),
+            InodeKind::Symlink { .. } => Err(ERRNO_INVAL),
+        }
+    }
+
+    pub(crate) fn set_times(
+        &mut self,
+        id: InodeId,
+        atime: Option<u64>,
+        mtime: Option<u64>,
+    ) -> Result<(), Errno> {
+        let inode = self.inode_mut(id)?;
+        if let Some(atime) = atime {
+            inode.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            inode.mtime = mtime;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(max_size: u64, max_inodes: u64) -> VfsTree {
+        VfsTree::new(VfsLimits {
+            max_size,
+            max_inodes,
+        })
+    }
+
+    #[test]
+    fn paths_are_resolved() {
+        let mut tree = tree(1024, 16);
+        let dir = tree.create_dir_all("tmp/data", 0).unwrap();
+        let file = tree.create_file(dir, "file", 0).unwrap();
+        tree.create_symlink(ROOT_INODE, "link", "tmp/data", 0)
+            .unwrap();
+
+        assert_eq!(tree.lookup(ROOT_INODE, "tmp/data/file", true), Ok(file));
+        assert_eq!(tree.lookup(dir, "../data/./file", true), Ok(file));
+        assert_eq!(tree.lookup(dir, "/link/file", true), Ok(file));
+        assert_eq!(tree.lookup(dir, "file/other", true), Err(ERRNO_NOTDIR));
+        assert_eq!(tree.lookup(dir, "other", true), Err(ERRNO_NOENT));
+        assert_eq!(
+            tree.lookup_parent(dir, "../data/new/"),
+            Ok((dir, String::from("new")))
+        );
+    }
+
+    #[test]
+    fn quotas_are_enforced() {
+        let mut tree = tree(8, 3);
+        let file = tree.create_file(ROOT_INODE, "file", 0).unwrap();
+        tree.write(file, 0, b"12345678").unwrap();
+        assert_eq!(tree.write(file, 8, b"9"), Err(ERRNO_NOSPC));
+        assert_eq!(tree.set_size(file, 9), Err(ERRNO_NOSPC));
+
+        tree.create_dir(ROOT_INODE, "dir", 0).unwrap();
+        assert_eq!(tree.create_file(ROOT_INODE, "other", 0), Err(ERRNO_NOSPC));
+
+        // removed files free their space and inodes
+        tree.unlink(ROOT_INODE, "file").unwrap();
+        let file = tree.create_file(ROOT_INODE, "other", 0).unwrap();
+        tree.write(file, 4, b"1234").unwrap();
+        assert_eq!(
+            tree.read(file, 0, 16),
+            Ok(&[0, 0, 0, 0, b'1', b'2', b'3', b'4'][..])
+        );
+    }
+
+    #[test]
+    fn rename_replaces_destination() {
+        let mut tree = tree(1024, 16);
+        let dir = tree.create_dir(ROOT_INODE, "dir", 0).unwrap();
+        let first = tree.create_file(ROOT_INODE, "first", 0).unwrap();
+        tree.create_file(dir, "second", 0).unwrap();
+
+        assert_eq!(
+            tree.rename(ROOT_INODE, "dir", dir, "nested"),
+            Err(ERRNO_INVAL)
+        );
+        assert_eq!(
+            tree.rename(ROOT_INODE, "first", ROOT_INODE, "dir"),
+            Err(ERRNO_ISDIR)
+        );
+
+        tree.rename(ROOT_INODE, "first", dir, "second").unwrap();
+        assert_eq!(tree.lookup(ROOT_INODE, "dir/second", false), Ok(first));
+        assert_eq!(tree.child(ROOT_INODE, "first"), Ok(None));
+        assert_eq!(tree.inodes_count(), 3);
+    }
+}
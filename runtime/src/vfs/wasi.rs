@@ -0,0 +1,948 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::errno::*;
+use super::tree::InodeId;
+use super::tree::InodeKind;
+use super::Errno;
+use super::SharedVfsTree;
+
+use serde::Deserialize;
+use serde::Serialize;
+use wasmer_core::import::ImportObject;
+use wasmer_core::import::Namespace;
+use wasmer_core::typed_func::DynamicFunc;
+use wasmer_core::types::FuncSig;
+use wasmer_core::types::Type as WType;
+use wasmer_core::types::Value as WValue;
+use wasmer_core::vm::Ctx;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+// WASI filetypes
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+const FILETYPE_SYMBOLIC_LINK: u8 = 7;
+
+// WASI flags of path_open, fd_fdstat_set_flags and fd_filestat_set_times
+const LOOKUP_SYMLINK_FOLLOW: i32 = 1;
+const O_CREAT: i32 = 1;
+const O_DIRECTORY: i32 = 2;
+const O_EXCL: i32 = 4;
+const O_TRUNC: i32 = 8;
+const FDFLAG_APPEND: u16 = 1;
+const FILESTAT_SET_ATIM: i32 = 1;
+const FILESTAT_SET_ATIM_NOW: i32 = 2;
+const FILESTAT_SET_MTIM: i32 = 4;
+const FILESTAT_SET_MTIM_NOW: i32 = 8;
+
+const WHENCE_SET: i32 = 0;
+const WHENCE_CUR: i32 = 1;
+const WHENCE_END: i32 = 2;
+
+// all rights defined by WASI snapshot1, they aren't enforced by the virtual filesystem
+const RIGHTS_ALL: u64 = (1 << 29) - 1;
+
+const FDSTAT_SIZE: usize = 24;
+const FILESTAT_SIZE: usize = 64;
+const DIRENT_HEADER_SIZE: usize = 24;
+
+/// File descriptors of a module instance, fds 0, 1 and 2 are stdin, stdout and stderr
+/// and preopened directories go right after them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FdTable {
+    fds: BTreeMap<u32, FileDescriptor>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct FileDescriptor {
+    kind: FdKind,
+    offset: u64,
+    flags: u16,
+    rights_base: u64,
+    rights_inheriting: u64,
+    preopen_name: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+enum FdKind {
+    Stdin,
+    Stdout,
+    Stderr,
+    Inode(InodeId),
+}
+
+impl FdTable {
+    pub(crate) fn new(preopens: Vec<(String, InodeId)>) -> Self {
+        let stdio = vec![
+            (None, FdKind::Stdin),
+            (None, FdKind::Stdout),
+            (None, FdKind::Stderr),
+        ];
+        let preopens = preopens
+            .into_iter()
+            .map(|(name, inode)| (Some(name), FdKind::Inode(inode)));
+
+        let fds = stdio
+            .into_iter()
+            .chain(preopens)
+            .enumerate()
+            .map(|(fd, (preopen_name, kind))| {
+                let descriptor = FileDescriptor {
+                    kind,
+                    offset: 0,
+                    flags: 0,
+                    rights_base: RIGHTS_ALL,
+                    rights_inheriting: RIGHTS_ALL,
+                    preopen_name,
+                };
+                (fd as u32, descriptor)
+            })
+            .collect();
+
+        Self { fds }
+    }
+
+    fn get(&self, fd: i32) -> Result<&FileDescriptor, Errno> {
+        self.fds.get(&(fd as u32)).ok_or(ERRNO_BADF)
+    }
+
+    fn get_mut(&mut self, fd: i32) -> Result<&mut FileDescriptor, Errno> {
+        self.fds.get_mut(&(fd as u32)).ok_or(ERRNO_BADF)
+    }
+
+    // like POSIX, the lowest free fd is used
+    fn insert(&mut self, descriptor: FileDescriptor) -> u32 {
+        let fd = (0..)
+            .find(|fd| !self.fds.contains_key(fd))
+            .unwrap_or_default();
+        self.fds.insert(fd, descriptor);
+
+        fd
+    }
+}
+
+/// WASI filesystem state of a module instance, its tree is shared with other instances.
+pub(crate) struct VfsInstance {
+    tree: SharedVfsTree,
+    fds: Mutex<FdTable>,
+    deterministic: bool,
+}
+
+// methods named after WASI syscalls mirror their signatures
+#[allow(clippy::too_many_arguments)]
+impl VfsInstance {
+    pub(crate) fn new(tree: SharedVfsTree, fds: FdTable, deterministic: bool) -> Self {
+        Self {
+            tree,
+            fds: Mutex::new(fds),
+            deterministic,
+        }
+    }
+
+    pub(crate) fn tree(&self) -> &SharedVfsTree {
+        &self.tree
+    }
+
+    pub(crate) fn fds(&self) -> FdTable {
+        self.fds.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_fds(&self, fds: FdTable) {
+        *self.fds.lock().unwrap() = fds;
+    }
+
+    // timestamps of deterministic modules are taken from their virtual clock
+    fn now(&self) -> u64 {
+        if self.deterministic {
+            return crate::deterministic::read_clock();
+        }
+
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64)
+    }
+
+    fn descriptor(&self, fd: i32) -> Result<FileDescriptor, Errno> {
+        self.fds.lock().unwrap().get(fd).map(Clone::clone)
+    }
+
+    fn inode(&self, fd: i32) -> Result<InodeId, Errno> {
+        match self.descriptor(fd)?.kind {
+            FdKind::Inode(inode) => Ok(inode),
+            _ => Err(ERRNO_NOTDIR),
+        }
+    }
+
+    fn read_iovecs(&self, ctx: &Ctx, iovs: i32, iovs_len: i32) -> Result<Vec<(i32, usize)>, Errno> {
+        let iovs = read_memory(ctx, iovs, (iovs_len as u32 as usize).saturating_mul(8))?;
+        let iovs = iovs
+            .chunks(8)
+            .map(|iov| {
+                let buf = u32::from_le_bytes([iov[0], iov[1], iov[2], iov[3]]) as i32;
+                let buf_len = u32::from_le_bytes([iov[4], iov[5], iov[6], iov[7]]) as usize;
+                (buf, buf_len)
+            })
+            .collect();
+
+        Ok(iovs)
+    }
+
+    fn gather(&self, ctx: &Ctx, iovs: i32, iovs_len: i32) -> Result<Vec<u8>, Errno> {
+        let mut data = Vec::new();
+        for (buf, buf_len) in self.read_iovecs(ctx, iovs, iovs_len)? {
+            data.extend(read_memory(ctx, buf, buf_len)?);
+        }
+
+        Ok(data)
+    }
+
+    fn scatter(&self, ctx: &Ctx, iovs: i32, iovs_len: i32, data: &[u8]) -> Result<(), Errno> {
+        let mut data = data;
+        for (buf, buf_len) in self.read_iovecs(ctx, iovs, iovs_len)? {
+            let (part, rest) = data.split_at(std::cmp::min(buf_len, data.len()));
+            write_memory(ctx, buf, part)?;
+            data = rest;
+        }
+
+        Ok(())
+    }
+
+    fn iovecs_len(&self, ctx: &Ctx, iovs: i32, iovs_len: i32) -> Result<usize, Errno> {
+        let iovs = self.read_iovecs(ctx, iovs, iovs_len)?;
+        Ok(iovs.iter().map(|(_, buf_len)| buf_len).sum())
+    }
+
+    fn read_at(
+        &self,
+        ctx: &Ctx,
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        offset: u64,
+    ) -> Result<usize, Errno> {
+        let inode = match self.descriptor(fd)?.kind {
+            FdKind::Inode(inode) => inode,
+            FdKind::Stdin => return Ok(0),
+            FdKind::Stdout | FdKind::Stderr => return Err(ERRNO_BADF),
+        };
+
+        let len = self.iovecs_len(ctx, iovs, iovs_len)?;
+        let tree = self.tree.lock().unwrap();
+        let data = tree.read(inode, offset, len)?;
+        self.scatter(ctx, iovs, iovs_len, data)?;
+
+        Ok(data.len())
+    }
+
+    // returns the number of written bytes and the offset right after them
+    fn write_at(
+        &self,
+        ctx: &Ctx,
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        offset: Option<u64>,
+    ) -> Result<(usize, u64), Errno> {
+        let data = self.gather(ctx, iovs, iovs_len)?;
+        let descriptor = self.descriptor(fd)?;
+        let inode = match descriptor.kind {
+            FdKind::Inode(inode) => inode,
+            FdKind::Stdin => return Err(ERRNO_BADF),
+            FdKind::Stdout => return write_host(std::io::stdout(), &data),
+            FdKind::Stderr => return write_host(std::io::stderr(), &data),
+        };
+
+        let mut tree = self.tree.lock().unwrap();
+        let offset = match offset {
+            Some(offset) => offset,
+            None if descriptor.flags & FDFLAG_APPEND != 0 => tree.inode(inode)?.size(),
+            None => descriptor.offset,
+        };
+        tree.write(inode, offset, &data)?;
+        let now = self.now();
+        tree.set_times(inode, None, Some(now))?;
+
+        Ok((data.len(), offset + data.len() as u64))
+    }
+
+    fn filestat(&self, fd_kind: FdKind) -> Result<[u8; FILESTAT_SIZE], Errno> {
+        let mut stat = [0u8; FILESTAT_SIZE];
+        let inode = match fd_kind {
+            FdKind::Inode(inode) => inode,
+            _ => {
+                stat[16] = FILETYPE_CHARACTER_DEVICE;
+                return Ok(stat);
+            }
+        };
+
+        let tree = self.tree.lock().unwrap();
+        let node = tree.inode(inode)?;
+        stat[8..16].copy_from_slice(&inode.to_le_bytes());
+        stat[16] = filetype(&node.kind);
+        stat[24..32].copy_from_slice(&node.nlink.to_le_bytes());
+        stat[32..40].copy_from_slice(&node.size().to_le_bytes());
+        stat[40..48].copy_from_slice(&node.atime.to_le_bytes());
+        stat[48..56].copy_from_slice(&node.mtime.to_le_bytes());
+        stat[56..64].copy_from_slice(&node.ctime.to_le_bytes());
+
+        Ok(stat)
+    }
+
+    fn set_times(&self, inode: InodeId, atim: i64, mtim: i64, fst_flags: i32) -> Result<(), Errno> {
+        let time = |value: i64, set_flag: i32, now_flag: i32| match (
+            fst_flags & set_flag != 0,
+            fst_flags & now_flag != 0,
+        ) {
+            (true, true) => Err(ERRNO_INVAL),
+            (true, false) => Ok(Some(value as u64)),
+            (false, true) => Ok(Some(self.now())),
+            (false, false) => Ok(None),
+        };
+
+        let atime = time(atim, FILESTAT_SET_ATIM, FILESTAT_SET_ATIM_NOW)?;
+        let mtime = time(mtim, FILESTAT_SET_MTIM, FILESTAT_SET_MTIM_NOW)?;
+        self.tree.lock().unwrap().set_times(inode, atime, mtime)
+    }
+
+    fn lookup(
+        &self,
+        ctx: &Ctx,
+        fd: i32,
+        flags: i32,
+        path: i32,
+        path_len: i32,
+    ) -> Result<InodeId, Errno> {
+        let dir = self.inode(fd)?;
+        let path = read_path(ctx, path, path_len)?;
+        self.tree
+            .lock()
+            .unwrap()
+            .lookup(dir, &path, flags & LOOKUP_SYMLINK_FOLLOW != 0)
+    }
+
+    fn lookup_parent(
+        &self,
+        ctx: &Ctx,
+        fd: i32,
+        path: i32,
+        path_len: i32,
+    ) -> Result<(InodeId, String), Errno> {
+        let dir = self.inode(fd)?;
+        let path = read_path(ctx, path, path_len)?;
+        self.tree.lock().unwrap().lookup_parent(dir, &path)
+    }
+
+    fn fd_advise(
+        &self,
+        _: &mut Ctx,
+        fd: i32,
+        _offset: i64,
+        _len: i64,
+        _advice: i32,
+    ) -> Result<(), Errno> {
+        self.descriptor(fd).map(|_| ())
+    }
+
+    fn fd_allocate(&self, _: &mut Ctx, fd: i32, offset: i64, len: i64) -> Result<(), Errno> {
+        let inode = self.inode(fd)?;
+        let size = (offset as u64).checked_add(len as u64).ok_or(ERRNO_FBIG)?;
+
+        let mut tree = self.tree.lock().unwrap();
+        if size > tree.inode(inode)?.size() {
+            tree.set_size(inode, size)?;
+        }
+
+        Ok(())
+    }
+
+    fn fd_close(&self, _: &mut Ctx, fd: i32) -> Result<(), Errno> {
+        let mut fds = self.fds.lock().unwrap();
+        fds.get(fd)?;
+        fds.fds.remove(&(fd as u32));
+
+        Ok(())
+    }
+
+    fn fd_datasync(&self, _: &mut Ctx, fd: i32) -> Result<(), Errno> {
+        self.descriptor(fd).map(|_| ())
+    }
+
+    fn fd_fdstat_get(&self, ctx: &mut Ctx, fd: i32, buf: i32) -> Result<(), Errno> {
+        let descriptor = self.descriptor(fd)?;
+        let filetype = match descriptor.kind {
+            FdKind::Inode(inode) => filetype(&self.tree.lock().unwrap().inode(inode)?.kind),
+            _ => FILETYPE_CHARACTER_DEVICE,
+        };
+
+        let mut stat = [0u8; FDSTAT_SIZE];
+        stat[0] = filetype;
+        stat[2..4].copy_from_slice(&descriptor.flags.to_le_bytes());
+        stat[8..16].copy_from_slice(&descriptor.rights_base.to_le_bytes());
+        stat[16..24].copy_from_slice(&descriptor.rights_inheriting.to_le_bytes());
+        write_memory(ctx, buf, &stat)
+    }
+
+    fn fd_fdstat_set_flags(&self, _: &mut Ctx, fd: i32, flags: i32) -> Result<(), Errno> {
+        self.fds.lock().unwrap().get_mut(fd)?.flags = flags as u16;
+        Ok(())
+    }
+
+    fn fd_fdstat_set_rights(
+        &self,
+        _: &mut Ctx,
+        fd: i32,
+        base: i64,
+        inheriting: i64,
+    ) -> Result<(), Errno> {
+        let mut fds = self.fds.lock().unwrap();
+        let descriptor = fds.get_mut(fd)?;
+        // rights could be only dropped
+        if base as u64 & !descriptor.rights_base != 0
+            || inheriting as u64 & !descriptor.rights_inheriting != 0
+        {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+
+        descriptor.rights_base = base as u64;
+        descriptor.rights_inheriting = inheriting as u64;
+        Ok(())
+    }
+
+    fn fd_filestat_get(&self, ctx: &mut Ctx, fd: i32, buf: i32) -> Result<(), Errno> {
+        let stat = self.filestat(self.descriptor(fd)?.kind)?;
+        write_memory(ctx, buf, &stat)
+    }
+
+    fn fd_filestat_set_size(&self, _: &mut Ctx, fd: i32, size: i64) -> Result<(), Errno> {
+        let inode = self.inode(fd)?;
+        self.tree.lock().unwrap().set_size(inode, size as u64)
+    }
+
+    fn fd_filestat_set_times(
+        &self,
+        _: &mut Ctx,
+        fd: i32,
+        atim: i64,
+        mtim: i64,
+        fst_flags: i32,
+    ) -> Result<(), Errno> {
+        let inode = self.inode(fd)?;
+        self.set_times(inode, atim, mtim, fst_flags)
+    }
+
+    fn fd_pread(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        offset: i64,
+        nread: i32,
+    ) -> Result<(), Errno> {
+        let read = self.read_at(ctx, fd, iovs, iovs_len, offset as u64)?;
+        write_memory(ctx, nread, &(read as u32).to_le_bytes())
+    }
+
+    fn fd_prestat_get(&self, ctx: &mut Ctx, fd: i32, buf: i32) -> Result<(), Errno> {
+        let name = self.descriptor(fd)?.preopen_name.ok_or(ERRNO_BADF)?;
+
+        let mut prestat = [0u8; 8];
+        prestat[4..8].copy_from_slice(&(name.len() as u32).to_le_bytes());
+        write_memory(ctx, buf, &prestat)
+    }
+
+    fn fd_prestat_dir_name(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        path: i32,
+        path_len: i32,
+    ) -> Result<(), Errno> {
+        let name = self.descriptor(fd)?.preopen_name.ok_or(ERRNO_BADF)?;
+        if (path_len as u32 as usize) < name.len() {
+            return Err(ERRNO_INVAL);
+        }
+
+        write_memory(ctx, path, name.as_bytes())
+    }
+
+    fn fd_pwrite(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        offset: i64,
+        nwritten: i32,
+    ) -> Result<(), Errno> {
+        let (written, _) = self.write_at(ctx, fd, iovs, iovs_len, Some(offset as u64))?;
+        write_memory(ctx, nwritten, &(written as u32).to_le_bytes())
+    }
+
+    fn fd_read(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        nread: i32,
+    ) -> Result<(), Errno> {
+        let offset = self.descriptor(fd)?.offset;
+        let read = self.read_at(ctx, fd, iovs, iovs_len, offset)?;
+        self.fds.lock().unwrap().get_mut(fd)?.offset = offset + read as u64;
+
+        write_memory(ctx, nread, &(read as u32).to_le_bytes())
+    }
+
+    fn fd_readdir(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        buf: i32,
+        buf_len: i32,
+        cookie: i64,
+        bufused: i32,
+    ) -> Result<(), Errno> {
+        let dir = self.inode(fd)?;
+        let buf_len = buf_len as u32 as usize;
+        let tree = self.tree.lock().unwrap();
+
+        let mut dirents = Vec::new();
+        for (position, (name, inode)) in tree
+            .read_dir(dir)?
+            .into_iter()
+            .enumerate()
+            .skip(cookie as u64 as usize)
+        {
+            if dirents.len() >= buf_len {
+                break;
+            }
+
+            let mut header = [0u8; DIRENT_HEADER_SIZE];
+            header[0..8].copy_from_slice(&(position as u64 + 1).to_le_bytes());
+            header[8..16].copy_from_slice(&inode.to_le_bytes());
+            header[16..20].copy_from_slice(&(name.len() as u32).to_le_bytes());
+            header[20] = filetype(&tree.inode(inode)?.kind);
+            dirents.extend_from_slice(&header);
+            dirents.extend_from_slice(name.as_bytes());
+        }
+        // the last entry could be truncated, then the module retries with a bigger buffer
+        dirents.truncate(buf_len);
+
+        write_memory(ctx, buf, &dirents)?;
+        write_memory(ctx, bufused, &(dirents.len() as u32).to_le_bytes())
+    }
+
+    fn fd_renumber(&self, _: &mut Ctx, from: i32, to: i32) -> Result<(), Errno> {
+        let mut fds = self.fds.lock().unwrap();
+        fds.get(to)?;
+        let descriptor = fds.fds.remove(&(from as u32)).ok_or(ERRNO_BADF)?;
+        fds.fds.insert(to as u32, descriptor);
+
+        Ok(())
+    }
+
+    fn fd_seek(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        offset: i64,
+        whence: i32,
+        newoffset: i32,
+    ) -> Result<(), Errno> {
+        let descriptor = self.descriptor(fd)?;
+        let inode = match descriptor.kind {
+            FdKind::Inode(inode) => inode,
+            _ => return Err(ERRNO_SPIPE),
+        };
+
+        let base = match whence {
+            WHENCE_SET => 0,
+            WHENCE_CUR => descriptor.offset as i64,
+            WHENCE_END => self.tree.lock().unwrap().inode(inode)?.size() as i64,
+            _ => return Err(ERRNO_INVAL),
+        };
+        let new_offset = base
+            .checked_add(offset)
+            .filter(|offset| *offset >= 0)
+            .ok_or(ERRNO_INVAL)?;
+        self.fds.lock().unwrap().get_mut(fd)?.offset = new_offset as u64;
+
+        write_memory(ctx, newoffset, &(new_offset as u64).to_le_bytes())
+    }
+
+    fn fd_sync(&self, _: &mut Ctx, fd: i32) -> Result<(), Errno> {
+        self.descriptor(fd).map(|_| ())
+    }
+
+    fn fd_tell(&self, ctx: &mut Ctx, fd: i32, offset: i32) -> Result<(), Errno> {
+        let descriptor = self.descriptor(fd)?;
+        write_memory(ctx, offset, &descriptor.offset.to_le_bytes())
+    }
+
+    fn fd_write(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        nwritten: i32,
+    ) -> Result<(), Errno> {
+        let (written, end) = self.write_at(ctx, fd, iovs, iovs_len, None)?;
+        let mut fds = self.fds.lock().unwrap();
+        let descriptor = fds.get_mut(fd)?;
+        if let FdKind::Inode(_) = descriptor.kind {
+            descriptor.offset = end;
+        }
+        drop(fds);
+
+        write_memory(ctx, nwritten, &(written as u32).to_le_bytes())
+    }
+
+    fn path_create_directory(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        path: i32,
+        path_len: i32,
+    ) -> Result<(), Errno> {
+        let (parent, name) = self.lookup_parent(ctx, fd, path, path_len)?;
+        let now = self.now();
+        self.tree
+            .lock()
+            .unwrap()
+            .create_dir(parent, &name, now)
+            .map(|_| ())
+    }
+
+    fn path_filestat_get(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        flags: i32,
+        path: i32,
+        path_len: i32,
+        buf: i32,
+    ) -> Result<(), Errno> {
+        let inode = self.lookup(ctx, fd, flags, path, path_len)?;
+        let stat = self.filestat(FdKind::Inode(inode))?;
+        write_memory(ctx, buf, &stat)
+    }
+
+    fn path_filestat_set_times(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        flags: i32,
+        path: i32,
+        path_len: i32,
+        atim: i64,
+        mtim: i64,
+        fst_flags: i32,
+    ) -> Result<(), Errno> {
+        let inode = self.lookup(ctx, fd, flags, path, path_len)?;
+        self.set_times(inode, atim, mtim, fst_flags)
+    }
+
+    fn path_link(
+        &self,
+        ctx: &mut Ctx,
+        old_fd: i32,
+        old_flags: i32,
+        old_path: i32,
+        old_path_len: i32,
+        new_fd: i32,
+        new_path: i32,
+        new_path_len: i32,
+    ) -> Result<(), Errno> {
+        let target = self.lookup(ctx, old_fd, old_flags, old_path, old_path_len)?;
+        let (parent, name) = self.lookup_parent(ctx, new_fd, new_path, new_path_len)?;
+        self.tree.lock().unwrap().link(parent, &name, target)
+    }
+
+    fn path_open(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        dirflags: i32,
+        path: i32,
+        path_len: i32,
+        oflags: i32,
+        rights_base: i64,
+        rights_inheriting: i64,
+        fdflags: i32,
+        opened_fd: i32,
+    ) -> Result<(), Errno> {
+        let dir = self.inode(fd)?;
+        let path = read_path(ctx, path, path_len)?;
+        let now = self.now();
+
+        let mut tree = self.tree.lock().unwrap();
+        let inode = match tree.lookup(dir, &path, dirflags & LOOKUP_SYMLINK_FOLLOW != 0) {
+            Ok(_) if oflags & O_CREAT != 0 && oflags & O_EXCL != 0 => return Err(ERRNO_EXIST),
+            Ok(inode) => inode,
+            Err(ERRNO_NOENT) if oflags & O_CREAT != 0 => {
+                let (parent, name) = tree.lookup_parent(dir, &path)?;
+                tree.create_file(parent, &name, now)?
+            }
+            Err(errno) => return Err(errno),
+        };
+
+        match filetype(&tree.inode(inode)?.kind) {
+            FILETYPE_SYMBOLIC_LINK => return Err(ERRNO_LOOP),
+            FILETYPE_DIRECTORY if oflags & O_TRUNC != 0 => return Err(ERRNO_ISDIR),
+            FILETYPE_REGULAR_FILE if oflags & O_DIRECTORY != 0 => return Err(ERRNO_NOTDIR),
+            FILETYPE_REGULAR_FILE if oflags & O_TRUNC != 0 => tree.set_size(inode, 0)?,
+            _ => {}
+        }
+        drop(tree);
+
+        let descriptor = FileDescriptor {
+            kind: FdKind::Inode(inode),
+            offset: 0,
+            flags: fdflags as u16,
+            rights_base: rights_base as u64,
+            rights_inheriting: rights_inheriting as u64,
+            preopen_name: None,
+        };
+        let new_fd = self.fds.lock().unwrap().insert(descriptor);
+
+        write_memory(ctx, opened_fd, &new_fd.to_le_bytes())
+    }
+
+    fn path_readlink(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        path: i32,
+        path_len: i32,
+        buf: i32,
+        buf_len: i32,
+        bufused: i32,
+    ) -> Result<(), Errno> {
+        let inode = self.lookup(ctx, fd, 0, path, path_len)?;
+        let tree = self.tree.lock().unwrap();
+        let target = match &tree.inode(inode)?.kind {
+            InodeKind::Symlink { target } => target.as_bytes(),
+            _ => return Err(ERRNO_INVAL),
+        };
+
+        let target = &target[..std::cmp::min(target.len(), buf_len as u32 as usize)];
+        write_memory(ctx, buf, target)?;
+        write_memory(ctx, bufused, &(target.len() as u32).to_le_bytes())
+    }
+
+    fn path_remove_directory(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        path: i32,
+        path_len: i32,
+    ) -> Result<(), Errno> {
+        let (parent, name) = self.lookup_parent(ctx, fd, path, path_len)?;
+        let mut tree = self.tree.lock().unwrap();
+        let dir = tree.child(parent, &name)?.ok_or(ERRNO_NOENT)?;
+        if !tree.inode(dir)?.is_dir() {
+            return Err(ERRNO_NOTDIR);
+        }
+
+        tree.remove_dir(parent, &name)
+    }
+
+    fn path_rename(
+        &self,
+        ctx: &mut Ctx,
+        old_fd: i32,
+        old_path: i32,
+        old_path_len: i32,
+        new_fd: i32,
+        new_path: i32,
+        new_path_len: i32,
+    ) -> Result<(), Errno> {
+        let (old_parent, old_name) = self.lookup_parent(ctx, old_fd, old_path, old_path_len)?;
+        let (new_parent, new_name) = self.lookup_parent(ctx, new_fd, new_path, new_path_len)?;
+        self.tree
+            .lock()
+            .unwrap()
+            .rename(old_parent, &old_name, new_parent, &new_name)
+    }
+
+    fn path_symlink(
+        &self,
+        ctx: &mut Ctx,
+        old_path: i32,
+        old_path_len: i32,
+        fd: i32,
+        new_path: i32,
+        new_path_len: i32,
+    ) -> Result<(), Errno> {
+        let target = read_path(ctx, old_path, old_path_len)?;
+        let (parent, name) = self.lookup_parent(ctx, fd, new_path, new_path_len)?;
+        let now = self.now();
+        self.tree
+            .lock()
+            .unwrap()
+            .create_symlink(parent, &name, &target, now)
+            .map(|_| ())
+    }
+
+    fn path_unlink_file(
+        &self,
+        ctx: &mut Ctx,
+        fd: i32,
+        path: i32,
+        path_len: i32,
+    ) -> Result<(), Errno> {
+        let (parent, name) = self.lookup_parent(ctx, fd, path, path_len)?;
+        self.tree.lock().unwrap().unlink(parent, &name)
+    }
+}
+
+/// Arguments of WASI syscalls, they are already checked by Wasmer against the signature.
+trait SyscallArg {
+    const TYPE: WType;
+
+    fn from_wvalue(value: Option<&WValue>) -> Self;
+}
+
+impl SyscallArg for i32 {
+    const TYPE: WType = WType::I32;
+
+    fn from_wvalue(value: Option<&WValue>) -> Self {
+        match value {
+            Some(WValue::I32(value)) => *value,
+            _ => 0,
+        }
+    }
+}
+
+impl SyscallArg for i64 {
+    const TYPE: WType = WType::I64;
+
+    fn from_wvalue(value: Option<&WValue>) -> Self {
+        match value {
+            Some(WValue::I64(value)) => *value,
+            _ => 0,
+        }
+    }
+}
+
+// imports are dynamic functions, because typed ones are required to be Send
+macro_rules! vfs_imports {
+    ($vfs:ident, $namespace:ident, $($name:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $({
+            let vfs = $vfs.clone();
+            let signature = FuncSig::new(vec![$(<$ty as SyscallArg>::TYPE),*], vec![WType::I32]);
+            let import = move |ctx: &mut Ctx, args: &[WValue]| -> Vec<WValue> {
+                let mut args = args.iter();
+                $(let $arg = <$ty as SyscallArg>::from_wvalue(args.next());)*
+                let errno = match vfs.$name(ctx, $($arg),*) {
+                    Ok(()) => ERRNO_SUCCESS,
+                    Err(errno) => errno,
+                };
+                vec![WValue::I32(errno)]
+            };
+            $namespace.insert(stringify!($name), DynamicFunc::new(Arc::new(signature), import));
+        })*
+    };
+}
+
+/// Creates WASI filesystem imports backed by the virtual filesystem.
+pub(crate) fn create_vfs_wasi_imports(vfs: &Arc<VfsInstance>) -> ImportObject {
+    let mut namespace = Namespace::new();
+
+    #[rustfmt::skip]
+    vfs_imports!(vfs, namespace,
+        fd_advise(fd: i32, offset: i64, len: i64, advice: i32),
+        fd_allocate(fd: i32, offset: i64, len: i64),
+        fd_close(fd: i32),
+        fd_datasync(fd: i32),
+        fd_fdstat_get(fd: i32, buf: i32),
+        fd_fdstat_set_flags(fd: i32, flags: i32),
+        fd_fdstat_set_rights(fd: i32, base: i64, inheriting: i64),
+        fd_filestat_get(fd: i32, buf: i32),
+        fd_filestat_set_size(fd: i32, size: i64),
+        fd_filestat_set_times(fd: i32, atim: i64, mtim: i64, fst_flags: i32),
+        fd_pread(fd: i32, iovs: i32, iovs_len: i32, offset: i64, nread: i32),
+        fd_prestat_get(fd: i32, buf: i32),
+        fd_prestat_dir_name(fd: i32, path: i32, path_len: i32),
+        fd_pwrite(fd: i32, iovs: i32, iovs_len: i32, offset: i64, nwritten: i32),
+        fd_read(fd: i32, iovs: i32, iovs_len: i32, nread: i32),
+        fd_readdir(fd: i32, buf: i32, buf_len: i32, cookie: i64, bufused: i32),
+        fd_renumber(from: i32, to: i32),
+        fd_seek(fd: i32, offset: i64, whence: i32, newoffset: i32),
+        fd_sync(fd: i32),
+        fd_tell(fd: i32, offset: i32),
+        fd_write(fd: i32, iovs: i32, iovs_len: i32, nwritten: i32),
+        path_create_directory(fd: i32, path: i32, path_len: i32),
+        path_filestat_get(fd: i32, flags: i32, path: i32, path_len: i32, buf: i32),
+        path_filestat_set_times(fd: i32, flags: i32, path: i32, path_len: i32, atim: i64, mtim: i64, fst_flags: i32),
+        path_link(old_fd: i32, old_flags: i32, old_path: i32, old_path_len: i32, new_fd: i32, new_path: i32, new_path_len: i32),
+        path_open(fd: i32, dirflags: i32, path: i32, path_len: i32, oflags: i32, rights_base: i64, rights_inheriting: i64, fdflags: i32, opened_fd: i32),
+        path_readlink(fd: i32, path: i32, path_len: i32, buf: i32, buf_len: i32, bufused: i32),
+        path_remove_directory(fd: i32, path: i32, path_len: i32),
+        path_rename(old_fd: i32, old_path: i32, old_path_len: i32, new_fd: i32, new_path: i32, new_path_len: i32),
+        path_symlink(old_path: i32, old_path_len: i32, fd: i32, new_path: i32, new_path_len: i32),
+        path_unlink_file(fd: i32, path: i32, path_len: i32),
+    );
+
+    let mut import_object = ImportObject::new();
+    import_object.register(super::VFS_WASI_NAMESPACE, namespace);
+
+    import_object
+}
+
+fn filetype(kind: &InodeKind) -> u8 {
+    match kind {
+        InodeKind::Directory { .. } => FILETYPE_DIRECTORY,
+        InodeKind::File { .. } => FILETYPE_REGULAR_FILE,
+        InodeKind::Symlink { .. } => FILETYPE_SYMBOLIC_LINK,
+    }
+}
+
+fn write_host(mut output: impl Write, data: &[u8]) -> Result<(usize, u64), Errno> {
+    output.write_all(data).map_err(|_| ERRNO_IO)?;
+    Ok((data.len(), 0))
+}
+
+fn read_memory(ctx: &Ctx, offset: i32, len: usize) -> Result<Vec<u8>, Errno> {
+    let offset = offset as u32 as usize;
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view.get(offset..offset + len).ok_or(ERRNO_FAULT)?;
+
+    Ok(cells.iter().map(|cell| cell.get()).collect())
+}
+
+fn write_memory(ctx: &Ctx, offset: i32, bytes: &[u8]) -> Result<(), Errno> {
+    let offset = offset as u32 as usize;
+    let view = ctx.memory(0).view::<u8>();
+    let cells = view.get(offset..offset + bytes.len()).ok_or(ERRNO_FAULT)?;
+    for (cell, &byte) in cells.iter().zip(bytes) {
+        cell.set(byte);
+    }
+
+    Ok(())
+}
+
+fn read_path(ctx: &Ctx, path: i32, path_len: i32) -> Result<String, Errno> {
+    let path = read_memory(ctx, path, path_len as u32 as usize)?;
+    String::from_utf8(path).map_err(|_| ERRNO_ILSEQ)
+}
@@ -0,0 +1,283 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::misc::WASI_NAMESPACES;
+
+use wasmer_core::error::RuntimeError;
+use wasmer_core::import::ImportObject;
+use wasmer_core::import::Namespace;
+use wasmer_core::typed_func::DynamicFunc;
+use wasmer_core::types::Type as WType;
+use wasmer_core::types::Value as WValue;
+use wasmer_core::vm::Ctx;
+use wasmer_core::Module as WasmerModule;
+use wasmer_wasi::types::__WASI_ENOSYS;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Groups of WASI functions available to a module, functions of a disabled group are replaced
+/// with stubs returning ENOSYS, a disabled proc_exit traps the module. All groups are enabled
+/// by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WasiCapabilities {
+    /// Access to preopened files and mapped dirs through path_* functions. If disabled,
+    /// no host directories are provided to the module, but it still could use stdio.
+    pub filesystem: bool,
+
+    /// clock_res_get and clock_time_get.
+    pub clock: bool,
+
+    /// random_get.
+    pub random: bool,
+
+    /// environ_get and environ_sizes_get, if disabled, no environment variables are provided.
+    pub environ: bool,
+
+    /// proc_exit and proc_raise.
+    pub proc_exit: bool,
+
+    /// poll_oneoff and sched_yield.
+    pub poll: bool,
+}
+
+impl Default for WasiCapabilities {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl WasiCapabilities {
+    /// All WASI functions are available.
+    pub fn all() -> Self {
+        Self {
+            filesystem: true,
+            clock: true,
+            random: true,
+            environ: true,
+            proc_exit: true,
+            poll: true,
+        }
+    }
+
+    /// No ambient authority, that's enough for pure modules.
+    pub fn none() -> Self {
+        Self {
+            filesystem: false,
+            clock: false,
+            random: false,
+            environ: false,
+            proc_exit: false,
+            poll: false,
+        }
+    }
+
+    fn is_allowed(&self, function_name: &str) -> bool {
+        match function_name {
+            "clock_res_get" | "clock_time_get" => self.clock,
+            "random_get" => self.random,
+            "environ_get" | "environ_sizes_get" => self.environ,
+            "proc_exit" | "proc_raise" => self.proc_exit,
+            "poll_oneoff" | "sched_yield" => self.poll,
+            name if name.starts_with("path_") => self.filesystem,
+            _ => true,
+        }
+    }
+}
+
+/// Creates stubs for WASI functions imported by the module which aren't allowed by capabilities,
+/// None if all of them are allowed.
+pub(crate) fn create_disabled_wasi_imports(
+    wasmer_module: &WasmerModule,
+    capabilities: &WasiCapabilities,
+) -> Option<ImportObject> {
+    let module_info = wasmer_module.info();
+
+    let mut namespaces = HashMap::new();
+    for (func_index, import_name) in module_info.imported_functions.iter() {
+        let namespace = module_info.namespace_table.get(import_name.namespace_index);
+        let name = module_info.name_table.get(import_name.name_index);
+        if !WASI_NAMESPACES.contains(&namespace) || capabilities.is_allowed(name) {
+            continue;
+        }
+
+        let sig_index = module_info.func_assoc[func_index.convert_up(module_info)];
+        let signature = module_info.signatures[sig_index].clone();
+        // all WASI functions except proc_exit return errno, proc_exit doesn't return at all,
+        // so its stub traps the module instead of letting it run past the call
+        let results = match signature.returns() {
+            [WType::I32] => Some(vec![WValue::I32(__WASI_ENOSYS as i32)]),
+            _ => None,
+        };
+        let trap_message = format!("WASI function {} is disabled", name);
+        // imports are dynamic functions, because typed ones can't take a signature of a module
+        let stub = move |ctx: &mut Ctx, _: &[WValue]| -> Vec<WValue> {
+            match &results {
+                Some(results) => results.clone(),
+                None => unsafe {
+                    let trap = RuntimeError::User(Box::new(trap_message.clone()));
+                    (*ctx.module).runnable_module.do_early_trap(trap)
+                },
+            }
+        };
+
+        namespaces
+            .entry(namespace)
+            .or_insert_with(Namespace::new)
+            .insert(name, DynamicFunc::new(Arc::new(signature), stub));
+    }
+
+    if namespaces.is_empty() {
+        return None;
+    }
+
+    let mut import_object = ImportObject::new();
+    for (namespace_name, namespace) in namespaces {
+        import_object.register(namespace_name, namespace);
+    }
+
+    Some(import_object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use parity_wasm::elements::*;
+
+    // a module with functions that return errno of WASI calls
+    fn wasi_consumer() -> Vec<u8> {
+        let time_type = FunctionType::new(
+            vec![ValueType::I32, ValueType::I64, ValueType::I32],
+            Some(ValueType::I32),
+        );
+        let random_type =
+            FunctionType::new(vec![ValueType::I32, ValueType::I32], Some(ValueType::I32));
+        let caller_type = FunctionType::new(vec![], Some(ValueType::I32));
+        let exit_type = FunctionType::new(vec![ValueType::I32], None);
+
+        let import = |name: &str, type_index| {
+            ImportEntry::new(
+                String::from("wasi_snapshot_preview1"),
+                name.to_string(),
+                External::Function(type_index),
+            )
+        };
+        let caller = |mut instructions: Vec<Instruction>| {
+            instructions.push(Instruction::End);
+            FuncBody::new(vec![], Instructions::new(instructions))
+        };
+
+        let module = Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![
+                Type::Function(time_type),
+                Type::Function(random_type),
+                Type::Function(caller_type),
+                Type::Function(exit_type),
+            ])),
+            Section::Import(ImportSection::with_entries(vec![
+                import("clock_time_get", 0),
+                import("random_get", 1),
+                import("proc_exit", 3),
+            ])),
+            Section::Function(FunctionSection::with_entries(vec![
+                Func::new(2),
+                Func::new(2),
+                Func::new(2),
+            ])),
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None)])),
+            Section::Export(ExportSection::with_entries(vec![
+                ExportEntry::new(String::from("now"), Internal::Function(3)),
+                ExportEntry::new(String::from("random"), Internal::Function(4)),
+                ExportEntry::new(String::from("exit"), Internal::Function(5)),
+            ])),
+            Section::Code(CodeSection::with_bodies(vec![
+                caller(vec![
+                    Instruction::I32Const(0),
+                    Instruction::I64Const(0),
+                    Instruction::I32Const(16),
+                    Instruction::Call(0),
+                ]),
+                caller(vec![
+                    Instruction::I32Const(16),
+                    Instruction::I32Const(8),
+                    Instruction::Call(1),
+                ]),
+                // returns 1 if proc_exit returns
+                caller(vec![
+                    Instruction::I32Const(0),
+                    Instruction::Call(2),
+                    Instruction::I32Const(1),
+                ]),
+            ])),
+        ]);
+
+        parity_wasm::serialize(module).unwrap()
+    }
+
+    #[test]
+    fn disabled_wasi_imports_return_enosys() {
+        let module = wasmer_runtime::compile(&wasi_consumer()).unwrap();
+        let mut import_object = wasmer_wasi::generate_import_object_for_version(
+            wasmer_wasi::WasiVersion::Latest,
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        assert!(create_disabled_wasi_imports(&module, &WasiCapabilities::all()).is_none());
+
+        let capabilities = WasiCapabilities {
+            random: false,
+            ..WasiCapabilities::all()
+        };
+        let stubs = create_disabled_wasi_imports(&module, &capabilities).unwrap();
+        import_object.extend(stubs.clone());
+        let instance = module.instantiate(&import_object).unwrap();
+
+        let now: wasmer_runtime::Func<'_, (), i32> = instance.exports.get("now").unwrap();
+        let random: wasmer_runtime::Func<'_, (), i32> = instance.exports.get("random").unwrap();
+        assert_eq!(now.call().unwrap(), 0);
+        assert_eq!(random.call().unwrap(), __WASI_ENOSYS as i32);
+    }
+
+    #[test]
+    fn disabled_proc_exit_traps() {
+        let module = wasmer_runtime::compile(&wasi_consumer()).unwrap();
+        let mut import_object = wasmer_wasi::generate_import_object_for_version(
+            wasmer_wasi::WasiVersion::Latest,
+            vec![],
+            vec![],
+            vec![],
+            vec![],
+        )
+        .unwrap();
+
+        let capabilities = WasiCapabilities {
+            proc_exit: false,
+            ..WasiCapabilities::all()
+        };
+        let stubs = create_disabled_wasi_imports(&module, &capabilities).unwrap();
+        import_object.extend(stubs.clone());
+        let instance = module.instantiate(&import_object).unwrap();
+
+        let exit: wasmer_runtime::Func<'_, (), i32> = instance.exports.get("exit").unwrap();
+        let error = exit.call().unwrap_err();
+        assert!(error.to_string().contains("proc_exit is disabled"));
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod utils;
+
+use utils::put;
+
+use marine::DiskQuota;
+use marine::Marine;
+
+use std::path::Path;
+use std::sync::Arc;
+
+fn local_storage(sites_dir: &Path, quota: Arc<DiskQuota>) -> Marine {
+    utils::local_storage(|config| {
+        utils::with_sites_dir(config.with_wasi_disk_quota(quota), sites_dir)
+    })
+}
+
+fn assert_no_space(result: String) {
+    assert!(
+        result.contains("No space left on device"),
+        "unexpected result {}",
+        result
+    );
+}
+
+#[test]
+pub fn disk_quota_for_dirs() {
+    let sites_dir = utils::temp_dir("marine_disk_quota_for_dirs");
+    let dir = sites_dir.path().to_path_buf();
+    std::fs::write(dir.join("existing"), &[1; 8]).unwrap();
+    let quota = Arc::new(DiskQuota::for_dirs(24, vec![dir.clone()]));
+    let mut marine = local_storage(&dir, quota.clone());
+
+    // files that existed before are accounted too
+    assert_eq!(put(&mut marine, "file", &[1; 16]), "Ok");
+    assert_eq!(quota.usage(), 24);
+    assert_no_space(put(&mut marine, "big", &[1; 1]));
+
+    // space of a truncated file could be reused
+    assert_eq!(put(&mut marine, "file", &[1; 8]), "Ok");
+    assert_eq!(put(&mut marine, "other", &[1; 8]), "Ok");
+    assert_eq!(quota.usage(), 24);
+
+    // files removed not through the quota aren't noticed by writes right after a recount
+    std::fs::remove_file(dir.join("other")).unwrap();
+    assert_no_space(put(&mut marine, "big", &[1; 1]));
+    assert_eq!(quota.usage(), 16);
+    assert_eq!(put(&mut marine, "big", &[1; 1]), "Ok");
+}
+
+#[test]
+pub fn disk_quota_for_writes() {
+    let sites_dir = utils::temp_dir("marine_disk_quota_for_writes");
+    let dir = sites_dir.path().to_path_buf();
+    std::fs::write(dir.join("existing"), &[1; 32]).unwrap();
+    let quota = Arc::new(DiskQuota::for_writes(16, vec![dir.clone()]));
+    let mut marine = local_storage(&dir, quota.clone());
+
+    // only files written by the module are accounted
+    assert_eq!(put(&mut marine, "file", &[1; 16]), "Ok");
+    assert_eq!(quota.usage(), 16);
+    assert_no_space(put(&mut marine, "big", &[1; 1]));
+
+    // removed files don't take space anymore
+    std::fs::remove_file(dir.join("file")).unwrap();
+    assert_eq!(put(&mut marine, "big", &[1; 1]), "Ok");
+    assert_eq!(quota.usage(), 1);
+}
+
+#[test]
+pub fn disk_quota_shared_by_modules() {
+    let sites_dir = utils::temp_dir("marine_disk_quota_shared");
+    let dir = sites_dir.path().to_path_buf();
+    let quota = Arc::new(DiskQuota::for_dirs(16, vec![dir.clone()]));
+    let mut first = local_storage(&dir, quota.clone());
+    let mut second = local_storage(&dir, quota.clone());
+
+    assert_eq!(put(&mut first, "first", &[1; 16]), "Ok");
+    assert_no_space(put(&mut second, "second", &[1; 1]));
+}
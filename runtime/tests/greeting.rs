@@ -114,3 +114,333 @@ pub fn non_exist_module_func() {
         marine::MError::NoSuchModule(non_exist_name)
     ));
 }
+
+#[test]
+// test that a module can't allocate more memory than it's allowed by config
+pub fn memory_limit_exceeded() {
+    let mut marine = Marine::new();
+    let mem_pages_count = 20;
+    let config = marine::MModuleConfig::default().with_mem_pages_count(mem_pages_count);
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    // 10 Mb string doesn't fit into 20 pages (~1.3 Mb) of memory
+    let huge_name = "a".repeat(10 * 1024 * 1024);
+    let call_result = marine.call("greeting", "greeting", &[IValue::String(huge_name)]);
+
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::MemoryLimitExceeded { limit, .. } if limit == mem_pages_count
+    ));
+}
+
+#[test]
+// test loading module with memory limit less than its initial memory size
+pub fn memory_limit_less_than_initial() {
+    let mut marine = Marine::new();
+    let config = marine::MModuleConfig::default().with_mem_pages_count(1);
+    let load_result = marine.load_module("greeting", &*GREETING_WASM_BYTES, config);
+
+    assert!(matches!(
+        load_result.err().unwrap(),
+        marine::MError::MemoryLimitExceeded { limit: 1, .. }
+    ));
+}
+
+#[test]
+// test that calls of a module with enabled fuel metering consume fuel and can run out of it
+pub fn fuel_metering() {
+    let mut marine = Marine::new();
+    let config = marine::MModuleConfig::default().with_fuel_metering(true);
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let arguments = [IValue::String(String::from("Fluence"))];
+    let (result, consumed_fuel) = marine
+        .call_with_fuel("greeting", "greeting", &arguments, u64::MAX)
+        .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+
+    assert_eq!(result, vec![IValue::String(String::from("Hi, Fluence"))]);
+    assert!(consumed_fuel > 0);
+
+    let call_result = marine.call_with_fuel("greeting", "greeting", &arguments, consumed_fuel / 2);
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::OutOfFuel { .. }
+    ));
+}
+
+#[test]
+// test that a call of an interruptible module is stopped by a cancelled deadline
+pub fn cancelled_call() {
+    let mut marine = Marine::new();
+    let config = marine::MModuleConfig::default().with_interruptible(true);
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let arguments = [IValue::String(String::from("Fluence"))];
+    let handle = marine::CancellationHandle::new();
+    let result = marine
+        .call_with_deadline("greeting", "greeting", &arguments, handle.clone())
+        .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+    assert_eq!(result, vec![IValue::String(String::from("Hi, Fluence"))]);
+
+    handle.cancel();
+    let call_result = marine.call_with_deadline("greeting", "greeting", &arguments, handle);
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::CallTimedOut(module_name) if module_name == "greeting"
+    ));
+
+    // the module stays usable after an interrupted call
+    let result = marine
+        .call("greeting", "greeting", &arguments)
+        .unwrap_or_else(|e| panic!("can't invoke greeting after interruption: {:?}", e));
+    assert_eq!(result, vec![IValue::String(String::from("Hi, Fluence"))]);
+}
+
+#[test]
+// test that a module state could be captured, serialized and restored
+pub fn snapshot_and_restore() {
+    let mut marine = Marine::new();
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let take_snapshot = |marine: &mut Marine| {
+        marine
+            .snapshot_module("greeting")
+            .unwrap_or_else(|e| panic!("can't take a snapshot of greeting: {:?}", e))
+    };
+
+    let initial_snapshot = take_snapshot(&mut marine);
+    assert!(initial_snapshot.memory_size() > 0);
+
+    let snapshot_bytes = initial_snapshot
+        .to_bytes()
+        .unwrap_or_else(|e| panic!("can't serialize a snapshot: {:?}", e));
+    let deserialized_snapshot = marine::ModuleSnapshot::from_bytes(&snapshot_bytes)
+        .unwrap_or_else(|e| panic!("can't deserialize a snapshot: {:?}", e));
+    assert_eq!(deserialized_snapshot, initial_snapshot);
+
+    // the module allocates its arguments and results in its memory, so a call changes it
+    let arguments = [IValue::String(String::from("Fluence"))];
+    marine
+        .call("greeting", "greeting", &arguments)
+        .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+    let changed_snapshot = take_snapshot(&mut marine);
+    // memories are compared with assert! to not print them on failure
+    assert!(changed_snapshot.memory() != initial_snapshot.memory());
+
+    marine
+        .restore_module("greeting", &deserialized_snapshot)
+        .unwrap_or_else(|e| panic!("can't restore greeting from a snapshot: {:?}", e));
+    assert!(take_snapshot(&mut marine).memory() == initial_snapshot.memory());
+
+    // a snapshot could be also restored into another instance of the same module
+    let mut another_marine = Marine::new();
+    another_marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+    another_marine
+        .restore_module("greeting", &changed_snapshot)
+        .unwrap_or_else(|e| panic!("can't restore greeting from a snapshot: {:?}", e));
+    assert!(take_snapshot(&mut another_marine).memory() == changed_snapshot.memory());
+
+    let result = marine
+        .call("greeting", "greeting", &arguments)
+        .unwrap_or_else(|e| panic!("can't invoke greeting after restore: {:?}", e));
+    assert_eq!(result, vec![IValue::String(String::from("Hi, Fluence"))]);
+}
+
+#[test]
+// test that modules are loaded from the cache and corrupted entries are recompiled
+pub fn module_cache() {
+    let cache_dir =
+        std::env::temp_dir().join(format!("marine_module_cache_{}", std::process::id()));
+    let module_cache = marine::ModuleCache::new(&cache_dir)
+        .unwrap_or_else(|e| panic!("can't create a module cache: {:?}", e));
+
+    let arguments = [IValue::String(String::from("Fluence"))];
+    let expected_result = vec![IValue::String(String::from("Hi, Fluence"))];
+
+    // the first load fills the cache, the second one loads from it
+    for _ in 0..2 {
+        let mut marine = Marine::with_module_cache(module_cache.clone());
+        marine
+            .load_module("greeting", &*GREETING_WASM_BYTES, <_>::default())
+            .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+        let result = marine
+            .call("greeting", "greeting", &arguments)
+            .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+        assert_eq!(result, expected_result);
+    }
+
+    // corrupt all cache entries
+    for dir in std::fs::read_dir(&cache_dir).unwrap() {
+        for entry in std::fs::read_dir(dir.unwrap().path()).unwrap() {
+            std::fs::write(entry.unwrap().path(), b"corrupted entry").unwrap();
+        }
+    }
+
+    let mut marine = Marine::with_module_cache(module_cache.clone());
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module with corrupted cache: {:?}", e));
+
+    let result = marine
+        .call("greeting", "greeting", &arguments)
+        .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+    assert_eq!(result, expected_result);
+
+    // dirs that don't belong to Marine aren't touched
+    let unrelated_dir = cache_dir.join("unrelated");
+    std::fs::create_dir_all(&unrelated_dir).unwrap();
+    std::fs::write(unrelated_dir.join("file"), b"content").unwrap();
+
+    assert_eq!(module_cache.prune_outdated().unwrap(), 0);
+    assert_eq!(module_cache.clear().unwrap(), 1);
+    assert!(unrelated_dir.join("file").exists());
+
+    std::fs::remove_dir_all(&cache_dir).unwrap();
+}
+
+#[test]
+// test that memory of a module in the reset after call mode isn't changed by calls
+pub fn reset_after_call() {
+    let mut marine = Marine::new();
+    let config = marine::MModuleConfig::default().with_reset_after_call(true);
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let initial_snapshot = marine
+        .snapshot_module("greeting")
+        .unwrap_or_else(|e| panic!("can't take a snapshot of greeting: {:?}", e));
+
+    for name in &["Fluence", "Marine"] {
+        let result = marine
+            .call("greeting", "greeting", &[IValue::String(name.to_string())])
+            .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+        assert_eq!(result, vec![IValue::String(format!("Hi, {}", name))]);
+
+        let snapshot = marine
+            .snapshot_module("greeting")
+            .unwrap_or_else(|e| panic!("can't take a snapshot of greeting: {:?}", e));
+        assert_eq!(snapshot, initial_snapshot);
+    }
+}
+
+#[test]
+// test that calls of a pooled module are served by its copies
+pub fn module_pool() {
+    let mut marine = Marine::new();
+    let config = marine::MModuleConfig::default().with_pool_size(3);
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let initial_snapshot = marine
+        .snapshot_module("greeting")
+        .unwrap_or_else(|e| panic!("can't take a snapshot of greeting: {:?}", e));
+
+    for id in 0..7 {
+        let name = format!("Fluence {}", id);
+        let result = marine
+            .call("greeting", "greeting", &[IValue::String(name.clone())])
+            .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+        assert_eq!(result, vec![IValue::String(format!("Hi, {}", name))]);
+    }
+
+    let snapshot = marine
+        .snapshot_module("greeting")
+        .unwrap_or_else(|e| panic!("can't take a snapshot of greeting: {:?}", e));
+    assert_eq!(snapshot, initial_snapshot);
+
+    let interface = marine
+        .module_interface("greeting")
+        .unwrap_or_else(|| panic!("can't get an interface of greeting"));
+    assert_eq!(interface.function_signatures.len(), 1);
+}
+
+#[test]
+// test that a deterministic module behaves the same way as an ordinary one
+pub fn deterministic_module() {
+    let mut marine = Marine::new();
+    let config = marine::MModuleConfig::default().with_deterministic(true);
+    marine
+        .load_module("greeting", &*GREETING_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    for seed in 0..2 {
+        marine.set_deterministic_env(marine::DeterministicEnv {
+            timestamp_ns: 0,
+            seed,
+        });
+        let result = marine
+            .call(
+                "greeting",
+                "greeting",
+                &[IValue::String(String::from("Fluence"))],
+            )
+            .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+        assert_eq!(result, vec![IValue::String(String::from("Hi, Fluence"))]);
+    }
+}
+
+#[test]
+// test that Marine instances could be moved to and shared between threads
+pub fn concurrent_calls() {
+    use std::sync::Arc;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Marine>();
+
+    fn load_greeting() -> Marine {
+        let mut marine = Marine::new();
+        marine
+            .load_module("greeting", &*GREETING_WASM_BYTES, <_>::default())
+            .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+        marine
+    }
+
+    fn call_greeting(marine: &Marine, name: String) {
+        let result = marine
+            .call("greeting", "greeting", &[IValue::String(name.clone())])
+            .unwrap_or_else(|e| panic!("can't invoke greeting: {:?}", e));
+        assert_eq!(result, vec![IValue::String(format!("Hi, {}", name))]);
+    }
+
+    const THREADS_COUNT: usize = 8;
+    const CALLS_COUNT: usize = 50;
+
+    let own_handles = (0..THREADS_COUNT).map(|thread_id| {
+        let marine = load_greeting();
+        std::thread::spawn(move || {
+            for call_id in 0..CALLS_COUNT {
+                call_greeting(&marine, format!("{} {}", thread_id, call_id));
+            }
+        })
+    });
+
+    let shared_marine = Arc::new(load_greeting());
+    let shared_handles = (0..THREADS_COUNT).map(|thread_id| {
+        let marine = shared_marine.clone();
+        std::thread::spawn(move || {
+            for call_id in 0..CALLS_COUNT {
+                call_greeting(&marine, format!("{} {}", thread_id, call_id));
+            }
+        })
+    });
+
+    let handles = own_handles.chain(shared_handles).collect::<Vec<_>>();
+    for handle in handles {
+        handle
+            .join()
+            .expect("thread calling greeting shouldn't panic");
+    }
+}
@@ -0,0 +1,500 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use marine::Marine;
+use marine::MModuleConfig;
+use marine::HostImportDescriptor;
+use marine::HostImportName;
+use marine::HostImportError;
+use marine::HostImportRecord;
+use marine::HostImportValue;
+use marine::IRecordFieldType;
+use marine::IRecordType;
+use marine::IType;
+use marine::IValue;
+use marine::MRecordTypes;
+use marine::ne_vec::NEVec;
+
+use wasmer_core::vm::Ctx;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+fn curl_adapter_config(curl_descriptor: HostImportDescriptor) -> MModuleConfig {
+    let mut config = MModuleConfig::default();
+    // the module logs through this import, so it should be provided as well
+    config.raw_imports.insert(
+        "host",
+        "log_utf8_string",
+        |_ctx: &mut Ctx, _level: i32, _target: i32, _offset: i32, _size: i32| {},
+    );
+    config
+        .host_imports
+        .insert(HostImportName::from("curl"), curl_descriptor);
+
+    config
+}
+
+#[test]
+pub fn panicking_host_import() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let curl_closure =
+        |_ctx: &mut Ctx, _args: Vec<IValue>| -> Option<IValue> { panic!("curl isn't available") };
+    let curl_descriptor = HostImportDescriptor {
+        host_exported_func: Box::new(curl_closure),
+        argument_types: vec![IType::Array(Box::new(IType::String))],
+        output_type: Some(IType::Record(0)),
+        error_handler: None,
+        record_types: <_>::default(),
+    };
+    let config = curl_adapter_config(curl_descriptor);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("curl_adapter", &curl_adapter_wasm_bytes, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    // the module is trapped on each call, but Marine stays usable
+    for _ in 0..2 {
+        let call_result = marine.call(
+            "curl_adapter",
+            "download",
+            &[IValue::String(String::from("https://fluence.network"))],
+        );
+
+        assert!(matches!(
+            call_result.err().unwrap(),
+            marine::MError::HostImportFailed {
+                import_name,
+                error: HostImportError::ClosurePanicked(message),
+            } if import_name == "curl" && message == "curl isn't available"
+        ));
+    }
+
+    let module_stats = marine.module_stats("curl_adapter").unwrap();
+    assert_eq!(module_stats.host_imports["curl"], 2);
+    assert_eq!(module_stats.traps_count, 2);
+}
+
+#[derive(Serialize, Deserialize)]
+struct MountedBinaryResult {
+    ret_code: i32,
+    error: String,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl HostImportRecord for MountedBinaryResult {
+    const NAME: &'static str = "MountedBinaryResult";
+
+    fn fields(record_types: &mut MRecordTypes) -> Vec<IRecordFieldType> {
+        let field = |name: &str, ty| IRecordFieldType {
+            name: name.to_string(),
+            ty,
+        };
+
+        vec![
+            field("ret_code", i32::itype(record_types)),
+            field("error", String::itype(record_types)),
+            field("stdout", Vec::<u8>::itype(record_types)),
+            field("stderr", Vec::<u8>::itype(record_types)),
+        ]
+    }
+}
+
+#[test]
+pub fn typed_host_import() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let curl_descriptor =
+        HostImportDescriptor::from_fn(|_ctx: &mut Ctx, args: Vec<String>| MountedBinaryResult {
+            ret_code: 0,
+            error: String::new(),
+            stdout: args.join(" ").into_bytes(),
+            stderr: Vec::new(),
+        });
+    assert_eq!(
+        curl_descriptor.argument_types,
+        vec![IType::Array(Box::new(IType::String))]
+    );
+    assert_eq!(curl_descriptor.output_type, Some(IType::Record(0)));
+    assert_eq!(curl_descriptor.record_types[&0].name, "MountedBinaryResult");
+
+    let config = curl_adapter_config(curl_descriptor);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("curl_adapter", &curl_adapter_wasm_bytes, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let result = marine
+        .call(
+            "curl_adapter",
+            "download",
+            &[IValue::String(String::from("https://fluence.network"))],
+        )
+        .unwrap_or_else(|e| panic!("can't invoke download: {:?}", e));
+
+    assert_eq!(
+        result,
+        vec![IValue::String(String::from("https://fluence.network"))]
+    );
+}
+
+#[test]
+pub fn mismatched_host_record_type() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    // stderr is missing in this record type
+    let fields = vec![
+        IRecordFieldType {
+            name: String::from("ret_code"),
+            ty: IType::S32,
+        },
+        IRecordFieldType {
+            name: String::from("error"),
+            ty: IType::String,
+        },
+        IRecordFieldType {
+            name: String::from("stdout"),
+            ty: IType::ByteArray,
+        },
+    ];
+    let mounted_binary_result = IRecordType {
+        name: String::from("MountedBinaryResult"),
+        fields: NEVec::new(fields).unwrap(),
+    };
+    let mut record_types = MRecordTypes::new();
+    record_types.insert(42, std::sync::Arc::new(mounted_binary_result));
+
+    let curl_descriptor = HostImportDescriptor {
+        host_exported_func: Box::new(|_ctx: &mut Ctx, _args: Vec<IValue>| None),
+        argument_types: vec![IType::Array(Box::new(IType::String))],
+        output_type: Some(IType::Record(42)),
+        error_handler: None,
+        record_types,
+    };
+    let config = curl_adapter_config(curl_descriptor);
+
+    let mut marine = Marine::new();
+    let load_result = marine.load_module("curl_adapter", &curl_adapter_wasm_bytes, config);
+
+    assert!(matches!(
+        load_result.err().unwrap(),
+        marine::MError::IncompatibleHostRecordType { import_name, record_name, .. }
+            if import_name == "curl" && record_name == "MountedBinaryResult"
+    ));
+}
+
+#[test]
+pub fn namespaced_host_imports() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let curl = |stdout: &'static str| {
+        move |_ctx: &mut Ctx, _args: Vec<String>| MountedBinaryResult {
+            ret_code: 0,
+            error: String::new(),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: Vec::new(),
+        }
+    };
+
+    // imports with the same name from different namespaces don't clash
+    let mut config = curl_adapter_config(HostImportDescriptor::from_fn(curl("ipfs")));
+    let ipfs_curl = config.host_imports.remove(&"curl".into()).unwrap();
+    config
+        .host_imports
+        .insert(HostImportName::new("ipfs", "curl"), ipfs_curl);
+    let host_curl = HostImportDescriptor::from_fn(curl("host"));
+    config
+        .host_imports
+        .insert(HostImportName::new("host", "curl"), host_curl);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("curl_adapter", &curl_adapter_wasm_bytes, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let result = marine
+        .call(
+            "curl_adapter",
+            "download",
+            &[IValue::String(String::from("https://fluence.network"))],
+        )
+        .unwrap_or_else(|e| panic!("can't invoke download: {:?}", e));
+
+    assert_eq!(result, vec![IValue::String(String::from("host"))]);
+
+    let module_stats = marine.module_stats("curl_adapter").unwrap();
+    assert_eq!(module_stats.host_imports["curl"], 1);
+    assert!(!module_stats.host_imports.contains_key("ipfs.curl"));
+}
+
+#[test]
+pub fn reserved_host_import_namespaces() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let curl = |_ctx: &mut Ctx, _args: Vec<String>| String::new();
+    let config_with_namespace = |namespace: &str| {
+        let mut config = curl_adapter_config(HostImportDescriptor::from_fn(curl));
+        config.host_imports.insert(
+            HostImportName::new(namespace, "curl"),
+            HostImportDescriptor::from_fn(curl),
+        );
+        config
+    };
+
+    let mut marine = Marine::new();
+    for namespace in &["wasi_snapshot_preview1", "env", "__marine", "downloader"] {
+        let load_result = marine.load_module(
+            "downloader",
+            &curl_adapter_wasm_bytes,
+            config_with_namespace(namespace),
+        );
+
+        assert!(matches!(
+            load_result.err().unwrap(),
+            marine::MError::ReservedHostImportNamespace { namespace: reserved, .. }
+                if &reserved == namespace
+        ));
+    }
+
+    // namespaces of loaded modules can't be used by host imports and vice versa
+    marine
+        .load_module(
+            "ipfs",
+            &curl_adapter_wasm_bytes,
+            curl_adapter_config(HostImportDescriptor::from_fn(curl)),
+        )
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+    let load_result = marine.load_module(
+        "downloader",
+        &curl_adapter_wasm_bytes,
+        config_with_namespace("ipfs"),
+    );
+    assert!(matches!(
+        load_result.err().unwrap(),
+        marine::MError::ReservedHostImportNamespace { namespace, .. } if namespace == "ipfs"
+    ));
+
+    marine
+        .load_module(
+            "downloader",
+            &curl_adapter_wasm_bytes,
+            config_with_namespace("files"),
+        )
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+    let load_result = marine.load_module(
+        "files",
+        &curl_adapter_wasm_bytes,
+        curl_adapter_config(HostImportDescriptor::from_fn(curl)),
+    );
+    assert!(matches!(
+        load_result.err().unwrap(),
+        marine::MError::HostImportNamespaceClash { module_name, importer }
+            if module_name == "files" && importer == "downloader"
+    ));
+}
+
+// returns a curl import that blocks the first call until it's interrupted
+fn blocking_curl_adapter_config() -> MModuleConfig {
+    let is_first_call = AtomicBool::new(true);
+    let curl = move |_ctx: &mut Ctx, args: Vec<String>| {
+        if is_first_call.swap(false, Ordering::Relaxed) {
+            let started = Instant::now();
+            while !marine::is_current_call_interrupted() && started.elapsed() < MAX_BLOCKING_TIME {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        MountedBinaryResult {
+            ret_code: 0,
+            error: String::new(),
+            stdout: args.join(" ").into_bytes(),
+            stderr: Vec::new(),
+        }
+    };
+
+    curl_adapter_config(HostImportDescriptor::from_fn(curl)).with_interruptible(true)
+}
+
+const MAX_BLOCKING_TIME: Duration = Duration::from_secs(10);
+
+fn assert_interrupted_and_reusable(
+    marine: &mut Marine,
+    call_result: Result<Vec<IValue>, marine::MError>,
+) {
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::CallTimedOut(module_name) if module_name == "curl_adapter"
+    ));
+
+    // the module stays usable after an interrupted call
+    let arguments = [IValue::String(String::from("https://fluence.network"))];
+    let result = marine
+        .call("curl_adapter", "download", &arguments)
+        .unwrap_or_else(|e| panic!("can't invoke download after interruption: {:?}", e));
+    assert_eq!(
+        result,
+        vec![IValue::String(String::from("https://fluence.network"))]
+    );
+}
+
+#[test]
+pub fn interrupted_by_timeout() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module(
+            "curl_adapter",
+            &curl_adapter_wasm_bytes,
+            blocking_curl_adapter_config(),
+        )
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let arguments = [IValue::String(String::from("https://fluence.network"))];
+    let started = Instant::now();
+    let call_result = marine.call_with_deadline(
+        "curl_adapter",
+        "download",
+        &arguments,
+        Duration::from_millis(100),
+    );
+    assert!(started.elapsed() < MAX_BLOCKING_TIME);
+
+    assert_interrupted_and_reusable(&mut marine, call_result);
+}
+
+#[test]
+pub fn cancelled_from_another_thread() {
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module(
+            "curl_adapter",
+            &curl_adapter_wasm_bytes,
+            blocking_curl_adapter_config(),
+        )
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let handle = marine::CancellationHandle::new();
+    let cancelling_thread = {
+        let handle = handle.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.cancel();
+        })
+    };
+
+    let arguments = [IValue::String(String::from("https://fluence.network"))];
+    let started = Instant::now();
+    let call_result = marine.call_with_deadline("curl_adapter", "download", &arguments, handle);
+    assert!(started.elapsed() < MAX_BLOCKING_TIME);
+    cancelling_thread.join().unwrap();
+
+    assert_interrupted_and_reusable(&mut marine, call_result);
+}
+
+#[test]
+// test that calls made from several threads run concurrently on copies of a pooled module
+pub fn overlapping_pooled_calls() {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    const THREADS_COUNT: usize = 4;
+
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    // every call waits until calls of all threads are running at the same time
+    let running_calls = Arc::new(AtomicUsize::new(0));
+    let max_running_calls = Arc::new(AtomicUsize::new(0));
+    let curl = {
+        let running_calls = running_calls.clone();
+        let max_running_calls = max_running_calls.clone();
+        move |_ctx: &mut Ctx, args: Vec<String>| {
+            let running = running_calls.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running_calls.fetch_max(running, Ordering::SeqCst);
+
+            let started = Instant::now();
+            while max_running_calls.load(Ordering::SeqCst) < THREADS_COUNT
+                && started.elapsed() < MAX_BLOCKING_TIME
+            {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            running_calls.fetch_sub(1, Ordering::SeqCst);
+
+            MountedBinaryResult {
+                ret_code: 0,
+                error: String::new(),
+                stdout: args.join(" ").into_bytes(),
+                stderr: Vec::new(),
+            }
+        }
+    };
+    let config =
+        curl_adapter_config(HostImportDescriptor::from_fn(curl)).with_pool_size(THREADS_COUNT);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("curl_adapter", &curl_adapter_wasm_bytes, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+    let marine = Arc::new(marine);
+
+    let handles = (0..THREADS_COUNT)
+        .map(|thread_id| {
+            let marine = marine.clone();
+            std::thread::spawn(move || {
+                let url = format!("https://fluence.network/{}", thread_id);
+                let result = marine
+                    .call("curl_adapter", "download", &[IValue::String(url.clone())])
+                    .unwrap_or_else(|e| panic!("can't invoke download: {:?}", e));
+                assert_eq!(result, vec![IValue::String(url)]);
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle
+            .join()
+            .expect("thread calling download shouldn't panic");
+    }
+
+    assert_eq!(max_running_calls.load(Ordering::SeqCst), THREADS_COUNT);
+}
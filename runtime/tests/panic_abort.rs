@@ -0,0 +1,173 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// Release builds use panic = "abort", so host import failures must trap modules without
+// panics. Tests here abort the whole process on any panic, that's why they can't be placed
+// along with tests that panic on purpose.
+
+use marine::Marine;
+use marine::MModuleConfig;
+use marine::HostImportDescriptor;
+use marine::HostImportName;
+use marine::HostImportError;
+use marine::HostImportRecord;
+use marine::HostImportValue;
+use marine::IRecordFieldType;
+use marine::IType;
+use marine::IValue;
+use marine::MRecordTypes;
+
+use wasmer_core::vm::Ctx;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde::Serializer;
+
+fn abort_on_panic() {
+    std::panic::set_hook(Box::new(|info| {
+        eprintln!("{}", info);
+        std::process::abort();
+    }));
+}
+
+fn curl_adapter_config(curl_descriptor: HostImportDescriptor) -> MModuleConfig {
+    let mut config = MModuleConfig::default();
+    // the module logs through this import, so it should be provided as well
+    config.raw_imports.insert(
+        "host",
+        "log_utf8_string",
+        |_ctx: &mut Ctx, _level: i32, _target: i32, _offset: i32, _size: i32| {},
+    );
+    config
+        .host_imports
+        .insert(HostImportName::from("curl"), curl_descriptor);
+
+    config
+}
+
+#[test]
+pub fn failed_host_import() {
+    abort_on_panic();
+
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let curl_closure = |_ctx: &mut Ctx, _args: Vec<IValue>| -> Option<IValue> {
+        marine::fail_host_import(HostImportError::InvalidResult(String::from(
+            "curl isn't available",
+        )));
+        None
+    };
+    let curl_descriptor = HostImportDescriptor {
+        host_exported_func: Box::new(curl_closure),
+        argument_types: vec![IType::Array(Box::new(IType::String))],
+        output_type: Some(IType::Record(0)),
+        error_handler: None,
+        record_types: <_>::default(),
+    };
+    let config = curl_adapter_config(curl_descriptor);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("curl_adapter", &curl_adapter_wasm_bytes, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    // the module is trapped on each call, but Marine stays usable
+    for _ in 0..2 {
+        let call_result = marine.call(
+            "curl_adapter",
+            "download",
+            &[IValue::String(String::from("https://fluence.network"))],
+        );
+
+        assert!(matches!(
+            call_result.err().unwrap(),
+            marine::MError::HostImportFailed {
+                import_name,
+                error: HostImportError::InvalidResult(message),
+            } if import_name == "curl" && message == "curl isn't available"
+        ));
+    }
+
+    let module_stats = marine.module_stats("curl_adapter").unwrap();
+    assert_eq!(module_stats.host_imports["curl"], 2);
+    assert_eq!(module_stats.traps_count, 2);
+}
+
+/// MountedBinaryResult that can't be passed to the module, because it fails to serialize.
+#[derive(Deserialize)]
+struct UnavailableResult {
+    #[allow(dead_code)]
+    ret_code: i32,
+}
+
+impl Serialize for UnavailableResult {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("curl isn't available"))
+    }
+}
+
+impl HostImportRecord for UnavailableResult {
+    const NAME: &'static str = "MountedBinaryResult";
+
+    fn fields(record_types: &mut MRecordTypes) -> Vec<IRecordFieldType> {
+        let field = |name: &str, ty| IRecordFieldType {
+            name: name.to_string(),
+            ty,
+        };
+
+        vec![
+            field("ret_code", i32::itype(record_types)),
+            field("error", String::itype(record_types)),
+            field("stdout", Vec::<u8>::itype(record_types)),
+            field("stderr", Vec::<u8>::itype(record_types)),
+        ]
+    }
+}
+
+#[test]
+pub fn failed_typed_host_import() {
+    abort_on_panic();
+
+    let curl_adapter_wasm_bytes =
+        std::fs::read("../examples/url-downloader/artifacts/curl_adapter.wasm")
+            .expect("../examples/url-downloader/artifacts/curl_adapter.wasm should presence");
+
+    let curl_descriptor = HostImportDescriptor::from_fn(|_ctx: &mut Ctx, _args: Vec<String>| {
+        UnavailableResult { ret_code: 1 }
+    });
+    let config = curl_adapter_config(curl_descriptor);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("curl_adapter", &curl_adapter_wasm_bytes, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let call_result = marine.call(
+        "curl_adapter",
+        "download",
+        &[IValue::String(String::from("https://fluence.network"))],
+    );
+
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::HostImportFailed {
+            import_name,
+            error: HostImportError::InvalidResult(message),
+        } if import_name == "curl" && message.contains("curl isn't available")
+    ));
+}
@@ -0,0 +1,96 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod utils;
+
+use utils::get;
+use utils::put;
+
+use marine::Marine;
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+
+fn local_storage(sites_dir: &Path, read_only_dirs: HashSet<PathBuf>) -> Marine {
+    utils::local_storage(|config| {
+        utils::with_sites_dir(config.with_wasi_read_only_dirs(read_only_dirs), sites_dir)
+    })
+}
+
+fn assert_read_only(result: String) {
+    assert!(
+        result.contains("Read-only file system"),
+        "unexpected result {}",
+        result
+    );
+}
+
+#[test]
+pub fn read_only_mapped_dir() {
+    let temp_dir = utils::temp_dir("marine_read_only_mapped_dir");
+    let dir = temp_dir.path().to_path_buf();
+    std::fs::write(dir.join("asset"), b"content").unwrap();
+    let read_only_dirs = vec![dir.clone()].into_iter().collect();
+    let mut marine = local_storage(&dir, read_only_dirs);
+
+    assert_eq!(get(&mut marine, "asset"), b"content");
+    assert_read_only(put(&mut marine, "asset", b"new"));
+    assert_read_only(put(&mut marine, "file", b"new"));
+
+    assert_eq!(std::fs::read(dir.join("asset")).unwrap(), b"content");
+    assert!(!dir.join("file").exists());
+}
+
+#[test]
+#[cfg(unix)]
+pub fn read_only_dir_behind_symlink() {
+    let temp_dir = utils::temp_dir("marine_read_only_writable_sites");
+    let sites_dir = temp_dir.path().to_path_buf();
+    let assets_dir = sites_dir.join("assets");
+    std::fs::create_dir_all(&assets_dir).unwrap();
+    std::fs::write(assets_dir.join("asset"), b"content").unwrap();
+    std::os::unix::fs::symlink("assets/asset", sites_dir.join("link")).unwrap();
+    let read_only_dirs = vec![assets_dir.clone()].into_iter().collect();
+    let mut marine = local_storage(&sites_dir, read_only_dirs);
+
+    // files outside of read-only dirs are still writable
+    assert_eq!(put(&mut marine, "file", b"new"), "Ok");
+    assert_read_only(put(&mut marine, "link", b"new"));
+    assert_eq!(std::fs::read(assets_dir.join("asset")).unwrap(), b"content");
+}
+
+#[test]
+#[cfg(unix)]
+pub fn symlink_out_of_preopened_dirs() {
+    let sites_temp_dir = utils::temp_dir("marine_read_only_escaping_sites");
+    let sites_dir = sites_temp_dir.path().to_path_buf();
+    let outside_temp_dir = utils::temp_dir("marine_read_only_outside");
+    let outside_dir = outside_temp_dir.path().to_path_buf();
+    std::fs::write(outside_dir.join("file"), b"content").unwrap();
+    std::os::unix::fs::symlink(outside_dir.join("file"), sites_dir.join("link")).unwrap();
+    let read_only_dirs = vec![sites_dir.join("assets")].into_iter().collect();
+    let mut marine = local_storage(&sites_dir, read_only_dirs);
+
+    let result = put(&mut marine, "link", b"new");
+    assert!(
+        result.contains("Capabilities insufficient"),
+        "unexpected result {}",
+        result
+    );
+    assert_eq!(get(&mut marine, "link"), b"error while reading file");
+    assert_eq!(std::fs::read(outside_dir.join("file")).unwrap(), b"content");
+}
@@ -26,15 +26,20 @@ pub fn records() {
         .expect("../examples/records/artifacts/records_pure.wasm should presence");
 
     let mut marine = Marine::new();
-    let load_result = marine.load_module("pure", &pure_wasm_bytes, <_>::default());
-    assert!(load_result.is_err());
-
+    // imports are resolved lazily, so pure could be loaded before effector
     marine
-        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
         .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
 
+    let call_result = marine.call("records_pure", "invoke", &[]);
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::UnresolvedImport { namespace, function_name }
+            if namespace == "records_effector" && function_name == "mutate_struct"
+    ));
+
     marine
-        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
         .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
 
     let result = marine
@@ -63,3 +68,199 @@ pub fn records() {
         )]
     );
 }
+
+#[test]
+pub fn unload_module_with_dependents() {
+    let effector_wasm_bytes = std::fs::read("../examples/records/artifacts/records_effector.wasm")
+        .expect("../examples/records/artifacts/records_effector.wasm should presence");
+
+    let pure_wasm_bytes = std::fs::read("../examples/records/artifacts/records_pure.wasm")
+        .expect("../examples/records/artifacts/records_pure.wasm should presence");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    marine
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    assert_eq!(
+        marine.dependents("records_effector"),
+        vec![String::from("records_pure")]
+    );
+    assert!(marine.dependents("records_pure").is_empty());
+
+    let unload_result = marine.unload_module("records_effector");
+    assert!(matches!(
+        unload_result.err().unwrap(),
+        marine::MError::ModuleHasDependents(dependents) if dependents == vec![String::from("records_pure")]
+    ));
+
+    let unloaded = marine
+        .unload_module_cascade("records_effector")
+        .unwrap_or_else(|e| panic!("can't unload a module from Marine: {:?}", e));
+    assert_eq!(
+        unloaded,
+        vec![
+            String::from("records_effector"),
+            String::from("records_pure")
+        ]
+    );
+    assert!(marine.module_interface("records_pure").is_none());
+}
+
+#[test]
+pub fn upgrade_module_with_dependents() {
+    let effector_wasm_bytes = std::fs::read("../examples/records/artifacts/records_effector.wasm")
+        .expect("../examples/records/artifacts/records_effector.wasm should presence");
+
+    let pure_wasm_bytes = std::fs::read("../examples/records/artifacts/records_pure.wasm")
+        .expect("../examples/records/artifacts/records_pure.wasm should presence");
+
+    let greeting_wasm_bytes = std::fs::read("../examples/greeting/artifacts/greeting.wasm")
+        .expect("../examples/greeting/artifacts/greeting.wasm should presence");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    marine
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let expected_result = marine
+        .call("records_pure", "invoke", &[])
+        .unwrap_or_else(|e| panic!("can't invoke pure: {:?}", e));
+
+    // greeting doesn't export functions imported by pure, so the upgrade is rolled back
+    let upgrade_result =
+        marine.upgrade_module("records_effector", &greeting_wasm_bytes, <_>::default());
+    assert!(matches!(
+        upgrade_result.err().unwrap(),
+        marine::MError::UnresolvedImport { namespace, function_name }
+            if namespace == "records_effector" && function_name == "mutate_struct"
+    ));
+
+    let result = marine
+        .call("records_pure", "invoke", &[])
+        .unwrap_or_else(|e| panic!("can't invoke pure after failed upgrade: {:?}", e));
+    assert_eq!(result, expected_result);
+
+    marine
+        .upgrade_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't upgrade a module in Marine: {:?}", e));
+
+    let result = marine
+        .call("records_pure", "invoke", &[])
+        .unwrap_or_else(|e| panic!("can't invoke pure after upgrade: {:?}", e));
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+pub fn call_trace() {
+    let effector_wasm_bytes = std::fs::read("../examples/records/artifacts/records_effector.wasm")
+        .expect("../examples/records/artifacts/records_effector.wasm should presence");
+
+    let pure_wasm_bytes = std::fs::read("../examples/records/artifacts/records_pure.wasm")
+        .expect("../examples/records/artifacts/records_pure.wasm should presence");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    marine
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    marine.enable_call_tracing(marine::TraceConfig {
+        max_value_len: Some(3),
+    });
+    let result = marine
+        .call("records_pure", "invoke", &[])
+        .unwrap_or_else(|e| panic!("can't invoke pure: {:?}", e));
+
+    let trace = marine
+        .take_last_call_trace()
+        .expect("the call should be traced");
+    assert_eq!(trace.kind, marine::CallKind::Module);
+    assert_eq!(trace.module_name, "records_pure");
+    assert_eq!(trace.function_name, "invoke");
+    assert!(trace.arguments.is_empty());
+    assert_eq!(trace.error, None);
+    assert_ne!(trace.results, result);
+
+    assert_eq!(trace.nested_calls.len(), 1);
+    let nested_call = &trace.nested_calls[0];
+    assert_eq!(nested_call.module_name, "records_effector");
+    assert_eq!(nested_call.function_name, "mutate_struct");
+    assert_eq!(nested_call.results, trace.results);
+    assert!(nested_call.duration <= trace.duration);
+
+    // strings of the record are truncated
+    match &trace.results[0] {
+        IValue::Record(fields) => assert_eq!(fields[11], IValue::String(String::from("fie"))),
+        value => panic!("invoke should return a record, but returned {:?}", value),
+    }
+
+    marine.disable_call_tracing();
+    marine
+        .call("records_pure", "invoke", &[])
+        .unwrap_or_else(|e| panic!("can't invoke pure: {:?}", e));
+    assert!(marine.take_last_call_trace().is_none());
+}
+
+#[test]
+pub fn module_stats() {
+    let effector_wasm_bytes = std::fs::read("../examples/records/artifacts/records_effector.wasm")
+        .expect("../examples/records/artifacts/records_effector.wasm should presence");
+
+    let pure_wasm_bytes = std::fs::read("../examples/records/artifacts/records_pure.wasm")
+        .expect("../examples/records/artifacts/records_pure.wasm should presence");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    // the call fails, because records_effector isn't loaded yet
+    let call_result = marine.call("records_pure", "invoke", &[]);
+    assert!(call_result.is_err());
+
+    marine
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    for _ in 0..3 {
+        marine
+            .call("records_pure", "invoke", &[])
+            .unwrap_or_else(|e| panic!("can't invoke pure: {:?}", e));
+    }
+
+    let pure_stats = marine
+        .module_stats("records_pure")
+        .expect("records_pure should be loaded");
+    let invoke_stats = &pure_stats.functions["invoke"];
+    assert_eq!(invoke_stats.calls_count, 4);
+    assert!(invoke_stats.max_call_time <= invoke_stats.total_call_time);
+    assert_eq!(pure_stats.traps_count, 1);
+    assert!(pure_stats.memory_pages > 0);
+    assert_eq!(pure_stats.peak_memory_pages, pure_stats.memory_pages);
+    assert!(pure_stats.host_imports.is_empty());
+
+    // a call with incorrect arguments fails before reaching Wasm code, so it isn't a trap
+    let call_result = marine.call("records_effector", "mutate_struct", &[IValue::S32(0)]);
+    assert!(call_result.is_err());
+
+    // calls from other modules are counted as well
+    let effector_stats = marine
+        .module_stats("records_effector")
+        .expect("records_effector should be loaded");
+    assert_eq!(effector_stats.functions["mutate_struct"].calls_count, 4);
+    assert_eq!(effector_stats.traps_count, 0);
+
+    assert!(marine.module_stats("non_existent_module").is_none());
+}
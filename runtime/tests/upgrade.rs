@@ -0,0 +1,215 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use marine::Marine;
+use marine::IValue;
+use marine::MModuleConfig;
+use marine::STATE_EXPORT_FUNC_NAME;
+use marine::STATE_MIGRATION_FUNC_NAME;
+
+use parity_wasm::elements::Module;
+use wasmer_core::vm::Ctx;
+
+use std::path::Path;
+
+// returns a module with the export renamed both in the Wasm module and in its IT
+fn rename_export(wasm_path: &str, old_name: &str, new_name: &str) -> Vec<u8> {
+    let file_name = Path::new(wasm_path).file_name().unwrap().to_str().unwrap();
+    let tmp_path = std::env::temp_dir().join(format!(
+        "marine_upgrade_{}_{}_{}",
+        std::process::id(),
+        new_name,
+        file_name
+    ));
+
+    let it = marine_it_parser::extract_text_it(wasm_path)
+        .unwrap_or_else(|e| panic!("can't extract IT from {}: {:?}", wasm_path, e));
+    let it = it.replace(
+        &format!("(@interface export \"{}\"", old_name),
+        &format!("(@interface export \"{}\"", new_name),
+    );
+    marine_it_parser::delete_it_section_from_file(wasm_path.into(), tmp_path.clone())
+        .unwrap_or_else(|e| panic!("can't delete IT from {}: {:?}", wasm_path, e));
+    marine_it_parser::embed_text_it(&tmp_path, &tmp_path, &it)
+        .unwrap_or_else(|e| panic!("can't embed IT into {}: {:?}", wasm_path, e));
+
+    let mut module: Module = parity_wasm::deserialize_file(&tmp_path)
+        .unwrap_or_else(|e| panic!("can't parse {:?}: {:?}", tmp_path, e));
+    let _ = std::fs::remove_file(&tmp_path);
+
+    let export = module
+        .export_section_mut()
+        .and_then(|section| {
+            section
+                .entries_mut()
+                .iter_mut()
+                .find(|export| export.field() == old_name)
+        })
+        .unwrap_or_else(|| panic!("{} doesn't export {}", wasm_path, old_name));
+    *export.field_mut() = new_name.to_string();
+
+    parity_wasm::serialize(module).unwrap_or_else(|e| panic!("can't serialize module: {:?}", e))
+}
+
+const GREETING_WASM_PATH: &str = "../examples/greeting/artifacts/greeting.wasm";
+const IPFS_PURE_WASM_PATH: &str = "../examples/ipfs-node/artifacts/ipfs_pure.wasm";
+const SQLITE_WASM_PATH: &str = "../examples/sqlite/artifacts/sqlite_test.wasm";
+const RECORDS_EFFECTOR_WASM_PATH: &str = "../examples/records/artifacts/records_effector.wasm";
+const RECORDS_PURE_WASM_PATH: &str = "../examples/records/artifacts/records_pure.wasm";
+
+fn ipfs_pure_config() -> MModuleConfig {
+    let mut config = MModuleConfig::default();
+    // the module logs through this import, so it should be provided as well
+    config.raw_imports.insert(
+        "host",
+        "log_utf8_string",
+        |_ctx: &mut Ctx, _level: i32, _target: i32, _offset: i32, _size: i32| {},
+    );
+    config
+}
+
+fn migrations_count(marine: &Marine, module_name: &str) -> u64 {
+    let module_stats = marine.module_stats(module_name).unwrap();
+    module_stats
+        .functions
+        .get(STATE_MIGRATION_FUNC_NAME)
+        .map_or(0, |function_stats| function_stats.calls_count)
+}
+
+#[test]
+pub fn upgrade_with_state_migration() {
+    // invoke of ipfs_pure returns a string, greeting accepts a string
+    let old_wasm_bytes = rename_export(IPFS_PURE_WASM_PATH, "invoke", STATE_EXPORT_FUNC_NAME);
+    let new_wasm_bytes = rename_export(GREETING_WASM_PATH, "greeting", STATE_MIGRATION_FUNC_NAME);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("service", &old_wasm_bytes, ipfs_pure_config())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    marine
+        .upgrade_module("service", &new_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't upgrade a module in Marine: {:?}", e));
+
+    assert_eq!(migrations_count(&marine, "service"), 1);
+    let module_interface = marine.module_interface("service").unwrap();
+    assert!(module_interface
+        .function_signatures
+        .iter()
+        .any(|signature| signature.name.as_str() == STATE_MIGRATION_FUNC_NAME));
+}
+
+#[test]
+pub fn failed_state_migration() {
+    // test1 of sqlite expects an integer, but the old module provides a string
+    let old_wasm_bytes = rename_export(IPFS_PURE_WASM_PATH, "invoke", STATE_EXPORT_FUNC_NAME);
+    let new_wasm_bytes = rename_export(SQLITE_WASM_PATH, "test1", STATE_MIGRATION_FUNC_NAME);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("service", &old_wasm_bytes, ipfs_pure_config())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let upgrade_result = marine.upgrade_module("service", &new_wasm_bytes, <_>::default());
+    assert!(matches!(
+        upgrade_result.err().unwrap(),
+        marine::MError::ITInstructionError(_)
+    ));
+
+    // the old module stays loaded
+    marine
+        .call("service", STATE_EXPORT_FUNC_NAME, &[])
+        .unwrap_or_else(|e| panic!("can't invoke the old module: {:?}", e));
+}
+
+#[test]
+pub fn upgrade_without_state_export() {
+    let old_wasm_bytes = std::fs::read(GREETING_WASM_PATH).expect("greeting.wasm should presence");
+    let new_wasm_bytes = rename_export(GREETING_WASM_PATH, "greeting", STATE_MIGRATION_FUNC_NAME);
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("service", &old_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    // the old module doesn't export its state, so there is nothing to migrate
+    marine
+        .upgrade_module("service", &new_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't upgrade a module in Marine: {:?}", e));
+    assert_eq!(migrations_count(&marine, "service"), 0);
+
+    let result = marine
+        .call(
+            "service",
+            STATE_MIGRATION_FUNC_NAME,
+            &[IValue::String(String::from("Fluence"))],
+        )
+        .unwrap_or_else(|e| panic!("can't invoke the upgraded module: {:?}", e));
+    assert_eq!(result, vec![IValue::String(String::from("Hi, Fluence"))]);
+}
+
+#[test]
+pub fn upgrade_with_incompatible_signature() {
+    let effector_wasm_bytes =
+        std::fs::read(RECORDS_EFFECTOR_WASM_PATH).expect("records_effector.wasm should presence");
+    let pure_wasm_bytes =
+        std::fs::read(RECORDS_PURE_WASM_PATH).expect("records_pure.wasm should presence");
+    // exports mutate_struct, but it accepts a string instead of a record
+    let new_wasm_bytes = rename_export(GREETING_WASM_PATH, "greeting", "mutate_struct");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+    marine
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    let upgrade_result = marine.upgrade_module("records_effector", &new_wasm_bytes, <_>::default());
+    assert!(matches!(
+        upgrade_result.err().unwrap(),
+        marine::MError::UnresolvedImport { namespace, function_name }
+            if namespace == "records_effector" && function_name == "mutate_struct"
+    ));
+
+    marine
+        .call("records_pure", "invoke", &[])
+        .unwrap_or_else(|e| panic!("can't invoke pure after failed upgrade: {:?}", e));
+}
+
+#[test]
+pub fn call_with_incompatible_signature() {
+    let pure_wasm_bytes =
+        std::fs::read(RECORDS_PURE_WASM_PATH).expect("records_pure.wasm should presence");
+    // exports mutate_struct, but it accepts a string instead of a record
+    let effector_wasm_bytes = rename_export(GREETING_WASM_PATH, "greeting", "mutate_struct");
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("records_effector", &effector_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+    marine
+        .load_module("records_pure", &pure_wasm_bytes, <_>::default())
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    // imports are resolved lazily, so the mismatch is found on the first call
+    let call_result = marine.call("records_pure", "invoke", &[]);
+    assert!(matches!(
+        call_result.err().unwrap(),
+        marine::MError::IncompatibleImport { namespace, function_name }
+            if namespace == "records_effector" && function_name == "mutate_struct"
+    ));
+}
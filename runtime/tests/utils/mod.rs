@@ -0,0 +1,109 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Fixtures of tests that store files with the local_storage example module.
+
+// each test uses only a part of the fixtures
+#![allow(dead_code)]
+
+use marine::IValue;
+use marine::Marine;
+use marine::MModuleConfig;
+
+use once_cell::sync::Lazy;
+use tempfile::TempDir;
+use wasmer_core::vm::Ctx;
+
+use std::path::Path;
+
+pub static LOCAL_STORAGE_WASM_BYTES: Lazy<Vec<u8>> = Lazy::new(|| {
+    std::fs::read("../examples/url-downloader/artifacts/local_storage.wasm")
+        .expect("../examples/url-downloader/artifacts/local_storage.wasm should presence")
+});
+
+/// Creates a temporary dir, it's removed when the guard is dropped even if the test fails.
+pub fn temp_dir(prefix: &str) -> TempDir {
+    tempfile::Builder::new()
+        .prefix(prefix)
+        .tempdir()
+        .unwrap_or_else(|e| panic!("can't create a temporary dir: {:?}", e))
+}
+
+/// Preopens the host dir and maps it to the sites dir of local_storage.
+pub fn with_sites_dir(config: MModuleConfig, sites_dir: &Path) -> MModuleConfig {
+    config
+        .with_wasi_preopened_files(vec![sites_dir.to_path_buf()].into_iter().collect())
+        .with_wasi_mapped_dirs(
+            vec![(String::from("sites"), sites_dir.to_path_buf())]
+                .into_iter()
+                .collect(),
+        )
+}
+
+/// Loads local_storage with the default config adjusted by the closure.
+pub fn local_storage(configure: impl FnOnce(MModuleConfig) -> MModuleConfig) -> Marine {
+    let mut config = configure(MModuleConfig::default());
+    // the module logs through this import, so it should be provided as well
+    config.raw_imports.insert(
+        "host",
+        "log_utf8_string",
+        |_ctx: &mut Ctx, _level: i32, _target: i32, _offset: i32, _size: i32| {},
+    );
+
+    let mut marine = Marine::new();
+    marine
+        .load_module("local_storage", &*LOCAL_STORAGE_WASM_BYTES, config)
+        .unwrap_or_else(|e| panic!("can't load a module into Marine: {:?}", e));
+
+    marine
+}
+
+pub fn put(marine: &mut Marine, name: &str, content: &[u8]) -> String {
+    let result = marine
+        .call(
+            "local_storage",
+            "put",
+            &[
+                IValue::String(name.to_string()),
+                IValue::ByteArray(content.to_vec()),
+            ],
+        )
+        .unwrap_or_else(|e| panic!("can't invoke put: {:?}", e));
+
+    match result.as_slice() {
+        [IValue::String(result)] => result.clone(),
+        result => panic!("unexpected result of put {:?}", result),
+    }
+}
+
+pub fn get(marine: &mut Marine, name: &str) -> Vec<u8> {
+    let result = marine
+        .call("local_storage", "get", &[IValue::String(name.to_string())])
+        .unwrap_or_else(|e| panic!("can't invoke get: {:?}", e));
+
+    match result.as_slice() {
+        [IValue::ByteArray(content)] => content.clone(),
+        // arrays of bytes could be also lifted element by element
+        [IValue::Array(content)] => content
+            .iter()
+            .map(|byte| match byte {
+                IValue::U8(byte) => *byte,
+                byte => panic!("unexpected byte {:?}", byte),
+            })
+            .collect(),
+        result => panic!("unexpected result of get {:?}", result),
+    }
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright 2020 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+mod utils;
+
+use utils::get;
+use utils::put;
+
+use marine::Marine;
+use marine::ModuleSnapshot;
+use marine::VfsConfig;
+
+use std::path::Path;
+
+// the host dir mapped to the sites dir must never be touched
+fn local_storage(vfs: VfsConfig, pool_size: usize, host_sites_dir: &Path) -> Marine {
+    let mapped_dirs = vec![(String::from("sites"), host_sites_dir.to_path_buf())];
+    utils::local_storage(|config| {
+        config
+            .with_wasi_vfs(vfs)
+            .with_pool_size(pool_size)
+            .with_wasi_mapped_dirs(mapped_dirs.into_iter().collect())
+    })
+}
+
+#[test]
+pub fn vfs_in_memory() {
+    let host_sites_dir = utils::temp_dir("marine_vfs_sites");
+    let mut marine = local_storage(VfsConfig::default(), 1, host_sites_dir.path());
+
+    assert_eq!(put(&mut marine, "file", b"content"), "Ok");
+    assert_eq!(get(&mut marine, "file"), b"content");
+    let host_files = std::fs::read_dir(host_sites_dir.path()).unwrap();
+    assert_eq!(host_files.count(), 0);
+}
+
+#[test]
+pub fn vfs_shared_by_pool() {
+    let host_sites_dir = utils::temp_dir("marine_vfs_sites");
+    let mut marine = local_storage(VfsConfig::default(), 2, host_sites_dir.path());
+
+    // calls go to different instances of the pool
+    assert_eq!(put(&mut marine, "file", b"content"), "Ok");
+    assert_eq!(get(&mut marine, "file"), b"content");
+}
+
+#[test]
+pub fn vfs_size_quota() {
+    let vfs = VfsConfig {
+        max_size: 16,
+        ..<_>::default()
+    };
+    let host_sites_dir = utils::temp_dir("marine_vfs_sites");
+    let mut marine = local_storage(vfs, 1, host_sites_dir.path());
+
+    assert_eq!(put(&mut marine, "small", &[1; 16]), "Ok");
+    let result = put(&mut marine, "big", &[1; 1]);
+    assert!(
+        result.contains("No space left on device"),
+        "unexpected result {}",
+        result
+    );
+}
+
+#[test]
+pub fn vfs_inodes_quota() {
+    // the root directory, the sites directory and one file
+    let vfs = VfsConfig {
+        max_inodes: 3,
+        ..<_>::default()
+    };
+    let host_sites_dir = utils::temp_dir("marine_vfs_sites");
+    let mut marine = local_storage(vfs, 1, host_sites_dir.path());
+
+    assert_eq!(put(&mut marine, "first", b"content"), "Ok");
+    let result = put(&mut marine, "second", b"content");
+    assert!(
+        result.contains("No space left on device"),
+        "unexpected result {}",
+        result
+    );
+}
+
+#[test]
+pub fn vfs_snapshot() {
+    let host_sites_dir = utils::temp_dir("marine_vfs_sites");
+    let mut marine = local_storage(VfsConfig::default(), 1, host_sites_dir.path());
+    assert_eq!(put(&mut marine, "file", b"old"), "Ok");
+
+    let snapshot = marine
+        .snapshot_module("local_storage")
+        .unwrap_or_else(|e| panic!("can't take a snapshot: {:?}", e));
+    let snapshot = ModuleSnapshot::from_bytes(&snapshot.to_bytes().unwrap()).unwrap();
+
+    assert_eq!(put(&mut marine, "file", b"new"), "Ok");
+    marine
+        .restore_module("local_storage", &snapshot)
+        .unwrap_or_else(|e| panic!("can't restore a snapshot: {:?}", e));
+
+    assert_eq!(get(&mut marine, "file"), b"old");
+}
+
+#[test]
+pub fn vfs_file_backend() {
+    let host_sites_dir = utils::temp_dir("marine_vfs_sites");
+    let vfs_dir = utils::temp_dir("marine_vfs_file");
+    let vfs = VfsConfig {
+        file: Some(vfs_dir.path().join("sites.vfs")),
+        ..<_>::default()
+    };
+
+    let mut marine = local_storage(vfs.clone(), 1, host_sites_dir.path());
+    assert_eq!(put(&mut marine, "file", b"content"), "Ok");
+    assert_eq!(put(&mut marine, "other", b"other content"), "Ok");
+    drop(marine);
+
+    // the filesystem is loaded by a new instance of the module along with its journal
+    let mut marine = local_storage(vfs, 1, host_sites_dir.path());
+    assert_eq!(get(&mut marine, "file"), b"content");
+    assert_eq!(get(&mut marine, "other"), b"other content");
+}
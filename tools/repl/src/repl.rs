@@ -176,7 +176,7 @@ impl REPL {
     fn show_envs<'args>(&mut self, mut args: impl Iterator<Item = &'args str>) {
         next_argument!(module_name, args, "Module name should be specified");
         match self.app_service.get_wasi_state(module_name) {
-            Ok(wasi_state) => print_envs(module_name, wasi_state),
+            Ok(wasi_state) => print_envs(module_name, &wasi_state),
             Err(e) => println!("{}", e),
         };
     }
@@ -184,7 +184,7 @@ impl REPL {
     fn show_fs<'args>(&mut self, mut args: impl Iterator<Item = &'args str>) {
         next_argument!(module_name, args, "Module name should be specified");
         match self.app_service.get_wasi_state(module_name) {
-            Ok(wasi_state) => print_fs_state(wasi_state),
+            Ok(wasi_state) => print_fs_state(&wasi_state),
             Err(e) => println!("{}", e),
         };
     }
@@ -196,7 +196,6 @@ impl REPL {
     }
 
     fn create_app_service<S: Into<PathBuf>>(config_file_path: Option<S>) -> ReplResult<AppService> {
-        let tmp_path: String = std::env::temp_dir().to_string_lossy().into();
         let service_id = uuid::Uuid::new_v4().to_string();
 
         let start = Instant::now();
@@ -205,7 +204,11 @@ impl REPL {
             .map(|p| TomlAppServiceConfig::load(p.into()))
             .transpose()?
             .unwrap_or_default();
-        config.service_base_dir = Some(tmp_path);
+        // service directories are kept in memory unless the config places them somewhere,
+        // so sessions don't litter the system temp dir
+        if config.service_base_dir.is_none() {
+            config.vfs.get_or_insert_with(Default::default);
+        }
 
         let app_service = AppService::new_with_empty_facade(config, &service_id, HashMap::new())?;
 